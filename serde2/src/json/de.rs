@@ -1,12 +1,15 @@
 use std::char;
+use std::f64;
 use std::fmt;
-use std::num;
+use std::i64;
 use std::str::ScalarValue;
 use std::str;
 
 use de;
 use de::Deserializer;
 
+use super::strtod;
+
 #[deriving(Clone, PartialEq, Eq)]
 pub enum ErrorCode {
     EOFWhileParsingList,
@@ -82,6 +85,8 @@ pub struct Parser<Iter> {
     ch: Option<char>,
     line: uint,
     col: uint,
+    buf: Vec<u8>,
+    non_finite_literals: bool,
 }
 
 impl<
@@ -94,11 +99,21 @@ impl<
             ch: Some('\x00'),
             line: 1,
             col: 0,
+            buf: Vec::with_capacity(32),
+            non_finite_literals: false,
         };
         p.bump();
         return p;
     }
 
+    /// Accepts the bare literals `NaN`, `Infinity` and `-Infinity` written by
+    /// `Writer` with `NonFiniteAsLiteral`. The string and `null` encodings
+    /// are left to the type being deserialized.
+    pub fn non_finite_literals(mut self, non_finite_literals: bool) -> Parser<Iter> {
+        self.non_finite_literals = non_finite_literals;
+        self
+    }
+
     pub fn end(&mut self) -> Result<(), Error> {
         if self.eof() {
             Ok(())
@@ -167,6 +182,14 @@ impl<
                 visitor.visit_bool(self, false)
             }
             '0' ... '9' | '-' => self.parse_number(visitor),
+            'N' if self.non_finite_literals => {
+                try!(self.parse_ident("aN"));
+                visitor.visit_f64(self, f64::NAN)
+            }
+            'I' if self.non_finite_literals => {
+                try!(self.parse_ident("nfinity"));
+                visitor.visit_f64(self, f64::INFINITY)
+            }
             '"' => {
                 let s = try!(self.parse_string());
                 visitor.visit_string(self, s)
@@ -198,38 +221,70 @@ impl<
         R,
         V: de::Visitor<Parser<Iter>, R, Error>,
     >(&mut self, visitor: &mut V) -> Result<R, Error> {
-        let mut neg = 1;
+        let mut neg = false;
 
         if self.ch_is('-') {
             self.bump();
-            neg = -1;
+            neg = true;
+
+            if self.ch_is('I') && self.non_finite_literals {
+                try!(self.parse_ident("nfinity"));
+                return visitor.visit_f64(self, f64::NEG_INFINITY);
+            }
         }
 
-        let res = try!(self.parse_integer());
+        // The digits are gathered in `buf` so that floats can be converted
+        // with a single rounding.
+        self.buf.clear();
+        try!(self.parse_integer());
 
         if self.ch_is('.') || self.ch_is('e') || self.ch_is('E') {
-            let neg = neg as f64;
-            let mut res = res as f64;
+            let mut exp = 0;
 
             if self.ch_is('.') {
-                res = try!(self.parse_decimal(res));
+                exp = try!(self.parse_decimal());
             }
 
             if self.ch_is('e') || self.ch_is('E') {
-                res = try!(self.parse_exponent(res));
+                exp += try!(self.parse_exponent());
             }
 
-            visitor.visit_f64(self, neg * res)
+            let res = strtod::parse_f64(self.buf.as_slice(), exp);
+            visitor.visit_f64(self, if neg { -res } else { res })
         } else {
-            visitor.visit_i64(self, neg * res)
+            let mut res = Some(0u64);
+            for &c in self.buf.iter() {
+                let digit = (c - b'0') as u64;
+                res = res
+                    .and_then(|res| res.checked_mul(&10))
+                    .and_then(|res| res.checked_add(&digit));
+            }
+
+            match res {
+                Some(res) if neg => {
+                    if res <= i64::MAX as u64 {
+                        visitor.visit_i64(self, -(res as i64))
+                    } else if res == i64::MAX as u64 + 1 {
+                        visitor.visit_i64(self, i64::MIN)
+                    } else {
+                        let res = strtod::parse_f64(self.buf.as_slice(), 0);
+                        visitor.visit_f64(self, -res)
+                    }
+                }
+                Some(res) if res > i64::MAX as u64 => visitor.visit_u64(self, res),
+                Some(res) => visitor.visit_i64(self, res as i64),
+                None => {
+                    let res = strtod::parse_f64(self.buf.as_slice(), 0);
+                    visitor.visit_f64(self, if neg { -res } else { res })
+                }
+            }
         }
     }
 
-    fn parse_integer(&mut self) -> Result<i64, Error> {
-        let mut res = 0;
-
+    fn parse_integer(&mut self) -> Result<(), Error> {
         match self.ch_or_null() {
             '0' => {
+                self.buf.push(b'0');
                 self.bump();
 
                 // There can be only one leading '0'.
@@ -244,8 +299,7 @@ impl<
                 while !self.eof() {
                     match self.ch_or_null() {
                         c @ '0' ... '9' => {
-                            res *= 10;
-                            res += (c as i64) - ('0' as i64);
+                            self.buf.push(c as u8);
                             self.bump();
                         }
                         _ => break,
@@ -257,10 +311,10 @@ impl<
             }
         }
 
-        Ok(res)
+        Ok(())
     }
 
-    fn parse_decimal(&mut self, res: f64) -> Result<f64, Error> {
+    fn parse_decimal(&mut self) -> Result<int, Error> {
         self.bump();
 
         // Make sure a digit follows the decimal place.
@@ -271,26 +325,25 @@ impl<
              }
         }
 
-        let mut res = res;
-        let mut dec = 1.0;
+        let mut exp = 0;
         while !self.eof() {
             match self.ch_or_null() {
                 c @ '0' ... '9' => {
-                    dec /= 10.0;
-                    res += (((c as int) - ('0' as int)) as f64) * dec;
+                    self.buf.push(c as u8);
+                    exp -= 1;
                     self.bump();
                 }
                 _ => break,
             }
         }
 
-        Ok(res)
+        Ok(exp)
     }
 
-    fn parse_exponent(&mut self, mut res: f64) -> Result<f64, Error> {
+    fn parse_exponent(&mut self) -> Result<int, Error> {
         self.bump();
 
-        let mut exp = 0u;
+        let mut exp = 0;
         let mut neg_exp = false;
 
         if self.ch_is('+') {
@@ -310,8 +363,11 @@ impl<
         while !self.eof() {
             match self.ch_or_null() {
                 c @ '0' ... '9' => {
-                    exp *= 10;
-                    exp += (c as uint) - ('0' as uint);
+                    // Anything this large is already out of range, so stop
+                    // growing rather than overflow.
+                    if exp < 1000000 {
+                        exp = exp * 10 + (c as int) - ('0' as int);
+                    }
 
                     self.bump();
                 }
@@ -319,14 +375,11 @@ impl<
             }
         }
 
-        let exp: f64 = num::pow(10u as f64, exp);
         if neg_exp {
-            res /= exp;
+            Ok(-exp)
         } else {
-            res *= exp;
+            Ok(exp)
         }
-
-        Ok(res)
     }
 
     fn decode_hex_escape(&mut self) -> Result<u16, Error> {
@@ -585,6 +638,7 @@ pub fn from_str<
 
 #[cfg(test)]
 mod tests {
+    use std::f64;
     use std::str;
     use std::fmt::Show;
     use std::collections::TreeMap;
@@ -684,6 +738,11 @@ mod tests {
             ("3", 3i64),
             ("-2", -2),
             ("-1234", -1234),
+            ("-9223372036854775808", -9223372036854775808),
+        ]);
+
+        test_parse_ok(vec![
+            ("18446744073709551615", 18446744073709551615u64),
         ]);
 
         test_parse_ok(vec![
@@ -694,9 +753,29 @@ mod tests {
             ("0.4e5", 0.4e5),
             ("0.4e15", 0.4e15),
             ("0.4e-01", 0.4e-01),
+            ("0.30000000000000004", 0.30000000000000004),
+            ("1e23", 1e23),
+            ("2.2250738585072011e-308", 2.2250738585072011e-308),
+            ("18446744073709551616", 18446744073709551616.0),
         ]);
     }
 
+    #[test]
+    fn test_parse_non_finite_literals() {
+        test_parse_err::<f64>(vec![
+            ("NaN", SyntaxError(InvalidSyntax(SomeValue), 1, 1)),
+            ("-Infinity", SyntaxError(InvalidNumber, 1, 2)),
+        ]);
+
+        let mut parser = Parser::new("[NaN,Infinity,-Infinity]".chars())
+            .non_finite_literals(true);
+        let v: Vec<f64> = Deserialize::deserialize(&mut parser).unwrap();
+        parser.end().unwrap();
+
+        assert!(v[0].is_nan());
+        assert_eq!(v.slice_from(1), [f64::INFINITY, f64::NEG_INFINITY].as_slice());
+    }
+
     #[test]
     fn test_parse_string() {
         test_parse_err::<String>(vec![
@@ -1,6 +1,20 @@
 pub use self::ser::Writer;
+pub use self::ser::{NonFiniteFloats, NonFiniteError, NonFiniteAsNull};
+pub use self::ser::{NonFiniteAsString, NonFiniteAsLiteral};
 pub use self::ser::{to_vec, to_string};
-pub use self::ser::escape_str;
+pub use self::ser::{escape_str, escape_str_with, Escaping};
 
 pub mod ser;
 pub mod de;
+
+// The float conversions and string escaping are shared with serde's json
+// module, so fixes only need to be made in one place.
+#[path = "../../../src/json/bignum.rs"]
+mod bignum;
+#[path = "../../../src/json/dtoa.rs"]
+#[allow(dead_code)]
+mod dtoa;
+#[path = "../../../src/json/escape.rs"]
+mod escape;
+#[path = "../../../src/json/strtod.rs"]
+mod strtod;
@@ -1,14 +1,21 @@
-use std::f64;
+use std::default::Default;
 use std::io::{IoError, MemWriter};
 use std::io;
-use std::num::{FPNaN, FPInfinite};
 
 use ser;
 use ser::Serializer;
 
+pub use super::escape::{Escaping, escape_bytes, escape_bytes_with};
+pub use super::escape::{escape_str, escape_str_with, escape_char, escape_char_with};
+pub use super::escape::{NonFiniteFloats, NonFiniteError, NonFiniteAsNull};
+pub use super::escape::{NonFiniteAsString, NonFiniteAsLiteral};
+use super::escape::{fmt_f32, fmt_f64};
+
 /// A structure for implementing serialization to JSON.
 pub struct Writer<W> {
     writer: W,
+    non_finite: NonFiniteFloats,
+    escaping: Escaping,
 }
 
 impl<W: io::Writer> Writer<W> {
@@ -18,9 +25,36 @@ impl<W: io::Writer> Writer<W> {
     pub fn new(writer: W) -> Writer<W> {
         Writer {
             writer: writer,
+            non_finite: NonFiniteError,
+            escaping: Default::default(),
         }
     }
 
+    /// Choose how `NaN` and infinite floats are written. By default they are
+    /// refused with an `InvalidInput` error, so they are never silently
+    /// turned into `null`.
+    #[inline]
+    pub fn non_finite_floats(mut self, non_finite: NonFiniteFloats) -> Writer<W> {
+        self.non_finite = non_finite;
+        self
+    }
+
+    /// Escapes every non-ASCII character in strings, so the output is plain
+    /// ASCII.
+    #[inline]
+    pub fn ascii_only(mut self, ascii_only: bool) -> Writer<W> {
+        self.escaping.ascii_only = ascii_only;
+        self
+    }
+
+    /// Escapes `<`, `>`, `&`, U+2028 and U+2029 in strings, so the output can
+    /// be embedded in an HTML `<script>` element.
+    #[inline]
+    pub fn html_safe(mut self, html_safe: bool) -> Writer<W> {
+        self.escaping.html_safe = html_safe;
+        self
+    }
+
     /// Unwrap the Writer from the Serializer.
     #[inline]
     pub fn unwrap(self) -> W {
@@ -33,11 +67,18 @@ impl<W: io::Writer> ser::Serializer<W, (), IoError> for Writer<W> {
     fn visit<
         T: ser::Serialize,
     >(&mut self, value: &T) -> Result<(), IoError> {
-        value.visit(&mut self.writer, Visitor)
+        let visitor = Visitor {
+            non_finite: self.non_finite,
+            escaping: self.escaping,
+        };
+        value.visit(&mut self.writer, visitor)
     }
 }
 
-struct Visitor;
+struct Visitor {
+    non_finite: NonFiniteFloats,
+    escaping: Escaping,
+}
 
 impl<W: io::Writer> ser::Visitor<W, (), IoError> for Visitor {
     #[inline]
@@ -104,19 +145,24 @@ impl<W: io::Writer> ser::Visitor<W, (), IoError> for Visitor {
         write!(writer, "{}", value)
     }
 
+    #[inline]
+    fn visit_f32(&self, writer: &mut W, value: f32) -> Result<(), IoError> {
+        fmt_f32(writer, value, self.non_finite)
+    }
+
     #[inline]
     fn visit_f64(&self, writer: &mut W, value: f64) -> Result<(), IoError> {
-        fmt_f64_or_null(writer, value)
+        fmt_f64(writer, value, self.non_finite)
     }
 
     #[inline]
     fn visit_char(&self, writer: &mut W, v: char) -> Result<(), IoError> {
-        escape_char(writer, v)
+        escape_char_with(writer, v, self.escaping)
     }
 
     #[inline]
     fn visit_str(&self, writer: &mut W, value: &str) -> Result<(), IoError> {
-        escape_str(writer, value)
+        escape_str_with(writer, value, self.escaping)
     }
 
     #[inline]
@@ -126,7 +172,7 @@ impl<W: io::Writer> ser::Visitor<W, (), IoError> for Visitor {
         try!(writer.write_str("["));
 
         loop {
-            match try!(visitor.visit(writer, Visitor)) {
+            match try!(visitor.visit(writer, *self)) {
                 Some(()) => { }
                 None => { break; }
             }
@@ -143,7 +189,7 @@ impl<W: io::Writer> ser::Visitor<W, (), IoError> for Visitor {
             try!(writer.write_str(","));
         }
 
-        value.visit(writer, Visitor)
+        value.visit(writer, *self)
     }
 
     #[inline]
@@ -153,7 +199,7 @@ impl<W: io::Writer> ser::Visitor<W, (), IoError> for Visitor {
         try!(writer.write_str("{{"));
 
         loop {
-            match try!(visitor.visit(writer, Visitor)) {
+            match try!(visitor.visit(writer, *self)) {
                 Some(()) => { }
                 None => { break; }
             }
@@ -171,62 +217,9 @@ impl<W: io::Writer> ser::Visitor<W, (), IoError> for Visitor {
             try!(writer.write_str(","));
         }
 
-        try!(key.visit(writer, Visitor));
+        try!(key.visit(writer, *self));
         try!(writer.write_str(":"));
-        value.visit(writer, Visitor)
-    }
-}
-
-#[inline]
-pub fn escape_bytes<W: io::Writer>(wr: &mut W, bytes: &[u8]) -> Result<(), IoError> {
-    try!(wr.write_str("\""));
-
-    let mut start = 0;
-
-    for (i, byte) in bytes.iter().enumerate() {
-        let escaped = match *byte {
-            b'"' => "\\\"",
-            b'\\' => "\\\\",
-            b'\x08' => "\\b",
-            b'\x0c' => "\\f",
-            b'\n' => "\\n",
-            b'\r' => "\\r",
-            b'\t' => "\\t",
-            _ => { continue; }
-        };
-
-        if start < i {
-            try!(wr.write(bytes.slice(start, i)));
-        }
-
-        try!(wr.write_str(escaped));
-
-        start = i + 1;
-    }
-
-    if start != bytes.len() {
-        try!(wr.write(bytes.slice_from(start)));
-    }
-
-    wr.write_str("\"")
-}
-
-#[inline]
-pub fn escape_str<W: io::Writer>(wr: &mut W, value: &str) -> Result<(), IoError> {
-    escape_bytes(wr, value.as_bytes())
-}
-
-#[inline]
-pub fn escape_char<W: io::Writer>(wr: &mut W, value: char) -> Result<(), IoError> {
-    let mut buf = [0, .. 4];
-    value.encode_utf8(buf);
-    escape_bytes(wr, buf)
-}
-
-fn fmt_f64_or_null<W: io::Writer>(wr: &mut W, value: f64) -> Result<(), IoError> {
-    match value.classify() {
-        FPNaN | FPInfinite => wr.write_str("null"),
-        _ => wr.write_str(f64::to_str_digits(value, 6).as_slice()),
+        value.visit(writer, *self)
     }
 }
 
@@ -247,3 +240,33 @@ pub fn to_string<
     let vec = try!(to_vec(value));
     Ok(String::from_utf8(vec))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::f64;
+    use std::io::{IoError, MemWriter, InvalidInput};
+
+    use ser::Serializer;
+    use super::{Writer, NonFiniteFloats};
+    use super::{NonFiniteError, NonFiniteAsNull, NonFiniteAsString, NonFiniteAsLiteral};
+
+    fn write(value: f64, non_finite: NonFiniteFloats) -> Result<String, IoError> {
+        let mut writer = Writer::new(MemWriter::new()).non_finite_floats(non_finite);
+        try!(writer.visit(&value));
+        Ok(String::from_utf8(writer.unwrap().unwrap()).unwrap())
+    }
+
+    #[test]
+    fn test_write_non_finite_floats() {
+        let value = vec![f64::NAN, f64::INFINITY, f64::NEG_INFINITY];
+        assert_eq!(super::to_vec(&value).unwrap_err().kind, InvalidInput);
+
+        assert_eq!(write(f64::NAN, NonFiniteError).unwrap_err().kind, InvalidInput);
+        assert_eq!(write(1.5, NonFiniteError).unwrap(), "1.5".to_string());
+        assert_eq!(write(f64::NAN, NonFiniteAsNull).unwrap(), "null".to_string());
+        assert_eq!(write(f64::INFINITY, NonFiniteAsString).unwrap(),
+                   "\"Infinity\"".to_string());
+        assert_eq!(write(f64::NEG_INFINITY, NonFiniteAsLiteral).unwrap(),
+                   "-Infinity".to_string());
+    }
+}
@@ -19,6 +19,9 @@ use syntax::ast::{
     MutMutable,
     LitStr,
     StructField,
+    StructVariantKind,
+    TupleVariantKind,
+    Ty,
     Variant,
 };
 use syntax::ast;
@@ -65,6 +68,10 @@ pub fn plugin_registrar(reg: &mut Registry) {
     reg.register_syntax_extension(
         token::intern("deriving_deserializable"),
         Decorator(box expand_deriving_deserializable));
+
+    reg.register_syntax_extension(
+        token::intern("deriving_json_schema"),
+        Decorator(box expand_deriving_json_schema));
 }
 
 fn expand_deriving_serializable(cx: &mut ExtCtxt,
@@ -243,6 +250,34 @@ pub fn expand_deriving_deserializable(cx: &mut ExtCtxt,
                 combine_substructure: combine_substructure(|a, b, c| {
                     deserializable_substructure(a, b, c)
                 }),
+            },
+            MethodDef {
+                name: "deserialize_token_into",
+                generics: LifetimeBounds::empty(),
+                explicit_self: None,
+                args: vec!(
+                    Ptr(box Self, Borrowed(None, MutMutable)),
+                    Ptr(
+                        box Literal(Path::new_local("__D")),
+                        Borrowed(None, MutMutable)
+                    ),
+                    Literal(Path::new(vec!("serde", "de", "Token"))),
+                ),
+                ret_ty: Literal(
+                    Path::new_(
+                        vec!("std", "result", "Result"),
+                        None,
+                        vec!(
+                            box Tuple(Vec::new()),
+                            box Literal(Path::new_local("__E"))
+                        ),
+                        true
+                    )
+                ),
+                attributes: Vec::new(),
+                combine_substructure: combine_substructure(|a, b, c| {
+                    deserializable_into_substructure(a, b, c)
+                }),
             })
     };
 
@@ -279,6 +314,55 @@ fn deserializable_substructure(cx: &mut ExtCtxt, span: Span,
     }
 }
 
+fn deserializable_into_substructure(cx: &mut ExtCtxt, span: Span,
+                                    substr: &Substructure) -> P<Expr> {
+    let place = substr.nonself_args[0].clone();
+    let deserializer = substr.nonself_args[1].clone();
+    let token = substr.nonself_args[2].clone();
+
+    match *substr.fields {
+        StaticStruct(ref definition, ref fields) => {
+            deserialize_struct_into(
+                cx,
+                span,
+                substr.type_ident,
+                definition.fields.as_slice(),
+                fields,
+                place,
+                deserializer,
+                token)
+        }
+        StaticEnum(..) => {
+            // An enum may change variant, so it is replaced.
+            quote_expr!(cx, {
+                *$place = try!(::serde::de::Deserializable::deserialize_token(
+                    $deserializer,
+                    $token));
+                Ok(())
+            })
+        }
+        _ => cx.bug("expected StaticEnum or StaticStruct in deriving(Deserializable)")
+    }
+}
+
+/// The names fields are serialized under.
+fn serial_field_strs(
+    cx: &ExtCtxt,
+    span: Span,
+    definitions: &[StructField],
+    fields: &[(Ident, Span)]
+) -> Vec<P<ast::Expr>> {
+    fields.iter()
+        .zip(definitions.iter())
+        .map(|(&(name, _), def)| {
+            match find_serial_name(def.node.attrs.iter()) {
+                Some(serial) => cx.expr_str(span, serial),
+                None => cx.expr_str(span, token::get_ident(name)),
+            }
+        })
+        .collect()
+}
+
 fn deserialize_struct(
     cx: &ExtCtxt,
     span: Span,
@@ -304,15 +388,7 @@ fn deserialize_struct(
         .collect();
 
     // Convert each field into their string.
-    let field_strs: Vec<P<ast::Expr>> = fields.iter()
-        .zip(definitions.iter())
-        .map(|(&(name, _), def)| {
-            match find_serial_name(def.node.attrs.iter()) {
-                Some(serial) => cx.expr_str(span, serial),
-                None => cx.expr_str(span, token::get_ident(name)),
-            }
-        })
-        .collect();
+    let field_strs = serial_field_strs(cx, span, definitions, fields);
 
     // Declare the static vec slice of field names.
     let static_fields = cx.expr_vec_slice(span, field_strs.clone());
@@ -383,6 +459,98 @@ fn deserialize_struct(
     })
 }
 
+/// Deserializes a struct over an existing one, field by field, so that each
+/// field can reuse its allocations.
+fn deserialize_struct_into(
+    cx: &ExtCtxt,
+    span: Span,
+    type_ident: Ident,
+    definitions: &[StructField],
+    fields: &StaticFields,
+    place: P<ast::Expr>,
+    deserializer: P<ast::Expr>,
+    token: P<ast::Expr>
+) -> P<ast::Expr> {
+    let type_name_str = cx.expr_str(span, token::get_ident(type_ident));
+
+    let fields = match *fields {
+        Unnamed(_) => panic!(),
+        Named(ref fields) => fields.as_slice(),
+    };
+
+    // Track whether each field was seen.
+    let seen_idents: Vec<ast::Ident> = fields.iter()
+        .enumerate()
+        .map(|(idx, _)| {
+            cx.ident_of(format!("seen{}", idx).as_slice())
+        })
+        .collect();
+
+    let field_strs = serial_field_strs(cx, span, definitions, fields);
+
+    let field_exprs: Vec<P<ast::Expr>> = fields.iter()
+        .map(|&(name, span)| cx.expr_field_access(span, place.clone(), name))
+        .collect();
+
+    let static_fields = cx.expr_vec_slice(span, field_strs.clone());
+
+    let let_seen: Vec<P<ast::Stmt>> = seen_idents.iter()
+        .map(|ident| quote_stmt!(cx, let mut $ident = false))
+        .collect();
+
+    let idx_arms: Vec<ast::Arm> = seen_idents.iter()
+        .zip(field_exprs.iter())
+        .enumerate()
+        .map(|(idx, (ident, field))| {
+            let field = cx.expr_mut_addr_of(span, field.clone());
+            quote_arm!(cx,
+                Some($idx) => {
+                    try!($deserializer.expect_struct_value_into($field));
+                    $ident = true;
+                }
+            )
+        })
+        .collect();
+
+    let missing_fields: Vec<P<ast::Stmt>> = seen_idents.iter()
+        .zip(field_exprs.iter())
+        .zip(field_strs.iter())
+        .map(|((ident, field), field_str)| {
+            quote_stmt!(cx,
+                if !$ident {
+                    $field = try!($deserializer.missing_field($field_str));
+                }
+            )
+        })
+        .collect();
+
+    quote_expr!(cx, {
+        try!($deserializer.expect_struct_start($token, $type_name_str));
+
+        static FIELDS: &'static [&'static str] = $static_fields;
+        $let_seen
+
+        loop {
+            let idx = match try!($deserializer.expect_struct_field_or_end(FIELDS)) {
+                Some(idx) => idx,
+                None => { break; }
+            };
+
+            match idx {
+                $idx_arms
+                Some(_) => unreachable!(),
+                None => {
+                    let _: ::serde::de::IgnoreTokens =
+                        try!(::serde::de::Deserializable::deserialize($deserializer));
+                }
+            }
+        }
+
+        $missing_fields
+        Ok(())
+    })
+}
+
 fn deserialize_enum(
     cx: &ExtCtxt,
     span: Span,
@@ -485,6 +653,159 @@ fn deserializable_static_fields(
     }
 }
 
+pub fn expand_deriving_json_schema(cx: &mut ExtCtxt,
+                                   span: Span,
+                                   mitem: &MetaItem,
+                                   item: &Item,
+                                   push: |P<Item>|) {
+    // The type parameters tell instantiations of a generic type apart in
+    // `Definitions`.
+    let ty_params: Vec<Ident> = match item.node {
+        ItemStruct(_, ref generics) | ItemEnum(_, ref generics) => {
+            generics.ty_params.iter().map(|param| param.ident).collect()
+        }
+        _ => Vec::new(),
+    };
+
+    let trait_def = TraitDef {
+        span: span,
+        attributes: Vec::new(),
+        path: Path::new(vec!("serde", "json", "schema", "ToJsonSchema")),
+        additional_bounds: Vec::new(),
+        generics: LifetimeBounds::empty(),
+        methods: vec!(
+            MethodDef {
+                name: "json_schema",
+                generics: LifetimeBounds::empty(),
+                explicit_self: None,
+                args: vec!(
+                    Literal(Path::new_(vec!("std", "option", "Option"), None,
+                                       vec!(box Self), true)),
+                    Ptr(
+                        box Literal(Path::new(vec!("serde", "json", "schema", "Definitions"))),
+                        Borrowed(None, MutMutable)
+                    ),
+                ),
+                ret_ty: Literal(Path::new(vec!("serde", "json", "Json"))),
+                attributes: Vec::new(),
+                combine_substructure: combine_substructure(|a, b, c| {
+                    json_schema_substructure(a, b, c, ty_params.as_slice())
+                }),
+            })
+    };
+
+    trait_def.expand(cx, mitem, item, push)
+}
+
+fn json_schema_substructure(cx: &mut ExtCtxt, span: Span,
+                            substr: &Substructure,
+                            ty_params: &[Ident]) -> P<Expr> {
+    let type_name = cx.expr_str(span, token::get_ident(substr.type_ident));
+    let defs = substr.nonself_args[1].clone();
+
+    // The type is defined under a key made of its path and the schemas of
+    // its type parameters, so that neither two instantiations of a generic
+    // type nor two types of the same name share a definition.
+    let params = ty_params.iter()
+        .map(|param| {
+            let none = none_of(cx, span, cx.ty_ident(span, *param));
+            quote_expr!(cx,
+                ::serde::json::schema::ToJsonSchema::json_schema($none, &mut *$defs))
+        })
+        .collect();
+    let params = cx.expr_vec_ng(span, params);
+    let key = quote_expr!(cx, ::serde::json::schema::definition_key(
+        concat!(module_path!(), "::", $type_name), $params));
+
+    // The schema is built inside a closure given to `Definitions::define`,
+    // whose `defs` argument the field schemas refer to.
+    match *substr.fields {
+        StaticStruct(ref definition, _) => {
+            // Name the fields the same way `serializable_substructure` does.
+            let fields = definition.fields.iter()
+                .enumerate()
+                .map(|(i, def)| {
+                    let name = match (find_serial_name(def.node.attrs.iter()), def.node.ident()) {
+                        (Some(serial), _) => serial,
+                        (None, Some(id)) => token::get_ident(id),
+                        (None, None) => token::intern_and_get_ident(format!("_field{}", i).as_slice()),
+                    };
+
+                    cx.expr_tuple(span, vec!(
+                        cx.expr_str(span, name),
+                        schema_of(cx, span, def.node.ty.clone()),
+                        is_optional(cx, span, def.node.ty.clone()),
+                    ))
+                })
+                .collect();
+
+            let fields = cx.expr_vec_ng(span, fields);
+
+            quote_expr!(cx, {
+                let key = $key;
+                $defs.define($type_name, key, |defs| {
+                    ::serde::json::schema::struct_schema($type_name, $fields)
+                })
+            })
+        }
+        StaticEnum(ref definition, _) => {
+            let variants = definition.variants.iter()
+                .map(|variant| {
+                    let tys: Vec<P<Ty>> = match variant.node.kind {
+                        TupleVariantKind(ref args) => {
+                            args.iter().map(|arg| arg.ty.clone()).collect()
+                        }
+                        StructVariantKind(ref def) => {
+                            def.fields.iter().map(|field| field.node.ty.clone()).collect()
+                        }
+                    };
+
+                    let fields = tys.into_iter()
+                        .map(|ty| schema_of(cx, span, ty))
+                        .collect();
+
+                    cx.expr_tuple(span, vec!(
+                        cx.expr_str(span, token::get_ident(variant.node.name)),
+                        cx.expr_vec_ng(span, fields),
+                    ))
+                })
+                .collect();
+
+            let variants = cx.expr_vec_ng(span, variants);
+
+            quote_expr!(cx, {
+                let key = $key;
+                $defs.define($type_name, key, |defs| {
+                    ::serde::json::schema::enum_schema($type_name, $variants)
+                })
+            })
+        }
+        _ => cx.bug("expected StaticEnum or StaticStruct in deriving(JsonSchema)")
+    }
+}
+
+/// Create `None::<ty>`, which names a type for the static methods of
+/// `ToJsonSchema`.
+fn none_of(cx: &ExtCtxt, span: Span, ty: P<Ty>) -> P<Expr> {
+    let path = cx.path_all(
+        span,
+        true,
+        vec!(cx.ident_of("std"), cx.ident_of("option"), cx.ident_of("None")),
+        Vec::new(),
+        vec!(ty));
+    cx.expr_path(path)
+}
+
+fn schema_of(cx: &ExtCtxt, span: Span, ty: P<Ty>) -> P<Expr> {
+    let none = none_of(cx, span, ty);
+    quote_expr!(cx, ::serde::json::schema::ToJsonSchema::json_schema($none, defs))
+}
+
+fn is_optional(cx: &ExtCtxt, span: Span, ty: P<Ty>) -> P<Expr> {
+    let none = none_of(cx, span, ty);
+    quote_expr!(cx, ::serde::json::schema::ToJsonSchema::is_optional($none))
+}
+
 fn find_serial_name<'a, I: Iterator<&'a Attribute>>(mut iterator: I)
                     -> Option<token::InternedString> {
     for at in iterator {
@@ -8,6 +8,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::ascii::StrAsciiExt;
 use std::collections::{HashMap, HashSet, TreeMap, TreeSet};
 use std::hash::Hash;
 use std::num;
@@ -15,6 +16,7 @@ use std::rc::Rc;
 use std::option;
 use std::string;
 use std::sync::Arc;
+use std::vec;
 
 #[deriving(Clone, PartialEq, Show)]
 pub enum Token {
@@ -32,6 +34,9 @@ pub enum Token {
     U64(u64),
     F32(f32),
     F64(f64),
+    /// A number kept in its exact textual form, for numbers that may not fit
+    /// in any of the primitive numeric types.
+    Number(string::String),
     Char(char),
     Str(&'static str),
     String(string::String),
@@ -63,6 +68,7 @@ impl Token {
             U64(_) => U64Kind,
             F32(_) => F32Kind,
             F64(_) => F64Kind,
+            Number(_) => NumberKind,
             Char(_) => CharKind,
             Str(_) => StrKind,
             String(_) => StringKind,
@@ -93,6 +99,7 @@ pub enum TokenKind {
     U64Kind,
     F32Kind,
     F64Kind,
+    NumberKind,
     CharKind,
     StrKind,
     StringKind,
@@ -107,7 +114,7 @@ pub enum TokenKind {
     EndKind,
 }
 
-static PRIMITIVE_TOKEN_KINDS: [TokenKind, .. 12] = [
+static PRIMITIVE_TOKEN_KINDS: [TokenKind, .. 13] = [
     IntKind,
     I8Kind,
     I16Kind,
@@ -120,6 +127,7 @@ static PRIMITIVE_TOKEN_KINDS: [TokenKind, .. 12] = [
     U64Kind,
     F32Kind,
     F64Kind,
+    NumberKind,
 ];
 
 static STR_TOKEN_KINDS: [TokenKind, .. 2] = [
@@ -153,6 +161,7 @@ impl ::std::fmt::Show for TokenKind {
             U64Kind => "U64".fmt(f),
             F32Kind => "F32".fmt(f),
             F64Kind => "F64".fmt(f),
+            NumberKind => "Number".fmt(f),
             CharKind => "Char".fmt(f),
             StrKind => "Str".fmt(f),
             StringKind => "String".fmt(f),
@@ -167,6 +176,131 @@ impl ::std::fmt::Show for TokenKind {
     }
 }
 
+impl TokenKind {
+    /// Describes the kind of token in words, for error messages.
+    pub fn description(&self) -> &'static str {
+        match *self {
+            NullKind => "a null",
+            BoolKind => "a bool",
+            IntKind => "an int",
+            I8Kind => "an i8",
+            I16Kind => "an i16",
+            I32Kind => "an i32",
+            I64Kind => "an i64",
+            UintKind => "a uint",
+            U8Kind => "a u8",
+            U16Kind => "a u16",
+            U32Kind => "a u32",
+            U64Kind => "a u64",
+            F32Kind => "an f32",
+            F64Kind => "an f64",
+            NumberKind => "a number",
+            CharKind => "a char",
+            StrKind => "a str",
+            StringKind => "a string",
+            OptionKind => "an option",
+            TupleStartKind => "a tuple",
+            StructStartKind => "a struct",
+            EnumStartKind => "an enum",
+            SeqStartKind => "a sequence",
+            MapStartKind => "a map",
+            EndKind => "the end of a compound value",
+        }
+    }
+}
+
+/// The primitive number types that `Deserializer::expect_num` converts
+/// tokens into.
+pub trait FromNumber: NumCast {
+    /// The kind of token that holds this type.
+    fn token_kind(_: option::Option<Self>) -> TokenKind;
+}
+
+macro_rules! impl_from_number {
+    ($ty:ty, $kind:expr) => {
+        impl FromNumber for $ty {
+            #[inline]
+            fn token_kind(_: option::Option<$ty>) -> TokenKind { $kind }
+        }
+    }
+}
+
+impl_from_number!(int, IntKind)
+impl_from_number!(i8, I8Kind)
+impl_from_number!(i16, I16Kind)
+impl_from_number!(i32, I32Kind)
+impl_from_number!(i64, I64Kind)
+impl_from_number!(uint, UintKind)
+impl_from_number!(u8, U8Kind)
+impl_from_number!(u16, U16Kind)
+impl_from_number!(u32, U32Kind)
+impl_from_number!(u64, U64Kind)
+impl_from_number!(f32, F32Kind)
+impl_from_number!(f64, F64Kind)
+
+/// Converts a float, which only fits in an integer type if it is integral and
+/// in range. Converting into an `f32` rounds.
+fn float_to_num<T: FromNumber>(value: f64) -> option::Option<T> {
+    match FromNumber::token_kind(None::<T>) {
+        F32Kind | F64Kind => num::cast(value),
+        _ => {
+            // This also rejects NaN and the infinities.
+            if value.fract() != 0.0 {
+                return None;
+            }
+
+            // Casting a float that is out of range to an integer is
+            // undefined, so only cast within the bounds of `i64` or `u64`.
+            // The bounds are powers of two, which a float holds exactly.
+            // Casting the integer to `T` then checks `T`'s own bounds.
+            if value >= -9223372036854775808.0 && value < 9223372036854775808.0 {
+                num::cast(value as i64)
+            } else if value >= 0.0 && value < 18446744073709551616.0 {
+                num::cast(value as u64)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Converts the text of a `Number` token, trying the integer types first so
+/// that large integers don't lose precision by going through a float.
+fn number_from_str<T: FromNumber>(value: &str) -> option::Option<T> {
+    match from_str::<i64>(value) {
+        Some(n) => return num::cast(n),
+        None => { }
+    }
+
+    match from_str::<u64>(value) {
+        Some(n) => return num::cast(n),
+        None => { }
+    }
+
+    match from_str::<f64>(value) {
+        Some(n) => float_to_num(n),
+        None => None,
+    }
+}
+
+/// Like `number_from_str`, but for `FromPrimitive` types.
+fn primitive_from_str<T: FromPrimitive>(value: &str) -> option::Option<T> {
+    match from_str::<i64>(value) {
+        Some(n) => return num::from_i64(n),
+        None => { }
+    }
+
+    match from_str::<u64>(value) {
+        Some(n) => return num::from_u64(n),
+        None => { }
+    }
+
+    match from_str::<f64>(value) {
+        Some(n) => num::from_f64(n),
+        None => None,
+    }
+}
+
 macro_rules! to_result {
     ($expr:expr, $err:expr) => {
         match $expr {
@@ -176,6 +310,26 @@ macro_rules! to_result {
     }
 }
 
+/// How an optional value starts, as read by `Deserializer::expect_option_start`.
+pub enum OptionStart {
+    /// The value is absent.
+    NoValue,
+    /// The value is present and starts with the next token.
+    ValueNext,
+    /// The value is present and starts with this token.
+    ValueToken(Token),
+}
+
+/// How a sequence starts, as read by `Deserializer::expect_seq_shape`.
+pub enum SeqShape {
+    /// A sequence of about this many elements, closed by an `End` token.
+    SeqElts(uint),
+    /// A single element that starts with this token.
+    SeqOne(Token),
+    /// No elements at all.
+    SeqEmpty,
+}
+
 pub trait Deserializer<E>: Iterator<Result<Token, E>> {
     /// Called when a `Deserializable` expected more tokens, but the
     /// `Deserializer` was empty.
@@ -190,6 +344,13 @@ pub trait Deserializer<E>: Iterator<Result<Token, E>> {
     /// Called when a value was unable to be coerced into another value.
     fn conversion_error(&mut self, token: Token) -> E;
 
+    /// Called when a number doesn't fit exactly in the primitive type of
+    /// kind `expected`, because it is out of range or has a fractional part.
+    #[inline]
+    fn number_conversion_error(&mut self, token: Token, _expected: TokenKind) -> E {
+        self.conversion_error(token)
+    }
+
     /// Called when a `Deserializable` structure did not deserialize a field
     /// named `field`.
     fn missing_field<
@@ -202,6 +363,20 @@ pub trait Deserializer<E>: Iterator<Result<Token, E>> {
         Ok(())
     }
 
+    /// Returns where the token most recently returned by `next` starts, if
+    /// the `Deserializer` tracks its position in the input.
+    #[inline]
+    fn token_start(&self) -> option::Option<Position> {
+        None
+    }
+
+    /// Returns the position just past the token most recently returned by
+    /// `next`, if the `Deserializer` tracks its position in the input.
+    #[inline]
+    fn token_end(&self) -> option::Option<Position> {
+        None
+    }
+
     #[inline]
     fn expect_token(&mut self) -> Result<Token, E> {
         match self.next() {
@@ -234,22 +409,8 @@ pub trait Deserializer<E>: Iterator<Result<Token, E>> {
     }
 
     #[inline]
-    fn expect_num<T: NumCast>(&mut self, token: Token) -> Result<T, E> {
-        match token {
-            Int(x) => to_result!(num::cast(x), self.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
-            I8(x) => to_result!(num::cast(x), self.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
-            I16(x) => to_result!(num::cast(x), self.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
-            I32(x) => to_result!(num::cast(x), self.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
-            I64(x) => to_result!(num::cast(x), self.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
-            Uint(x) => to_result!(num::cast(x), self.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
-            U8(x) =>  to_result!(num::cast(x), self.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
-            U16(x) => to_result!(num::cast(x), self.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
-            U32(x) => to_result!(num::cast(x), self.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
-            U64(x) => to_result!(num::cast(x), self.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
-            F32(x) => to_result!(num::cast(x), self.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
-            F64(x) => to_result!(num::cast(x), self.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
-            token => Err(self.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
-        }
+    fn expect_num<T: FromNumber>(&mut self, token: Token) -> Result<T, E> {
+        default_expect_num(self, token)
     }
 
     #[inline]
@@ -267,6 +428,12 @@ pub trait Deserializer<E>: Iterator<Result<Token, E>> {
             U64(x) => to_result!(num::from_u64(x), self.conversion_error(token)),
             F32(x) => to_result!(num::from_f32(x), self.conversion_error(token)),
             F64(x) => to_result!(num::from_f64(x), self.conversion_error(token)),
+            Number(value) => {
+                match primitive_from_str(value.as_slice()) {
+                    Some(value) => Ok(value),
+                    None => Err(self.conversion_error(Number(value))),
+                }
+            }
             token => Err(self.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
         }
     }
@@ -303,20 +470,45 @@ pub trait Deserializer<E>: Iterator<Result<Token, E>> {
         }
     }
 
+    /// Decides whether an optional value is present. Both `expect_option`
+    /// and seeds go through this, so overriding it changes what they accept.
+    #[inline]
+    fn expect_option_start(&mut self, token: Token) -> Result<OptionStart, E> {
+        match token {
+            Option(false) => Ok(NoValue),
+            Option(true) => Ok(ValueNext),
+            token => Err(self.syntax_error(token, [OptionKind])),
+        }
+    }
+
     #[inline]
     fn expect_option<
         T: Deserializable<Self, E>
     >(&mut self, token: Token) -> Result<option::Option<T>, E> {
-        match token {
-            Option(false) => Ok(None),
-            Option(true) => {
+        match try!(self.expect_option_start(token)) {
+            NoValue => Ok(None),
+            ValueNext => {
                 let value: T = try!(Deserializable::deserialize(self));
                 Ok(Some(value))
             }
-            token => Err(self.syntax_error(token, [OptionKind])),
+            ValueToken(token) => {
+                let value: T = try!(Deserializable::deserialize_token(self, token));
+                Ok(Some(value))
+            }
         }
     }
 
+    /// Reads an optional value whose first token hasn't been read yet.
+    /// Deserializers that can look ahead override this, so that a present
+    /// value is read by `T` from its start.
+    #[inline]
+    fn expect_option_value<
+        T: Deserializable<Self, E>
+    >(&mut self) -> Result<option::Option<T>, E> {
+        let token = try!(self.expect_token());
+        self.expect_option(token)
+    }
+
     #[inline]
     fn expect_tuple_start(&mut self, token: Token) -> Result<uint, E> {
         match token {
@@ -382,6 +574,14 @@ pub trait Deserializer<E>: Iterator<Result<Token, E>> {
         Deserializable::deserialize(self)
     }
 
+    /// Like `expect_struct_value`, but deserializes over `value`.
+    #[inline]
+    fn expect_struct_value_into<
+        T: Deserializable<Self, E>
+    >(&mut self, value: &mut T) -> Result<(), E> {
+        value.deserialize_into(self)
+    }
+
     #[inline]
     fn expect_struct_end(&mut self) -> Result<(), E> {
         match try!(self.expect_token()) {
@@ -444,13 +644,44 @@ pub trait Deserializer<E>: Iterator<Result<Token, E>> {
         }
     }
 
+    /// Decides how a sequence is laid out. Both `expect_seq` and seeds go
+    /// through this, so overriding it changes what they accept.
+    #[inline]
+    fn expect_seq_shape(&mut self, token: Token) -> Result<SeqShape, E> {
+        Ok(SeqElts(try!(self.expect_seq_start(token))))
+    }
+
+    /// Like `expect_seq_elt_or_end`, but deserializes the element over
+    /// `value`. Returns `false` at the end of the sequence.
+    #[inline]
+    fn expect_seq_elt_into_or_end<
+        T: Deserializable<Self, E>
+    >(&mut self, value: &mut T) -> Result<bool, E> {
+        match try!(self.expect_token()) {
+            End => Ok(false),
+            token => {
+                try!(Deserializable::deserialize_token_into(value, self, token));
+                Ok(true)
+            }
+        }
+    }
+
     #[inline]
     fn expect_seq<
         'a,
         T: Deserializable<Self, E>,
         C: FromIterator<T>
     >(&'a mut self, token: Token) -> Result<C, E> {
-        let len = try!(self.expect_seq_start(token));
+        let len = match try!(self.expect_seq_shape(token)) {
+            SeqElts(len) => len,
+            SeqOne(token) => {
+                let value: T = try!(Deserializable::deserialize_token(self, token));
+                return Ok(FromIterator::from_iter(Some(value).into_iter()));
+            }
+            SeqEmpty => {
+                return Ok(FromIterator::from_iter(None::<T>.into_iter()));
+            }
+        };
 
         let mut d: SeqDeserializer<'a, Self, E> = SeqDeserializer {
             d: self,
@@ -576,6 +807,43 @@ impl<
     }
 }
 
+/// The default implementation of `Deserializer::expect_num`, for
+/// deserializers that accept extra tokens as numbers but otherwise convert
+/// them as usual.
+///
+/// Conversions are exact: a number that is out of range for `T`, or a float
+/// with a fractional part converted into an integer type, is a
+/// `number_conversion_error` rather than being truncated. Integers converted
+/// into floats are rounded.
+#[inline]
+pub fn default_expect_num<
+    D: Deserializer<E>,
+    E,
+    T: FromNumber
+>(d: &mut D, token: Token) -> Result<T, E> {
+    let value = match token {
+        Int(x) => num::cast(x),
+        I8(x) => num::cast(x),
+        I16(x) => num::cast(x),
+        I32(x) => num::cast(x),
+        I64(x) => num::cast(x),
+        Uint(x) => num::cast(x),
+        U8(x) => num::cast(x),
+        U16(x) => num::cast(x),
+        U32(x) => num::cast(x),
+        U64(x) => num::cast(x),
+        F32(x) => float_to_num(x as f64),
+        F64(x) => float_to_num(x),
+        Number(ref value) => number_from_str(value.as_slice()),
+        token => { return Err(d.syntax_error(token, PRIMITIVE_TOKEN_KINDS)); }
+    };
+
+    match value {
+        Some(value) => Ok(value),
+        None => Err(d.number_conversion_error(token, FromNumber::token_kind(None::<T>))),
+    }
+}
+
 //////////////////////////////////////////////////////////////////////////////
 
 pub trait Deserializable<D: Deserializer<E>, E> {
@@ -586,6 +854,23 @@ pub trait Deserializable<D: Deserializer<E>, E> {
     }
 
     fn deserialize_token(d: &mut D, token: Token) -> Result<Self, E>;
+
+    /// Deserializes over an existing value, reusing its allocations where it
+    /// can. If this fails, the value is left in a valid but unspecified
+    /// state.
+    #[inline]
+    fn deserialize_into(&mut self, d: &mut D) -> Result<(), E> {
+        let token = try!(d.expect_token());
+        Deserializable::deserialize_token_into(self, d, token)
+    }
+
+    /// Like `deserialize_token`, but overwrites `value`. By default `value`
+    /// is replaced by a newly deserialized one.
+    #[inline]
+    fn deserialize_token_into(value: &mut Self, d: &mut D, token: Token) -> Result<(), E> {
+        *value = try!(Deserializable::deserialize_token(d, token));
+        Ok(())
+    }
 }
 
 //////////////////////////////////////////////////////////////////////////////
@@ -616,7 +901,33 @@ impl_deserializable!(f32, expect_num)
 impl_deserializable!(f64, expect_num)
 impl_deserializable!(char, expect_char)
 impl_deserializable!(&'static str, expect_str)
-impl_deserializable!(string::String, expect_string)
+
+impl<D: Deserializer<E>, E> Deserializable<D, E> for string::String {
+    #[inline]
+    fn deserialize_token(d: &mut D, token: Token) -> Result<string::String, E> {
+        d.expect_string(token)
+    }
+
+    // A `String` token has already been allocated by the deserializer, so
+    // it is taken over rather than copied.
+    #[inline]
+    fn deserialize_token_into(value: &mut string::String,
+                              d: &mut D,
+                              token: Token) -> Result<(), E> {
+        match token {
+            Str(s) => {
+                value.clear();
+                value.push_str(s);
+            }
+            Char(c) => {
+                value.clear();
+                value.push(c);
+            }
+            token => { *value = try!(d.expect_string(token)); }
+        }
+        Ok(())
+    }
+}
 
 //////////////////////////////////////////////////////////////////////////////
 
@@ -629,6 +940,11 @@ impl<
     fn deserialize_token(d: &mut D, token: Token) -> Result<Box<T>, E> {
         Ok(box try!(Deserializable::deserialize_token(d, token)))
     }
+
+    #[inline]
+    fn deserialize_token_into(value: &mut Box<T>, d: &mut D, token: Token) -> Result<(), E> {
+        Deserializable::deserialize_token_into(&mut **value, d, token)
+    }
 }
 
 impl<
@@ -655,15 +971,67 @@ impl<
 
 //////////////////////////////////////////////////////////////////////////////
 
+/// A position in the input of a `Deserializer`.
+#[deriving(Clone, PartialEq, Eq, PartialOrd, Ord, Show)]
+pub struct Position {
+    pub line: uint,
+    pub col: uint,
+}
+
+/// A value along with the span of input it was deserialized from, so that
+/// checks done after deserialization can point at the offending text. The
+/// span runs from the start of the value's first token to just past its
+/// last one. Both ends are `None` if the `Deserializer` doesn't track its
+/// position.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Spanned<T> {
+    pub value: T,
+    pub start: option::Option<Position>,
+    pub end: option::Option<Position>,
+}
+
+impl<
+    D: Deserializer<E>,
+    E,
+    T: Deserializable<D, E>
+> Deserializable<D, E> for Spanned<T> {
+    #[inline]
+    fn deserialize_token(d: &mut D, token: Token) -> Result<Spanned<T>, E> {
+        let start = d.token_start();
+        let value = try!(Deserializable::deserialize_token(d, token));
+
+        Ok(Spanned {
+            value: value,
+            start: start,
+            end: d.token_end(),
+        })
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
 impl<
     D: Deserializer<E>,
     E,
     T: Deserializable<D ,E>
 > Deserializable<D, E> for option::Option<T> {
+    #[inline]
+    fn deserialize(d: &mut D) -> Result<option::Option<T>, E> {
+        d.expect_option_value()
+    }
+
     #[inline]
     fn deserialize_token(d: &mut D, token: Token) -> Result<option::Option<T>, E> {
         d.expect_option(token)
     }
+
+    // Replaced like any other value, but leaving the first token to
+    // `expect_option_value`, as `deserialize` does.
+    #[inline]
+    fn deserialize_into(&mut self, d: &mut D) -> Result<(), E> {
+        *self = try!(d.expect_option_value());
+        Ok(())
+    }
 }
 
 //////////////////////////////////////////////////////////////////////////////
@@ -677,6 +1045,39 @@ impl<
     fn deserialize_token(d: &mut D, token: Token) -> Result<Vec<T>, E> {
         d.expect_seq(token)
     }
+
+    // Existing elements are deserialized over, and the vector is only grown
+    // if the new sequence is longer.
+    #[inline]
+    fn deserialize_token_into(value: &mut Vec<T>, d: &mut D, token: Token) -> Result<(), E> {
+        match token {
+            TupleStart(_) | SeqStart(_) => { }
+            token => {
+                *value = try!(Deserializable::deserialize_token(d, token));
+                return Ok(());
+            }
+        }
+
+        try!(d.expect_seq_start(token));
+
+        let mut len = 0;
+        loop {
+            if len < value.len() {
+                if !try!(d.expect_seq_elt_into_or_end(value.get_mut(len))) {
+                    break;
+                }
+            } else {
+                match try!(d.expect_seq_elt_or_end()) {
+                    Some(elt) => { value.push(elt); }
+                    None => { break; }
+                }
+            }
+            len += 1;
+        }
+
+        value.truncate(len);
+        Ok(())
+    }
 }
 
 //////////////////////////////////////////////////////////////////////////////
@@ -691,6 +1092,32 @@ impl<
     fn deserialize_token(d: &mut D, token: Token) -> Result<HashMap<K, V>, E> {
         d.expect_map(token)
     }
+
+    // The map is cleared rather than replaced, to keep its capacity.
+    #[inline]
+    fn deserialize_token_into(value: &mut HashMap<K, V>,
+                              d: &mut D,
+                              token: Token) -> Result<(), E> {
+        match token {
+            MapStart(_) => { }
+            token => {
+                *value = try!(Deserializable::deserialize_token(d, token));
+                return Ok(());
+            }
+        }
+
+        try!(d.expect_map_start(token));
+        value.clear();
+
+        loop {
+            match try!(d.expect_map_elt_or_end()) {
+                Some((k, v)) => { value.insert(k, v); }
+                None => { break; }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl<
@@ -980,98 +1407,888 @@ impl<D: Deserializer<E>, E> Deserializable<D, E> for GatherTokens {
 
 //////////////////////////////////////////////////////////////////////////////
 
-#[cfg(test)]
-mod tests {
-    use std::collections::TreeMap;
-    use std::{option, string};
-    use serialize::Decoder;
+/// A buffered value, which can be looked into and then deserialized, as many
+/// times as needed, as any type. This is what internally tagged types, whose
+/// tag may come after the other fields, and untagged types, which try one
+/// type after another, are built on.
+///
+/// `Content::deserialize` replays the value through a `ContentDeserializer`,
+/// which errors through the original `Deserializer`, and accepts both the
+/// token layout of structs and enums and the object layout JSON uses for
+/// them.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Content {
+    tokens: Vec<Token>,
+}
 
-    use super::{Deserializer, Deserializable, Token, TokenKind, IgnoreTokens};
-    use super::{
-        Null,
-        Bool,
-        Int,
-        I8,
-        I16,
-        I32,
-        I64,
-        Uint,
-        U8,
-        U16,
-        U32,
-        U64,
-        F32,
-        F64,
-        Char,
-        Str,
-        String,
-        Option,
-        TupleStart,
-        StructStart,
-        EnumStart,
-        SeqStart,
-        MapStart,
-        End,
-    };
+impl Content {
+    /// Wraps the tokens of a single value.
+    #[inline]
+    pub fn from_tokens(tokens: Vec<Token>) -> Content {
+        Content { tokens: tokens }
+    }
 
-    macro_rules! treemap {
-        ($($k:expr => $v:expr),*) => ({
-            let mut _m = ::std::collections::TreeMap::new();
-            $(_m.insert($k, $v);)*
-            _m
-        })
+    #[inline]
+    pub fn tokens<'a>(&'a self) -> &'a [Token] {
+        self.tokens.as_slice()
     }
 
-    //////////////////////////////////////////////////////////////////////////////
+    #[inline]
+    pub fn into_tokens(self) -> Vec<Token> {
+        self.tokens
+    }
 
-    #[deriving(Clone, PartialEq, Show, Decodable)]
-    struct Inner {
-        a: (),
-        b: uint,
-        c: TreeMap<string::String, option::Option<char>>,
+    /// The string this holds, if it is a single string.
+    pub fn as_str<'a>(&'a self) -> option::Option<&'a str> {
+        if self.tokens.len() != 1 {
+            return None;
+        }
+
+        match self.tokens.as_slice()[0] {
+            Str(value) => Some(value),
+            String(ref value) => Some(value.as_slice()),
+            _ => None,
+        }
     }
 
-    impl<
-        D: Deserializer<E>,
-        E
-    > Deserializable<D, E> for Inner {
-        #[inline]
-        fn deserialize_token(d: &mut D, token: Token) -> Result<Inner, E> {
-            try!(d.expect_struct_start(token, "Inner"));
+    /// The value of the key or field named `key`, if this is a map or a
+    /// struct that has it.
+    pub fn get(&self, key: &str) -> option::Option<Content> {
+        let tokens = self.tokens.as_slice();
 
-            let mut a = None;
-            let mut b = None;
-            let mut c = None;
+        match tokens.head() {
+            Some(&MapStart(_)) | Some(&StructStart(_, _)) => { }
+            _ => { return None; }
+        }
 
-            static FIELDS: &'static [&'static str] = &["a", "b", "c"];
+        let mut idx = 1;
+        while idx < tokens.len() {
+            let found = match tokens[idx] {
+                End => { return None; }
+                Str(name) => name == key,
+                String(ref name) => name.as_slice() == key,
+                _ => false,
+            };
 
-            loop {
-                let idx = match try!(d.expect_struct_field_or_end(FIELDS)) {
-                    Some(idx) => idx,
-                    None => { break; }
-                };
+            let start = value_end(tokens, idx);
+            let end = value_end(tokens, start);
 
-                match idx {
-                    Some(0) => { a = Some(try!(d.expect_struct_value())); }
-                    Some(1) => { b = Some(try!(d.expect_struct_value())); }
-                    Some(2) => { c = Some(try!(d.expect_struct_value())); }
-                    Some(_) => unreachable!(),
-                    None => { let _: IgnoreTokens = try!(Deserializable::deserialize(d)); }
-                }
+            if found {
+                return Some(Content::from_tokens(tokens.slice(start, end).to_vec()));
             }
 
-            Ok(Inner { a: a.unwrap(), b: b.unwrap(), c: c.unwrap() })
+            idx = end;
         }
-    }
-
-    //////////////////////////////////////////////////////////////////////////////
 
-    #[deriving(Clone, PartialEq, Show, Decodable)]
-    struct Outer {
-        inner: Vec<Inner>,
+        None
     }
 
-    impl<D: Deserializer<E>, E> Deserializable<D, E> for Outer {
+    /// Deserializes the value as a `T`, reporting errors through `d`.
+    #[inline]
+    pub fn deserialize<
+        'a,
+        D: Deserializer<E>,
+        E,
+        T: Deserializable<ContentDeserializer<'a, D>, E>
+    >(&self, d: &'a mut D) -> Result<T, E> {
+        let mut d = ContentDeserializer::new(self.clone(), d);
+        Deserializable::deserialize(&mut d)
+    }
+}
+
+/// Returns the index just past the value that starts at `start`.
+fn value_end(tokens: &[Token], start: uint) -> uint {
+    let mut depth = 0u;
+    let mut idx = start;
+
+    while idx < tokens.len() {
+        match tokens[idx] {
+            TupleStart(_) | StructStart(_, _) | EnumStart(_, _, _) | SeqStart(_) | MapStart(_) => {
+                depth += 1;
+            }
+            End => { depth -= 1; }
+            // The value follows.
+            Option(true) => {
+                idx += 1;
+                continue;
+            }
+            _ => { }
+        }
+
+        idx += 1;
+
+        if depth == 0 {
+            break;
+        }
+    }
+
+    idx
+}
+
+impl<D: Deserializer<E>, E> Deserializable<D, E> for Content {
+    #[inline]
+    fn deserialize_token(d: &mut D, token: Token) -> Result<Content, E> {
+        let tokens: GatherTokens = try!(Deserializable::deserialize_token(d, token));
+        Ok(Content::from_tokens(tokens.unwrap()))
+    }
+}
+
+/// Replays a `Content`. Errors are made by the `Deserializer` it borrows,
+/// which also converts numbers.
+pub struct ContentDeserializer<'a, D: 'a> {
+    tokens: vec::MoveItems<Token>,
+    d: &'a mut D,
+    /// Whether each enum being read is written as a JSON object.
+    enums: Vec<bool>,
+}
+
+impl<'a, D> ContentDeserializer<'a, D> {
+    #[inline]
+    pub fn new(content: Content, d: &'a mut D) -> ContentDeserializer<'a, D> {
+        ContentDeserializer {
+            tokens: content.tokens.into_iter(),
+            d: d,
+            enums: Vec::new(),
+        }
+    }
+}
+
+impl<'a, D: Deserializer<E>, E> Iterator<Result<Token, E>> for ContentDeserializer<'a, D> {
+    #[inline]
+    fn next(&mut self) -> option::Option<Result<Token, E>> {
+        self.tokens.next().map(|token| Ok(token))
+    }
+}
+
+impl<'a, D: Deserializer<E>, E> Deserializer<E> for ContentDeserializer<'a, D> {
+    #[inline]
+    fn end_of_stream_error(&mut self) -> E {
+        self.d.end_of_stream_error()
+    }
+
+    #[inline]
+    fn syntax_error(&mut self, token: Token, expected: &[TokenKind]) -> E {
+        self.d.syntax_error(token, expected)
+    }
+
+    #[inline]
+    fn unexpected_name_error(&mut self, token: Token) -> E {
+        self.d.unexpected_name_error(token)
+    }
+
+    #[inline]
+    fn conversion_error(&mut self, token: Token) -> E {
+        self.d.conversion_error(token)
+    }
+
+    #[inline]
+    fn number_conversion_error(&mut self, token: Token, expected: TokenKind) -> E {
+        self.d.number_conversion_error(token, expected)
+    }
+
+    #[inline]
+    fn missing_field<
+        T: Deserializable<ContentDeserializer<'a, D>, E>
+    >(&mut self, field: &'static str) -> Result<T, E> {
+        // Let the original deserializer decide whether a missing field is an
+        // error, and read it as `null` if it isn't.
+        let _: IgnoreTokens = try!(self.d.missing_field(field));
+        Deserializable::deserialize_token(self, Null)
+    }
+
+    #[inline]
+    fn expect_num<T: FromNumber>(&mut self, token: Token) -> Result<T, E> {
+        self.d.expect_num(token)
+    }
+
+    #[inline]
+    fn expect_option_start(&mut self, token: Token) -> Result<OptionStart, E> {
+        match token {
+            Null | Option(false) => Ok(NoValue),
+            Option(true) => Ok(ValueNext),
+            token => Ok(ValueToken(token)),
+        }
+    }
+
+    #[inline]
+    fn expect_struct_start(&mut self, token: Token, name: &str) -> Result<(), E> {
+        match token {
+            StructStart(n, _) => {
+                if name == n {
+                    Ok(())
+                } else {
+                    Err(self.unexpected_name_error(token))
+                }
+            }
+            MapStart(_) => Ok(()),
+            _ => Err(self.syntax_error(token, [StructStartKind, MapStartKind])),
+        }
+    }
+
+    #[inline]
+    fn expect_enum_start(&mut self, token: Token, name: &str, variants: &[&str]) -> Result<uint, E> {
+        let variant = match token {
+            EnumStart(n, v, _) => {
+                if name != n {
+                    return Err(self.unexpected_name_error(token));
+                }
+                self.enums.push(false);
+                v.to_string()
+            }
+            // `{"<variant-name>": [<fields>]}`
+            MapStart(_) => {
+                let variant = match try!(self.expect_token()) {
+                    Str(variant) => variant.to_string(),
+                    String(variant) => variant,
+                    token => { return Err(self.syntax_error(token, STR_TOKEN_KINDS)); }
+                };
+
+                match try!(self.expect_token()) {
+                    TupleStart(_) | SeqStart(_) => { }
+                    token => {
+                        return Err(self.syntax_error(token, [TupleStartKind, SeqStartKind]));
+                    }
+                }
+
+                self.enums.push(true);
+                variant
+            }
+            token => { return Err(self.syntax_error(token, [EnumStartKind, MapStartKind])); }
+        };
+
+        match variants.iter().position(|v| *v == variant.as_slice()) {
+            Some(idx) => Ok(idx),
+            None => Err(self.unexpected_name_error(String(variant))),
+        }
+    }
+
+    #[inline]
+    fn expect_enum_end(&mut self) -> Result<(), E> {
+        // An enum written as an object ends both its fields and the object.
+        let ends = if self.enums.pop() == Some(true) { 2u } else { 1 };
+
+        for _ in range(0, ends) {
+            match try!(self.expect_token()) {
+                End => { }
+                token => { return Err(self.syntax_error(token, [EndKind])); }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+/// A `Deserializer` that wraps another one to read loosely typed input, such
+/// as form posts or CSV files where every value is a string. On top of what
+/// the wrapped `Deserializer` accepts:
+///
+/// * a number may be given as a string, such as `"42"` or `" 1.5 "`,
+/// * a bool may be given as `"true"`, `"false"`, `"1"`, `"0"`, `"yes"`,
+///   `"no"`, `"on"` or `"off"`, in any case,
+/// * a string may be given as a number or a bool,
+/// * an empty string reads as `None`,
+/// * a single value reads as a sequence holding just that value, and `null`
+///   as an empty sequence.
+///
+/// Everything else, including errors, is left to the wrapped `Deserializer`,
+/// so the same `Deserializable` types read both strict and sloppy input.
+pub struct LenientDeserializer<D> {
+    d: D,
+}
+
+impl<D> LenientDeserializer<D> {
+    #[inline]
+    pub fn new(d: D) -> LenientDeserializer<D> {
+        LenientDeserializer { d: d }
+    }
+
+    /// Unwrap the wrapped `Deserializer`.
+    #[inline]
+    pub fn unwrap(self) -> D {
+        self.d
+    }
+}
+
+/// Turns a string holding a number into a `Number` token. Other tokens are
+/// returned as they are.
+fn coerce_number<D: Deserializer<E>, E>(d: &mut D, token: Token) -> Result<Token, E> {
+    let number = match token {
+        Str(value) => number_token(value),
+        String(ref value) => number_token(value.as_slice()),
+        token => { return Ok(token); }
+    };
+
+    match number {
+        Some(number) => Ok(number),
+        None => Err(d.syntax_error(token, PRIMITIVE_TOKEN_KINDS)),
+    }
+}
+
+// `from_str` also accepts `NaN`, `inf` and numbers too large for a float,
+// none of which are numbers a string should stand for.
+fn number_token(value: &str) -> option::Option<Token> {
+    let value = value.trim();
+    match from_str::<f64>(value) {
+        Some(n) if n.is_finite() => Some(Number(value.to_string())),
+        _ => None,
+    }
+}
+
+fn bool_from_str(value: &str) -> option::Option<bool> {
+    let value = value.trim();
+    if ["true", "1", "yes", "on"].iter().any(|s| value.eq_ignore_ascii_case(*s)) {
+        Some(true)
+    } else if ["false", "0", "no", "off"].iter().any(|s| value.eq_ignore_ascii_case(*s)) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+impl<D: Deserializer<E>, E> Iterator<Result<Token, E>> for LenientDeserializer<D> {
+    #[inline]
+    fn next(&mut self) -> option::Option<Result<Token, E>> {
+        self.d.next()
+    }
+}
+
+impl<D: Deserializer<E>, E> Deserializer<E> for LenientDeserializer<D> {
+    #[inline]
+    fn end_of_stream_error(&mut self) -> E {
+        self.d.end_of_stream_error()
+    }
+
+    #[inline]
+    fn syntax_error(&mut self, token: Token, expected: &[TokenKind]) -> E {
+        self.d.syntax_error(token, expected)
+    }
+
+    #[inline]
+    fn unexpected_name_error(&mut self, token: Token) -> E {
+        self.d.unexpected_name_error(token)
+    }
+
+    #[inline]
+    fn conversion_error(&mut self, token: Token) -> E {
+        self.d.conversion_error(token)
+    }
+
+    #[inline]
+    fn number_conversion_error(&mut self, token: Token, expected: TokenKind) -> E {
+        self.d.number_conversion_error(token, expected)
+    }
+
+    #[inline]
+    fn missing_field<
+        T: Deserializable<LenientDeserializer<D>, E>
+    >(&mut self, field: &'static str) -> Result<T, E> {
+        // Let the wrapped deserializer decide whether a missing field is an
+        // error, and read it as `null` if it isn't.
+        let _: IgnoreTokens = try!(self.d.missing_field(field));
+        Deserializable::deserialize_token(self, Null)
+    }
+
+    #[inline]
+    fn ignore_field(&mut self, token: Token) -> Result<(), E> {
+        self.d.ignore_field(token)
+    }
+
+    #[inline]
+    fn token_start(&self) -> option::Option<Position> {
+        self.d.token_start()
+    }
+
+    #[inline]
+    fn token_end(&self) -> option::Option<Position> {
+        self.d.token_end()
+    }
+
+    #[inline]
+    fn expect_token(&mut self) -> Result<Token, E> {
+        self.d.expect_token()
+    }
+
+    #[inline]
+    fn expect_null(&mut self, token: Token) -> Result<(), E> {
+        self.d.expect_null(token)
+    }
+
+    #[inline]
+    fn expect_bool(&mut self, token: Token) -> Result<bool, E> {
+        let value = match token {
+            Str(value) => bool_from_str(value),
+            String(ref value) => bool_from_str(value.as_slice()),
+            token => { return self.d.expect_bool(token); }
+        };
+
+        match value {
+            Some(value) => Ok(value),
+            None => Err(self.d.syntax_error(token, [BoolKind])),
+        }
+    }
+
+    #[inline]
+    fn expect_num<T: FromNumber>(&mut self, token: Token) -> Result<T, E> {
+        let token = try!(coerce_number(&mut self.d, token));
+        self.d.expect_num(token)
+    }
+
+    #[inline]
+    fn expect_from_primitive<T: FromPrimitive>(&mut self, token: Token) -> Result<T, E> {
+        let token = try!(coerce_number(&mut self.d, token));
+        self.d.expect_from_primitive(token)
+    }
+
+    #[inline]
+    fn expect_char(&mut self, token: Token) -> Result<char, E> {
+        self.d.expect_char(token)
+    }
+
+    #[inline]
+    fn expect_str(&mut self, token: Token) -> Result<&'static str, E> {
+        self.d.expect_str(token)
+    }
+
+    #[inline]
+    fn expect_string(&mut self, token: Token) -> Result<string::String, E> {
+        match token {
+            Bool(value) => Ok(value.to_string()),
+            Int(value) => Ok(value.to_string()),
+            I8(value) => Ok(value.to_string()),
+            I16(value) => Ok(value.to_string()),
+            I32(value) => Ok(value.to_string()),
+            I64(value) => Ok(value.to_string()),
+            Uint(value) => Ok(value.to_string()),
+            U8(value) => Ok(value.to_string()),
+            U16(value) => Ok(value.to_string()),
+            U32(value) => Ok(value.to_string()),
+            U64(value) => Ok(value.to_string()),
+            F32(value) => Ok(value.to_string()),
+            F64(value) => Ok(value.to_string()),
+            Number(value) => Ok(value),
+            token => self.d.expect_string(token),
+        }
+    }
+
+    #[inline]
+    fn expect_option_start(&mut self, token: Token) -> Result<OptionStart, E> {
+        match token {
+            Null | Option(false) => Ok(NoValue),
+            Str(value) if value.trim().is_empty() => Ok(NoValue),
+            String(ref value) if value.as_slice().trim().is_empty() => Ok(NoValue),
+            Option(true) => Ok(ValueNext),
+            token => Ok(ValueToken(token)),
+        }
+    }
+
+    #[inline]
+    fn expect_tuple_start(&mut self, token: Token) -> Result<uint, E> {
+        self.d.expect_tuple_start(token)
+    }
+
+    #[inline]
+    fn expect_tuple_end(&mut self) -> Result<(), E> {
+        self.d.expect_tuple_end()
+    }
+
+    #[inline]
+    fn expect_struct_start(&mut self, token: Token, name: &str) -> Result<(), E> {
+        self.d.expect_struct_start(token, name)
+    }
+
+    #[inline]
+    fn expect_struct_field_or_end(&mut self,
+                                  fields: &'static [&'static str]
+                                 ) -> Result<option::Option<option::Option<uint>>, E> {
+        self.d.expect_struct_field_or_end(fields)
+    }
+
+    #[inline]
+    fn expect_struct_end(&mut self) -> Result<(), E> {
+        self.d.expect_struct_end()
+    }
+
+    #[inline]
+    fn expect_enum_start(&mut self, token: Token, name: &str, variants: &[&str]) -> Result<uint, E> {
+        self.d.expect_enum_start(token, name, variants)
+    }
+
+    #[inline]
+    fn expect_enum_end(&mut self) -> Result<(), E> {
+        self.d.expect_enum_end()
+    }
+
+    #[inline]
+    fn expect_seq_start(&mut self, token: Token) -> Result<uint, E> {
+        self.d.expect_seq_start(token)
+    }
+
+    #[inline]
+    fn expect_seq_shape(&mut self, token: Token) -> Result<SeqShape, E> {
+        match token {
+            TupleStart(_) | SeqStart(_) => Ok(SeqElts(try!(self.d.expect_seq_start(token)))),
+            Null => Ok(SeqEmpty),
+            token => Ok(SeqOne(token)),
+        }
+    }
+
+    #[inline]
+    fn expect_map_start(&mut self, token: Token) -> Result<uint, E> {
+        self.d.expect_map_start(token)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+/// Deserializes a `T` with the help of runtime context held by the seed, such
+/// as a string interner, an arena or a schema that picks how to decode a
+/// value. It is the stateful counterpart of `Deserializable`.
+///
+/// `Forward` passes a seed on to the elements of the built-in collections.
+pub trait DeserializeSeed<D: Deserializer<E>, E, T> {
+    #[inline]
+    fn deserialize(&mut self, d: &mut D) -> Result<T, E> {
+        let token = try!(d.expect_token());
+        self.deserialize_token(d, token)
+    }
+
+    fn deserialize_token(&mut self, d: &mut D, token: Token) -> Result<T, E>;
+}
+
+impl<
+    'a,
+    D: Deserializer<E>,
+    E,
+    T,
+    S: DeserializeSeed<D, E, T>
+> DeserializeSeed<D, E, T> for &'a mut S {
+    #[inline]
+    fn deserialize_token(&mut self, d: &mut D, token: Token) -> Result<T, E> {
+        (**self).deserialize_token(d, token)
+    }
+}
+
+/// A seed for an `Option`, `Box`, `Vec`, `TreeSet`, `HashSet`, `TreeMap` or
+/// `HashMap` that passes the seed it wraps on to each of the elements. Map
+/// keys are deserialized without it. Options and sequences start through
+/// `expect_option_start` and `expect_seq_shape`, so they accept the same
+/// tokens as the `Deserializable` impls do.
+pub struct Forward<S>(pub S);
+
+impl<
+    D: Deserializer<E>,
+    E,
+    T,
+    S: DeserializeSeed<D, E, T>
+> DeserializeSeed<D, E, option::Option<T>> for Forward<S> {
+    #[inline]
+    fn deserialize_token(&mut self, d: &mut D, token: Token) -> Result<option::Option<T>, E> {
+        let Forward(ref mut seed) = *self;
+        match try!(d.expect_option_start(token)) {
+            NoValue => Ok(None),
+            ValueNext => Ok(Some(try!(seed.deserialize(d)))),
+            ValueToken(token) => Ok(Some(try!(seed.deserialize_token(d, token)))),
+        }
+    }
+}
+
+impl<
+    D: Deserializer<E>,
+    E,
+    T,
+    S: DeserializeSeed<D, E, T>
+> DeserializeSeed<D, E, Box<T>> for Forward<S> {
+    #[inline]
+    fn deserialize_token(&mut self, d: &mut D, token: Token) -> Result<Box<T>, E> {
+        let Forward(ref mut seed) = *self;
+        Ok(box try!(seed.deserialize_token(d, token)))
+    }
+}
+
+struct SeqSeedDeserializer<'a, D: 'a, E, S: 'a> {
+    d: &'a mut D,
+    seed: &'a mut S,
+    len: uint,
+    err: option::Option<E>,
+}
+
+impl<
+    'a,
+    D: Deserializer<E>,
+    E,
+    T,
+    S: DeserializeSeed<D, E, T>
+> Iterator<T> for SeqSeedDeserializer<'a, D, E, S> {
+    #[inline]
+    fn next(&mut self) -> option::Option<T> {
+        let result = match self.d.expect_token() {
+            Ok(End) => { return None; }
+            Ok(token) => self.seed.deserialize_token(self.d, token),
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.err = Some(err);
+                None
+            }
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (uint, option::Option<uint>) {
+        (self.len, Some(self.len))
+    }
+}
+
+fn deserialize_seq_seed<
+    D: Deserializer<E>,
+    E,
+    T,
+    S: DeserializeSeed<D, E, T>,
+    C: FromIterator<T>
+>(seed: &mut S, d: &mut D, token: Token) -> Result<C, E> {
+    let len = match try!(d.expect_seq_shape(token)) {
+        SeqElts(len) => len,
+        SeqOne(token) => {
+            let value = try!(seed.deserialize_token(d, token));
+            return Ok(FromIterator::from_iter(Some(value).into_iter()));
+        }
+        SeqEmpty => {
+            return Ok(FromIterator::from_iter(None::<T>.into_iter()));
+        }
+    };
+
+    let mut d = SeqSeedDeserializer {
+        d: d,
+        seed: seed,
+        len: len,
+        err: None,
+    };
+
+    let collection: C = d.collect();
+
+    match d.err {
+        Some(err) => Err(err),
+        None => Ok(collection),
+    }
+}
+
+struct MapSeedDeserializer<'a, D: 'a, E, S: 'a> {
+    d: &'a mut D,
+    seed: &'a mut S,
+    len: uint,
+    err: option::Option<E>,
+}
+
+impl<
+    'a,
+    D: Deserializer<E>,
+    E,
+    K: Deserializable<D, E>,
+    V,
+    S: DeserializeSeed<D, E, V>
+> Iterator<(K, V)> for MapSeedDeserializer<'a, D, E, S> {
+    #[inline]
+    fn next(&mut self) -> option::Option<(K, V)> {
+        let key = match self.d.expect_token() {
+            Ok(End) => { return None; }
+            Ok(token) => Deserializable::deserialize_token(self.d, token),
+            Err(err) => Err(err),
+        };
+
+        let result = match key {
+            Ok(key) => {
+                match self.seed.deserialize(self.d) {
+                    Ok(value) => Ok((key, value)),
+                    Err(err) => Err(err),
+                }
+            }
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                self.err = Some(err);
+                None
+            }
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (uint, option::Option<uint>) {
+        (self.len, Some(self.len))
+    }
+}
+
+fn deserialize_map_seed<
+    D: Deserializer<E>,
+    E,
+    K: Deserializable<D, E>,
+    V,
+    S: DeserializeSeed<D, E, V>,
+    C: FromIterator<(K, V)>
+>(seed: &mut S, d: &mut D, token: Token) -> Result<C, E> {
+    let len = try!(d.expect_map_start(token));
+
+    let mut d = MapSeedDeserializer {
+        d: d,
+        seed: seed,
+        len: len,
+        err: None,
+    };
+
+    let collection: C = d.collect();
+
+    match d.err {
+        Some(err) => Err(err),
+        None => Ok(collection),
+    }
+}
+
+macro_rules! impl_seq_seed {
+    ($ty:ident $($bounds:tt)*) => {
+        impl<
+            D: Deserializer<E>,
+            E,
+            T $($bounds)*,
+            S: DeserializeSeed<D, E, T>
+        > DeserializeSeed<D, E, $ty<T>> for Forward<S> {
+            #[inline]
+            fn deserialize_token(&mut self, d: &mut D, token: Token) -> Result<$ty<T>, E> {
+                let Forward(ref mut seed) = *self;
+                deserialize_seq_seed(seed, d, token)
+            }
+        }
+    }
+}
+
+impl_seq_seed!(Vec)
+impl_seq_seed!(TreeSet: Ord)
+impl_seq_seed!(HashSet: Eq + Hash)
+
+macro_rules! impl_map_seed {
+    ($ty:ident, $($bound:ident),*) => {
+        impl<
+            D: Deserializer<E>,
+            E,
+            K: Deserializable<D, E> $(+ $bound)*,
+            V,
+            S: DeserializeSeed<D, E, V>
+        > DeserializeSeed<D, E, $ty<K, V>> for Forward<S> {
+            #[inline]
+            fn deserialize_token(&mut self, d: &mut D, token: Token) -> Result<$ty<K, V>, E> {
+                let Forward(ref mut seed) = *self;
+                deserialize_map_seed(seed, d, token)
+            }
+        }
+    }
+}
+
+impl_map_seed!(TreeMap, Ord)
+impl_map_seed!(HashMap, Eq, Hash)
+
+//////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, TreeMap};
+    use std::{f64, i64, option, string, vec};
+    use serialize::Decoder;
+
+    use super::{Deserializer, Deserializable, Token, TokenKind, IgnoreTokens};
+    use super::{LenientDeserializer, DeserializeSeed, Forward, Content};
+    use json;
+    use super::{
+        Null,
+        Bool,
+        Int,
+        I8,
+        I16,
+        I32,
+        I64,
+        Uint,
+        U8,
+        U16,
+        U32,
+        U64,
+        F32,
+        F64,
+        Number,
+        Char,
+        Str,
+        String,
+        Option,
+        TupleStart,
+        StructStart,
+        EnumStart,
+        SeqStart,
+        MapStart,
+        End,
+    };
+
+    macro_rules! treemap {
+        ($($k:expr => $v:expr),*) => ({
+            let mut _m = ::std::collections::TreeMap::new();
+            $(_m.insert($k, $v);)*
+            _m
+        })
+    }
+
+    //////////////////////////////////////////////////////////////////////////////
+
+    #[deriving(Clone, PartialEq, Show, Decodable)]
+    struct Inner {
+        a: (),
+        b: uint,
+        c: TreeMap<string::String, option::Option<char>>,
+    }
+
+    impl<
+        D: Deserializer<E>,
+        E
+    > Deserializable<D, E> for Inner {
+        #[inline]
+        fn deserialize_token(d: &mut D, token: Token) -> Result<Inner, E> {
+            try!(d.expect_struct_start(token, "Inner"));
+
+            let mut a = None;
+            let mut b = None;
+            let mut c = None;
+
+            static FIELDS: &'static [&'static str] = &["a", "b", "c"];
+
+            loop {
+                let idx = match try!(d.expect_struct_field_or_end(FIELDS)) {
+                    Some(idx) => idx,
+                    None => { break; }
+                };
+
+                match idx {
+                    Some(0) => { a = Some(try!(d.expect_struct_value())); }
+                    Some(1) => { b = Some(try!(d.expect_struct_value())); }
+                    Some(2) => { c = Some(try!(d.expect_struct_value())); }
+                    Some(_) => unreachable!(),
+                    None => { let _: IgnoreTokens = try!(Deserializable::deserialize(d)); }
+                }
+            }
+
+            Ok(Inner { a: a.unwrap(), b: b.unwrap(), c: c.unwrap() })
+        }
+    }
+
+    //////////////////////////////////////////////////////////////////////////////
+
+    #[deriving(Clone, PartialEq, Show, Decodable)]
+    struct Outer {
+        inner: Vec<Inner>,
+    }
+
+    impl<D: Deserializer<E>, E> Deserializable<D, E> for Outer {
         #[inline]
         fn deserialize_token(d: &mut D, token: Token) -> Result<Outer, E> {
             try!(d.expect_struct_start(token, "Outer"));
@@ -1217,11 +2434,56 @@ mod tests {
         vec!(U64(5)) => 5: u64,
         vec!(F32(5.0)) => 5.0: f32,
         vec!(F64(5.0)) => 5.0: f64,
+        vec!(Number("5".to_string())) => 5: i32,
+        vec!(Number("18446744073709551615".to_string())) => 18446744073709551615: u64,
+        vec!(Number("5.5".to_string())) => 5.5: f64,
+        vec!(F64(5.0)) => 5: int,
+        vec!(Number("5e2".to_string())) => 500: u16,
         vec!(Char('c')) => 'c': char,
         vec!(Str("abc")) => "abc": &str,
         vec!(String("abc".to_string())) => "abc".to_string(): string::String
     ])
 
+    #[test]
+    fn test_inexact_numbers() {
+        let tokens = vec![F64(3.5), I64(300), I32(-1), Number("2.5".to_string())];
+        for token in tokens.into_iter() {
+            let mut deserializer = TokenDeserializer::new(vec![token].into_iter());
+            let value: Result<u8, Error> = Deserializable::deserialize(&mut deserializer);
+            match value {
+                Err(ConversionError) => { }
+                value => panic!("unexpected {}", value),
+            }
+        }
+    }
+
+    #[test]
+    fn test_float_bounds() {
+        fn convert<
+            T: Deserializable<TokenDeserializer<vec::MoveItems<Token>>, Error>
+        >(value: f64) -> option::Option<T> {
+            let mut deserializer = TokenDeserializer::new(vec![F64(value)].into_iter());
+            Deserializable::deserialize(&mut deserializer).ok()
+        }
+
+        assert_eq!(convert::<i64>(-9223372036854775808.0), Some(i64::MIN));
+        assert_eq!(convert::<i64>(9223372036854775808.0), None);
+        assert_eq!(convert::<i64>(9223372036854774784.0), Some(9223372036854774784));
+        assert_eq!(convert::<i64>(-9223372036854777856.0), None);
+        assert_eq!(convert::<u64>(9223372036854775808.0), Some(9223372036854775808));
+        assert_eq!(convert::<u64>(18446744073709551616.0), None);
+        assert_eq!(convert::<u64>(18446744073709549568.0), Some(18446744073709549568));
+        assert_eq!(convert::<u64>(-1.0), None);
+        assert_eq!(convert::<u64>(-0.0), Some(0));
+        assert_eq!(convert::<u8>(255.0), Some(255));
+        assert_eq!(convert::<u8>(256.0), None);
+        assert_eq!(convert::<i8>(-128.0), Some(-128));
+        assert_eq!(convert::<i8>(-129.0), None);
+        assert_eq!(convert::<i32>(1e300), None);
+        assert_eq!(convert::<i32>(f64::INFINITY), None);
+        assert_eq!(convert::<i32>(f64::NAN), None);
+    }
+
     test_value!(test_tuples, [
         vec!(
             TupleStart(0),
@@ -1373,4 +2635,274 @@ mod tests {
         ) => treemap!(5i => "a".to_string(), 6i => "b".to_string()): TreeMap<int, string::
         String>
     ])
+
+    #[test]
+    fn test_lenient() {
+        fn lenient<
+            T: Deserializable<LenientDeserializer<TokenDeserializer<vec::MoveItems<Token>>>, Error>
+        >(tokens: Vec<Token>) -> Result<T, Error> {
+            let mut deserializer = LenientDeserializer::new(
+                TokenDeserializer::new(tokens.into_iter()));
+            Deserializable::deserialize(&mut deserializer)
+        }
+
+        assert_eq!(lenient::<u8>(vec![Str("42")]).unwrap(), 42);
+        assert_eq!(lenient::<f64>(vec![String(" 1.5 ".to_string())]).unwrap(), 1.5);
+        assert_eq!(lenient::<int>(vec![Int(7)]).unwrap(), 7);
+        assert!(lenient::<int>(vec![Str("4x")]).is_err());
+        assert!(lenient::<u8>(vec![Str("2.5")]).is_err());
+        assert!(lenient::<f64>(vec![Str("NaN")]).is_err());
+        assert!(lenient::<f64>(vec![Str("inf")]).is_err());
+        assert!(lenient::<f64>(vec![Str("-inf")]).is_err());
+        assert!(lenient::<f64>(vec![Str("1e400")]).is_err());
+        assert!(lenient::<option::Option<f64>>(vec![Str("NaN")]).is_err());
+
+        assert_eq!(lenient::<bool>(vec![Str("ON")]).unwrap(), true);
+        assert_eq!(lenient::<bool>(vec![String("0".to_string())]).unwrap(), false);
+        assert!(lenient::<bool>(vec![Str("maybe")]).is_err());
+
+        assert_eq!(lenient::<string::String>(vec![I64(-3)]).unwrap(), "-3".to_string());
+        assert_eq!(lenient::<string::String>(vec![Bool(true)]).unwrap(), "true".to_string());
+
+        assert_eq!(lenient::<option::Option<int>>(vec![Str(" ")]).unwrap(), None);
+        assert_eq!(lenient::<option::Option<int>>(vec![Null]).unwrap(), None);
+        assert_eq!(lenient::<option::Option<int>>(vec![Str("5")]).unwrap(), Some(5));
+        assert_eq!(lenient::<option::Option<int>>(vec![Option(true), Str("5")]).unwrap(),
+                   Some(5));
+
+        assert_eq!(lenient::<Vec<int>>(vec![Str("5")]).unwrap(), vec![5]);
+        assert_eq!(lenient::<Vec<int>>(vec![Null]).unwrap(), vec![]);
+        assert_eq!(lenient::<Vec<int>>(vec![SeqStart(2), Str("1"), Int(2), End]).unwrap(),
+                   vec![1, 2]);
+    }
+
+    struct Interner {
+        names: Vec<string::String>,
+    }
+
+    impl<D: Deserializer<E>, E> DeserializeSeed<D, E, uint> for Interner {
+        fn deserialize_token(&mut self, d: &mut D, token: Token) -> Result<uint, E> {
+            let name = try!(d.expect_string(token));
+            match self.names.iter().position(|n| *n == name) {
+                Some(idx) => Ok(idx),
+                None => {
+                    self.names.push(name);
+                    Ok(self.names.len() - 1)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_seed() {
+        let mut interner = Interner { names: Vec::new() };
+
+        let tokens = vec![SeqStart(3), Str("a"), Str("b"), Str("a"), End];
+        let mut d = TokenDeserializer::new(tokens.into_iter());
+        let value: Vec<uint> = Forward(&mut interner).deserialize(&mut d).unwrap();
+        assert_eq!(value, vec![0, 1, 0]);
+
+        let tokens = vec![
+            MapStart(2),
+                Int(1),
+                Option(true),
+                Str("c"),
+
+                Int(2),
+                Option(false),
+            End,
+        ];
+        let mut d = TokenDeserializer::new(tokens.into_iter());
+        let value: TreeMap<int, option::Option<uint>> =
+            Forward(Forward(&mut interner)).deserialize(&mut d).unwrap();
+        assert_eq!(value, treemap!(1i => Some(2u), 2i => None));
+
+        let tokens = vec![SeqStart(1), Int(5), End];
+        let mut d = TokenDeserializer::new(tokens.into_iter());
+        let value: Result<Vec<uint>, Error> = Forward(&mut interner).deserialize(&mut d);
+        assert!(value.is_err());
+
+        let mut parser = json::Parser::new("[\"b\", null, \"d\"]".bytes());
+        let value: Vec<option::Option<uint>> =
+            Forward(Forward(&mut interner)).deserialize(&mut parser).unwrap();
+        assert_eq!(value, vec![Some(1), None, Some(3)]);
+
+        let tokens = vec![SeqStart(3), Str(" "), Str("c"), Null, End];
+        let mut d = LenientDeserializer::new(TokenDeserializer::new(tokens.into_iter()));
+        let value: Vec<option::Option<uint>> =
+            Forward(Forward(&mut interner)).deserialize(&mut d).unwrap();
+        assert_eq!(value, vec![None, Some(2), None]);
+
+        let mut d = LenientDeserializer::new(TokenDeserializer::new(vec![Str("d")].into_iter()));
+        let value: Vec<uint> = Forward(&mut interner).deserialize(&mut d).unwrap();
+        assert_eq!(value, vec![3]);
+
+        let mut d = LenientDeserializer::new(TokenDeserializer::new(vec![Null].into_iter()));
+        let value: Vec<uint> = Forward(&mut interner).deserialize(&mut d).unwrap();
+        assert_eq!(value, vec![]);
+
+        assert_eq!(interner.names, vec!["a".to_string(), "b".to_string(),
+                                        "c".to_string(), "d".to_string()]);
+    }
+
+    #[test]
+    fn test_deserialize_into() {
+        fn tokens(tokens: Vec<Token>) -> TokenDeserializer<vec::MoveItems<Token>> {
+            TokenDeserializer::new(tokens.into_iter())
+        }
+
+        let mut value: Vec<string::String> = Vec::with_capacity(8);
+        value.push(string::String::with_capacity(16));
+        value.push("b".to_string());
+        value.push("c".to_string());
+
+        value.deserialize_into(&mut tokens(vec![SeqStart(2), Str("x"), Str("y"), End])).unwrap();
+        assert_eq!(value, vec!["x".to_string(), "y".to_string()]);
+        assert!(value.capacity() >= 8);
+        assert!(value.as_slice()[0].capacity() >= 16);
+
+        let mut d = tokens(vec![SeqStart(3), Str("x"), Str("y"), Str("z"), End]);
+        value.deserialize_into(&mut d).unwrap();
+        assert_eq!(value, vec!["x".to_string(), "y".to_string(), "z".to_string()]);
+
+        let mut value: HashMap<int, int> = HashMap::with_capacity(64);
+        value.insert(1, 1);
+        let capacity = value.capacity();
+        value.deserialize_into(&mut tokens(vec![MapStart(1), Int(2), Int(4), End])).unwrap();
+        assert_eq!(value.len(), 1);
+        assert_eq!(value.find(&2), Some(&4));
+        assert_eq!(value.capacity(), capacity);
+
+        let mut value = box 5i;
+        value.deserialize_into(&mut tokens(vec![Int(6)])).unwrap();
+        assert_eq!(*value, 6);
+
+        let mut value = vec![1i];
+        let result = value.deserialize_into(&mut tokens(vec![SeqStart(1), Str("a"), End]));
+        assert!(result.is_err());
+    }
+
+    #[deriving(PartialEq, Show)]
+    #[deriving_deserializable]
+    struct Circle {
+        radius: int,
+    }
+
+    #[deriving(PartialEq, Show)]
+    #[deriving_deserializable]
+    struct Square {
+        side: int,
+    }
+
+    #[deriving(PartialEq, Show)]
+    enum Shape {
+        CircleShape(Circle),
+        SquareShape(Square),
+    }
+
+    // Tagged by a `type` field, which may come anywhere in the struct.
+    impl<D: Deserializer<E>, E> Deserializable<D, E> for Shape {
+        fn deserialize_token(d: &mut D, token: Token) -> Result<Shape, E> {
+            let content: Content = try!(Deserializable::deserialize_token(d, token));
+            let tag = content.get("type");
+            match tag.as_ref().and_then(|tag| tag.as_str()) {
+                Some("circle") => Ok(CircleShape(try!(content.deserialize(d)))),
+                Some("square") => Ok(SquareShape(try!(content.deserialize(d)))),
+                _ => Err(d.unexpected_name_error(Str("type"))),
+            }
+        }
+    }
+
+    #[deriving(PartialEq, Show)]
+    enum Id {
+        NumId(u64),
+        NameId(string::String),
+    }
+
+    // Untagged, so each variant is tried in turn.
+    impl<D: Deserializer<E>, E> Deserializable<D, E> for Id {
+        fn deserialize_token(d: &mut D, token: Token) -> Result<Id, E> {
+            let content: Content = try!(Deserializable::deserialize_token(d, token));
+            match content.deserialize(d) {
+                Ok(id) => Ok(NumId(id)),
+                Err(_) => Ok(NameId(try!(content.deserialize(d)))),
+            }
+        }
+    }
+
+    #[test]
+    fn test_content() {
+        let content = Content::from_tokens(vec![
+            MapStart(3),
+                Str("a"),
+                SeqStart(1),
+                    Int(1),
+                End,
+
+                Str("b"),
+                Option(true),
+                Int(2),
+
+                String("c".to_string()),
+                Str("x"),
+            End,
+        ]);
+        assert_eq!(content.get("b"), Some(Content::from_tokens(vec![Option(true), Int(2)])));
+        assert_eq!(content.get("c").unwrap().as_str(), Some("x"));
+        assert_eq!(content.get("d"), None);
+        assert_eq!(content.get("a").unwrap().get("a"), None);
+
+        let tokens = vec![
+            MapStart(2),
+                Str("radius"),
+                Int(2),
+
+                Str("type"),
+                Str("circle"),
+            End,
+        ];
+        let mut d = TokenDeserializer::new(tokens.into_iter());
+        let shape: Shape = Deserializable::deserialize(&mut d).unwrap();
+        assert_eq!(shape, CircleShape(Circle { radius: 2 }));
+
+        let tokens = vec![
+            StructStart("Square", 2),
+                Str("type"),
+                Str("square"),
+
+                Str("side"),
+                Int(3),
+            End,
+        ];
+        let mut d = TokenDeserializer::new(tokens.into_iter());
+        let shape: Shape = Deserializable::deserialize(&mut d).unwrap();
+        assert_eq!(shape, SquareShape(Square { side: 3 }));
+
+        let mut d = TokenDeserializer::new(vec![SeqStart(2), U64(5), Str("x"), End].into_iter());
+        let ids: Vec<Id> = Deserializable::deserialize(&mut d).unwrap();
+        assert_eq!(ids, vec![NumId(5), NameId("x".to_string())]);
+    }
+
+    #[test]
+    fn test_content_json() {
+        let shapes: Vec<Shape> = json::from_str(
+            "[{\"radius\": 1, \"type\": \"circle\"}, {\"type\": \"square\", \"side\": 2}]"
+        ).unwrap();
+        assert_eq!(shapes, vec![
+            CircleShape(Circle { radius: 1 }),
+            SquareShape(Square { side: 2 }),
+        ]);
+
+        assert!(json::from_str::<Shape>("{\"radius\": 1, \"type\": \"oval\"}").is_err());
+        assert!(json::from_str::<Shape>("{\"radius\": 1.5, \"type\": \"circle\"}").is_err());
+
+        let ids: Vec<Id> = json::from_str("[7, \"seven\"]").unwrap();
+        assert_eq!(ids, vec![NumId(7), NameId("seven".to_string())]);
+
+        // Enums are replayed from their JSON layout too.
+        let content: Content = json::from_str("{\"Frog\": [\"Henry\", 349]}").unwrap();
+        let mut parser = json::Parser::new("".bytes());
+        let animal: Result<Animal, json::ParserError> = content.deserialize(&mut parser);
+        assert_eq!(animal, Ok(Frog("Henry".to_string(), 349)));
+    }
 }
@@ -0,0 +1,159 @@
+// Copyright 2012-2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Just enough of an arbitrary precision unsigned integer for exact float
+//! formatting and parsing.
+
+/// An unsigned big integer. The digits are base 2^32, least significant
+/// first, with no trailing zeros.
+#[deriving(Clone, PartialEq, Eq)]
+pub struct Big {
+    digits: Vec<u32>,
+}
+
+impl Big {
+    pub fn from_u64(v: u64) -> Big {
+        let mut big = Big { digits: vec![v as u32, (v >> 32) as u32] };
+        big.trim();
+        big
+    }
+
+    fn trim(&mut self) {
+        while self.digits.last() == Some(&0) {
+            self.digits.pop();
+        }
+    }
+
+    pub fn mul_small(&mut self, m: u32) {
+        let mut carry = 0u64;
+        for digit in self.digits.iter_mut() {
+            let v = (*digit as u64) * (m as u64) + carry;
+            *digit = v as u32;
+            carry = v >> 32;
+        }
+        if carry > 0 {
+            self.digits.push(carry as u32);
+        }
+    }
+
+    pub fn add_small(&mut self, a: u32) {
+        let mut carry = a as u64;
+        for digit in self.digits.iter_mut() {
+            if carry == 0 {
+                break;
+            }
+            let v = (*digit as u64) + carry;
+            *digit = v as u32;
+            carry = v >> 32;
+        }
+        if carry > 0 {
+            self.digits.push(carry as u32);
+        }
+    }
+
+    pub fn mul_pow2(&mut self, n: uint) {
+        let bits = n % 32;
+        if bits > 0 {
+            let mut carry = 0u32;
+            for digit in self.digits.iter_mut() {
+                let v = *digit;
+                *digit = (v << bits) | carry;
+                carry = v >> (32 - bits);
+            }
+            if carry > 0 {
+                self.digits.push(carry);
+            }
+        }
+
+        let words = n / 32;
+        if words > 0 && !self.digits.is_empty() {
+            let mut digits = Vec::from_elem(words, 0u32);
+            digits.push_all(self.digits.as_slice());
+            self.digits = digits;
+        }
+    }
+
+    pub fn mul_pow10(&mut self, mut n: uint) {
+        while n >= 9 {
+            self.mul_small(1000000000);
+            n -= 9;
+        }
+
+        let mut m = 1u32;
+        while n > 0 {
+            m *= 10;
+            n -= 1;
+        }
+        self.mul_small(m);
+    }
+
+    pub fn add(&self, other: &Big) -> Big {
+        let len = if self.digits.len() > other.digits.len() {
+            self.digits.len()
+        } else {
+            other.digits.len()
+        };
+
+        let mut digits = Vec::with_capacity(len + 1);
+        let mut carry = 0u64;
+        for i in range(0, len) {
+            let a = if i < self.digits.len() { self.digits[i] as u64 } else { 0 };
+            let b = if i < other.digits.len() { other.digits[i] as u64 } else { 0 };
+            let v = a + b + carry;
+            digits.push(v as u32);
+            carry = v >> 32;
+        }
+        if carry > 0 {
+            digits.push(carry as u32);
+        }
+
+        Big { digits: digits }
+    }
+
+    /// Subtracts `other`, which must not be larger than `self`.
+    pub fn sub(&mut self, other: &Big) {
+        let mut borrow = 0i64;
+        for i in range(0, self.digits.len()) {
+            let b = if i < other.digits.len() { other.digits[i] as i64 } else { 0 };
+            let mut v = self.digits[i] as i64 - b - borrow;
+            if v < 0 {
+                v += 1i64 << 32;
+                borrow = 1;
+            } else {
+                borrow = 0;
+            }
+            *self.digits.get_mut(i) = v as u32;
+        }
+        debug_assert_eq!(borrow, 0);
+        self.trim();
+    }
+}
+
+impl PartialOrd for Big {
+    fn partial_cmp(&self, other: &Big) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Big {
+    fn cmp(&self, other: &Big) -> Ordering {
+        if self.digits.len() != other.digits.len() {
+            return self.digits.len().cmp(&other.digits.len());
+        }
+
+        for (a, b) in self.digits.iter().rev().zip(other.digits.iter().rev()) {
+            if a != b {
+                return a.cmp(b);
+            }
+        }
+
+        Equal
+    }
+}
@@ -76,7 +76,7 @@ impl ObjectBuilder {
 #[cfg(test)]
 mod tests {
     use std::collections::TreeMap;
-    use json::{List, Integer, Object};
+    use json::{List, Integer, Null, Boolean, Object};
     use super::{ListBuilder, ObjectBuilder};
 
     #[test]
@@ -124,4 +124,31 @@ mod tests {
         map.insert("b".to_string(), Integer(2));
         assert_eq!(value, Object(map));
     }
+
+    #[test]
+    fn test_json_macro() {
+        assert_eq!(json!(null), Null);
+        assert_eq!(json!([]), List(Vec::new()));
+        assert_eq!(json!({}), Object(TreeMap::new()));
+
+        let x = 3i;
+        let value = json!({
+            "a": 1i,
+            "b": [true, null, x, (-x)],
+            "c": { "d": [] },
+        });
+
+        let expected = ObjectBuilder::new()
+            .insert("a".to_string(), 1i)
+            .insert_list("b", |bld| bld.push(true).push(Null).push(3i).push(-3i))
+            .insert_object("c", |bld| bld.insert_list("d", |bld| bld))
+            .unwrap();
+        assert_eq!(value, expected);
+
+        assert_eq!(json!([1i, [2i], {"a": false}]),
+                   List(vec!(
+                       Integer(1),
+                       List(vec!(Integer(2))),
+                       ObjectBuilder::new().insert("a".to_string(), Boolean(false)).unwrap())));
+    }
 }
@@ -0,0 +1,522 @@
+// Copyright 2012-2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Canonical JSON, as described by the JSON Canonicalization Scheme (RFC
+//! 8785). Object members are sorted by the UTF-16 code units of their keys,
+//! numbers are written the way ECMAScript prints them, strings only escape
+//! what JSON requires, and there is no whitespace. The output only depends on
+//! the value being written, so it can be hashed or signed.
+//!
+//! Integers are numbers like any other, so one beyond 2^53 is written as the
+//! nearest `f64`: `u64::MAX` becomes `18446744073709552000`, as it does in
+//! other RFC 8785 implementations.
+
+use std::io::{IoError, IoResult, MemWriter};
+use std::io;
+use std::mem;
+use std::num::{FPNaN, FPInfinite};
+use std::string;
+
+use de;
+use ser::Serializable;
+use ser;
+
+use super::{Parser, dtoa, escape_str};
+
+/// A serializer that writes canonical JSON.
+pub struct Serializer<W> {
+    wr: CaptureWriter<W>,
+    frames: Vec<Frame>,
+    in_key: bool,
+}
+
+/// An open array or object.
+struct Frame {
+    first: bool,
+    /// The members of an object, which are only written once all of them are
+    /// known. `None` for arrays.
+    members: Option<Vec<Member>>,
+    /// The key of the member being written.
+    key: Vec<u16>,
+}
+
+struct Member {
+    key: Vec<u16>,
+    text: Vec<u8>,
+}
+
+/// Passes writes through to the underlying writer, or to the innermost
+/// capture buffer if there is one.
+struct CaptureWriter<W> {
+    wr: W,
+    bufs: Vec<Vec<u8>>,
+}
+
+impl<W: Writer> Writer for CaptureWriter<W> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> IoResult<()> {
+        match self.bufs.last_mut() {
+            Some(b) => {
+                b.push_all(buf);
+                Ok(())
+            }
+            None => self.wr.write(buf),
+        }
+    }
+}
+
+impl<W: Writer> Serializer<W> {
+    /// Creates a new canonical JSON serializer whose output will be written to
+    /// the writer specified.
+    pub fn new(wr: W) -> Serializer<W> {
+        Serializer {
+            wr: CaptureWriter { wr: wr, bufs: Vec::new() },
+            frames: Vec::new(),
+            in_key: false,
+        }
+    }
+
+    /// Unwrap the Writer from the Serializer.
+    pub fn unwrap(self) -> W {
+        self.wr.wr
+    }
+
+    fn begin_array(&mut self) -> IoResult<()> {
+        self.frames.push(Frame { first: true, members: None, key: Vec::new() });
+        self.wr.write_str("[")
+    }
+
+    fn array_elt(&mut self) -> IoResult<()> {
+        let frame = self.frames.last_mut().unwrap();
+
+        if frame.first {
+            frame.first = false;
+            Ok(())
+        } else {
+            self.wr.write_str(",")
+        }
+    }
+
+    fn end_array(&mut self) -> IoResult<()> {
+        self.frames.pop();
+        self.wr.write_str("]")
+    }
+
+    fn begin_object(&mut self) -> IoResult<()> {
+        self.frames.push(Frame { first: true, members: Some(Vec::new()), key: Vec::new() });
+        self.wr.write_str("{")
+    }
+
+    // Starts capturing a member, whose text is `"key":value`.
+    fn begin_member(&mut self, key: &str) -> IoResult<()> {
+        self.frames.last_mut().unwrap().key = key.utf16_units().collect();
+        self.wr.bufs.push(Vec::new());
+        try!(escape_str(&mut self.wr, key));
+        self.wr.write_str(":")
+    }
+
+    fn end_member(&mut self) {
+        let text = self.wr.bufs.pop().unwrap();
+        let frame = self.frames.last_mut().unwrap();
+        let key = mem::replace(&mut frame.key, Vec::new());
+
+        match frame.members {
+            Some(ref mut members) => { members.push(Member { key: key, text: text }); }
+            None => { }
+        }
+    }
+
+    fn end_object(&mut self) -> IoResult<()> {
+        let mut members = match self.frames.pop() {
+            Some(Frame { members: Some(members), .. }) => members,
+            _ => Vec::new(),
+        };
+
+        members.sort_by(|a, b| a.key.cmp(&b.key));
+
+        for (i, member) in members.iter().enumerate() {
+            if i != 0 {
+                try!(self.wr.write_str(","));
+            }
+            try!(self.wr.write(member.text.as_slice()));
+        }
+
+        self.wr.write_str("}")
+    }
+
+    // Map keys may be any value, so they are written out in key mode to find
+    // the string they stand for. Strings are used as is, anything else by its
+    // JSON text.
+    fn key_string<
+        K: Serializable<Serializer<W>, IoError>
+    >(&mut self, key: &K) -> IoResult<string::String> {
+        self.wr.bufs.push(Vec::new());
+        self.in_key = true;
+        let result = key.serialize(self);
+        self.in_key = false;
+        let buf = self.wr.bufs.pop().unwrap();
+
+        try!(result);
+        Ok(string::String::from_utf8(buf).unwrap())
+    }
+
+    fn write_f64(&mut self, v: f64) -> IoResult<()> {
+        match v.classify() {
+            FPNaN | FPInfinite => {
+                Err(io::IoError {
+                    kind: io::InvalidInput,
+                    desc: "canonical JSON cannot represent NaN or infinite floats",
+                    detail: Some(v.to_string()),
+                })
+            }
+            _ => dtoa::write_f64_ecmascript(&mut self.wr, v),
+        }
+    }
+}
+
+impl<W: Writer> ser::Serializer<IoError> for Serializer<W> {
+    #[inline]
+    fn serialize_null(&mut self) -> IoResult<()> {
+        self.wr.write_str("null")
+    }
+
+    #[inline]
+    fn serialize_bool(&mut self, v: bool) -> IoResult<()> {
+        if v {
+            self.wr.write_str("true")
+        } else {
+            self.wr.write_str("false")
+        }
+    }
+
+    #[inline]
+    fn serialize_int(&mut self, v: int) -> IoResult<()> {
+        self.write_f64(v as f64)
+    }
+
+    #[inline]
+    fn serialize_i8(&mut self, v: i8) -> IoResult<()> {
+        write!(self.wr, "{}", v)
+    }
+
+    #[inline]
+    fn serialize_i16(&mut self, v: i16) -> IoResult<()> {
+        write!(self.wr, "{}", v)
+    }
+
+    #[inline]
+    fn serialize_i32(&mut self, v: i32) -> IoResult<()> {
+        write!(self.wr, "{}", v)
+    }
+
+    #[inline]
+    fn serialize_i64(&mut self, v: i64) -> IoResult<()> {
+        self.write_f64(v as f64)
+    }
+
+    #[inline]
+    fn serialize_uint(&mut self, v: uint) -> IoResult<()> {
+        self.write_f64(v as f64)
+    }
+
+    #[inline]
+    fn serialize_u8(&mut self, v: u8) -> IoResult<()> {
+        write!(self.wr, "{}", v)
+    }
+
+    #[inline]
+    fn serialize_u16(&mut self, v: u16) -> IoResult<()> {
+        write!(self.wr, "{}", v)
+    }
+
+    #[inline]
+    fn serialize_u32(&mut self, v: u32) -> IoResult<()> {
+        write!(self.wr, "{}", v)
+    }
+
+    #[inline]
+    fn serialize_u64(&mut self, v: u64) -> IoResult<()> {
+        self.write_f64(v as f64)
+    }
+
+    #[inline]
+    fn serialize_f32(&mut self, v: f32) -> IoResult<()> {
+        self.write_f64(v as f64)
+    }
+
+    #[inline]
+    fn serialize_f64(&mut self, v: f64) -> IoResult<()> {
+        self.write_f64(v)
+    }
+
+    // Exact numbers are reparsed, so that `1.50` and `1.5` give the same
+    // output.
+    fn serialize_number(&mut self, v: &str) -> IoResult<()> {
+        let mut parser = Parser::new(v.bytes());
+
+        match (parser.next(), parser.next()) {
+            (Some(Ok(de::I64(v))), None) => self.write_f64(v as f64),
+            (Some(Ok(de::U64(v))), None) => self.write_f64(v as f64),
+            (Some(Ok(de::F64(v))), None) => self.write_f64(v),
+            _ => {
+                Err(io::IoError {
+                    kind: io::InvalidInput,
+                    desc: "invalid JSON number",
+                    detail: Some(v.to_string()),
+                })
+            }
+        }
+    }
+
+    #[inline]
+    fn serialize_char(&mut self, v: char) -> IoResult<()> {
+        let s = v.to_string();
+        self.serialize_str(s.as_slice())
+    }
+
+    #[inline]
+    fn serialize_str(&mut self, v: &str) -> IoResult<()> {
+        if self.in_key {
+            self.wr.write_str(v)
+        } else {
+            escape_str(&mut self.wr, v)
+        }
+    }
+
+    #[inline]
+    fn serialize_tuple_start(&mut self, _len: uint) -> IoResult<()> {
+        self.begin_array()
+    }
+
+    #[inline]
+    fn serialize_tuple_elt<
+        T: Serializable<Serializer<W>, IoError>
+    >(&mut self, value: &T) -> IoResult<()> {
+        try!(self.array_elt());
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_tuple_end(&mut self) -> IoResult<()> {
+        self.end_array()
+    }
+
+    #[inline]
+    fn serialize_struct_start(&mut self, _name: &str, _len: uint) -> IoResult<()> {
+        self.begin_object()
+    }
+
+    #[inline]
+    fn serialize_struct_elt<
+        T: Serializable<Serializer<W>, IoError>
+    >(&mut self, name: &str, value: &T) -> IoResult<()> {
+        try!(self.begin_member(name));
+        try!(value.serialize(self));
+        self.end_member();
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_struct_end(&mut self) -> IoResult<()> {
+        self.end_object()
+    }
+
+    #[inline]
+    fn serialize_enum_start(&mut self, _name: &str, variant: &str, _len: uint) -> IoResult<()> {
+        try!(self.begin_object());
+        try!(self.begin_member(variant));
+        self.begin_array()
+    }
+
+    #[inline]
+    fn serialize_enum_elt<
+        T: Serializable<Serializer<W>, IoError>
+    >(&mut self, value: &T) -> IoResult<()> {
+        try!(self.array_elt());
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_enum_end(&mut self) -> IoResult<()> {
+        try!(self.end_array());
+        self.end_member();
+        self.end_object()
+    }
+
+    #[inline]
+    fn serialize_option<
+        T: Serializable<Serializer<W>, IoError>
+    >(&mut self, v: &Option<T>) -> IoResult<()> {
+        match *v {
+            Some(ref v) => v.serialize(self),
+            None => self.serialize_null(),
+        }
+    }
+
+    #[inline]
+    fn serialize_seq<
+        T: Serializable<Serializer<W>, IoError>,
+        Iter: Iterator<T>
+    >(&mut self, mut iter: Iter) -> IoResult<()> {
+        try!(self.begin_array());
+
+        for elt in iter {
+            try!(self.array_elt());
+            try!(elt.serialize(self));
+        }
+
+        self.end_array()
+    }
+
+    #[inline]
+    fn serialize_map<
+        K: Serializable<Serializer<W>, IoError>,
+        V: Serializable<Serializer<W>, IoError>,
+        Iter: Iterator<(K, V)>
+    >(&mut self, mut iter: Iter) -> IoResult<()> {
+        try!(self.begin_object());
+
+        for (key, value) in iter {
+            let key = try!(self.key_string(&key));
+            try!(self.begin_member(key.as_slice()));
+            try!(value.serialize(self));
+            self.end_member();
+        }
+
+        self.end_object()
+    }
+}
+
+/// Encode the specified value into a canonical JSON `[u8]` buffer.
+pub fn to_vec<
+    T: Serializable<Serializer<MemWriter>, IoError>
+>(value: &T) -> IoResult<Vec<u8>> {
+    let mut serializer = Serializer::new(MemWriter::new());
+    try!(value.serialize(&mut serializer));
+    Ok(serializer.unwrap().unwrap())
+}
+
+/// Encode the specified value into a canonical JSON `String`.
+pub fn to_string<
+    T: Serializable<Serializer<MemWriter>, IoError>
+>(value: &T) -> IoResult<string::String> {
+    let buf = try!(to_vec(value));
+    Ok(string::String::from_utf8(buf).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, TreeMap};
+    use std::f64;
+    use std::i64;
+    use std::io;
+    use std::string;
+
+    use json::{Json, Integer, U64, Floating, Number, String, List, Object};
+    use json;
+    use super::to_string;
+
+    #[deriving_serializable]
+    struct Payload {
+        numbers: Vec<f64>,
+        string: string::String,
+        literals: (Option<int>, bool, bool),
+    }
+
+    #[test]
+    fn test_canonical_numbers() {
+        let tests = [
+            (0.0f64, "0"),
+            (-0.0, "0"),
+            (3.0, "3"),
+            (-1.5, "-1.5"),
+            (1e21, "1e+21"),
+            (1e20, "100000000000000000000"),
+            (1e-7, "1e-7"),
+            (0.000001, "0.000001"),
+            (333333333.33333329, "333333333.3333333"),
+            (4.50, "4.5"),
+            (2e-3, "0.002"),
+            (0.000000000000000000000000001, "1e-27"),
+        ];
+
+        for &(v, s) in tests.iter() {
+            assert_eq!(to_string(&v).unwrap(), s.to_string());
+        }
+
+        assert_eq!(to_string(&U64(18446744073709551615)).unwrap(),
+                   "18446744073709552000".to_string());
+        assert_eq!(to_string(&9007199254740993u64).unwrap(), "9007199254740992".to_string());
+        assert_eq!(to_string(&9007199254740992i64).unwrap(), "9007199254740992".to_string());
+        assert_eq!(to_string(&i64::MIN).unwrap(), "-9223372036854776000".to_string());
+        assert_eq!(to_string(&-42i).unwrap(), "-42".to_string());
+        assert_eq!(to_string(&Number("18446744073709551615".to_string())).unwrap(),
+                   "18446744073709552000".to_string());
+        assert_eq!(to_string(&Number("1.50e2".to_string())).unwrap(), "150".to_string());
+        assert_eq!(to_string(&f64::NAN).unwrap_err().kind, io::InvalidInput);
+    }
+
+    #[test]
+    fn test_canonical_strings() {
+        assert_eq!(to_string(&"\u20ac$\u000f\nA'B\"\\\\\"/".to_string()).unwrap(),
+                   "\"\u20ac$\\u000f\\nA'B\\\"\\\\\\\\\\\"/\"".to_string());
+    }
+
+    #[test]
+    fn test_canonical_key_order() {
+        let mut map = HashMap::new();
+        map.insert("\u20ac".to_string(), 1i);
+        map.insert("\r".to_string(), 2i);
+        map.insert("\U0001f600".to_string(), 3i);
+        map.insert("1".to_string(), 4i);
+        map.insert("\u0080".to_string(), 5i);
+        map.insert("\u00f6".to_string(), 6i);
+        map.insert("\ufb03".to_string(), 7i);
+
+        // U+1F600 is a surrogate pair in UTF-16, so it sorts before U+FB03.
+        assert_eq!(
+            to_string(&map).unwrap(),
+            concat!(
+                "{\"\\r\":2,\"1\":4,\"\u0080\":5,\"\u00f6\":6,",
+                "\"\u20ac\":1,\"\U0001f600\":3,\"\ufb03\":7}"
+            ).to_string());
+    }
+
+    #[test]
+    fn test_canonical_json_matches_serializable() {
+        let payload = Payload {
+            numbers: vec![333333333.33333329, 1E30, 4.50, 2e-3, 0.000000000000000000000000001],
+            string: "\u20ac$\u000f\nA'B\"\\\\\"/".to_string(),
+            literals: (None, true, false),
+        };
+
+        let mut object = TreeMap::new();
+        object.insert("numbers".to_string(), List(vec![
+            Floating(333333333.33333329),
+            Floating(1E30),
+            Floating(4.50),
+            Floating(2e-3),
+            Floating(0.000000000000000000000000001),
+        ]));
+        object.insert("string".to_string(), String(payload.string.clone()));
+        object.insert("literals".to_string(), json::from_str("[null, true, false]").unwrap());
+        let value: Json = Object(object);
+
+        let expected = concat!(
+            "{\"literals\":[null,true,false],",
+            "\"numbers\":[333333333.3333333,1e+30,4.5,0.002,1e-27],",
+            "\"string\":\"\u20ac$\\u000f\\nA'B\\\"\\\\\\\\\\\"/\"}"
+        ).to_string();
+
+        assert_eq!(to_string(&payload).unwrap(), expected);
+        assert_eq!(to_string(&value).unwrap(), expected);
+        assert_eq!(to_string(&Integer(3)).unwrap(), to_string(&Floating(3.0)).unwrap());
+    }
+}
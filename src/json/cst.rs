@@ -0,0 +1,1116 @@
+// Copyright 2012-2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Format preserving JSON documents. A `Document` keeps the source text it
+//! was parsed from, along with a tree recording where every value, key and
+//! comma lives in it. Edits are made through JSON Pointers (RFC 6901) and
+//! only replace the bytes of the value being changed, so whitespace, key
+//! order and, in relaxed mode, comments are kept as they were.
+//!
+//! ```rust
+//! use serde::json::cst::Document;
+//!
+//! let mut doc = Document::parse_relaxed("{\n  \"server\": { \"port\": 80 } // web\n}").unwrap();
+//! doc.set("/server/port", &8080i).unwrap();
+//! assert_eq!(doc.as_str(), "{\n  \"server\": { \"port\": 8080 } // web\n}");
+//! ```
+//!
+//! `parse_recovering` uses the same parser to report every syntax error in a
+//! document at once, which is what a linter wants.
+
+use std::cmp;
+use std::collections::TreeMap;
+use std::io::{IoError, MemWriter};
+use std::string;
+
+use de;
+use ser::Serializable;
+
+use super::{
+    Json,
+    Null,
+    List,
+    Object,
+    Parser,
+    ParserError,
+    Serializer,
+    SyntaxError,
+    ErrorCode,
+    EOFWhileParsingList,
+    EOFWhileParsingObject,
+    EOFWhileParsingString,
+    EOFWhileParsingValue,
+    ExpectedColon,
+    InvalidSyntax,
+    KeyMustBeAString,
+    TrailingCharacters,
+    ListCommaOrEnd,
+    ObjectCommaOrEnd,
+    SomeValue,
+    escape_str,
+    line_col,
+    parse_index,
+    parse_pointer,
+};
+
+/// A value in a document, along with the byte range of its text.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Node {
+    pub start: uint,
+    pub end: uint,
+    pub kind: NodeKind,
+}
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum NodeKind {
+    /// A string, number, boolean or null.
+    ScalarNode,
+    ArrayNode(Vec<Element>),
+    ObjectNode(Vec<Member>),
+}
+
+/// An element of an array.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Element {
+    pub value: Node,
+    /// The offset of the comma following the element, if there is one.
+    pub comma: Option<uint>,
+}
+
+/// A member of an object.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Member {
+    /// The decoded key.
+    pub key: string::String,
+    pub key_start: uint,
+    pub key_end: uint,
+    pub value: Node,
+    /// The offset of the comma following the member, if there is one.
+    pub comma: Option<uint>,
+}
+
+/// The errors that can arise while editing a document.
+#[deriving(Clone, PartialEq, Show)]
+pub enum EditError {
+    /// Nothing is at the pointer, or the parent of a new value is not an
+    /// object or an array.
+    PathNotFound(string::String),
+    /// The new text is not a single JSON value.
+    InvalidValue(ParserError),
+}
+
+/// A parsed JSON document that remembers its exact source text.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Document {
+    text: string::String,
+    relaxed: bool,
+    root: Node,
+}
+
+impl Document {
+    /// Parses a strict JSON document.
+    pub fn parse(text: &str) -> Result<Document, ParserError> {
+        Document::parse_with(text, false)
+    }
+
+    /// Parses a document written in the syntax accepted by
+    /// `Parser::relaxed`. Comments are kept when the document is edited.
+    pub fn parse_relaxed(text: &str) -> Result<Document, ParserError> {
+        Document::parse_with(text, true)
+    }
+
+    fn parse_with(text: &str, relaxed: bool) -> Result<Document, ParserError> {
+        let root = try!(parse_root(text, relaxed));
+        Ok(Document {
+            text: text.to_string(),
+            relaxed: relaxed,
+            root: root,
+        })
+    }
+
+    /// The current text of the document.
+    pub fn as_str<'a>(&'a self) -> &'a str {
+        self.text.as_slice()
+    }
+
+    /// Consumes the document, returning its text.
+    pub fn into_string(self) -> string::String {
+        self.text
+    }
+
+    pub fn root<'a>(&'a self) -> &'a Node {
+        &self.root
+    }
+
+    /// Looks up the value at a JSON Pointer, such as `/server/port`.
+    pub fn get<'a>(&'a self, pointer: &str) -> Option<&'a Node> {
+        let tokens = match parse_pointer(pointer) {
+            Some(tokens) => tokens,
+            None => { return None; }
+        };
+
+        let mut node = &self.root;
+        for token in tokens.iter() {
+            node = match node.child(token.as_slice()) {
+                Some(child) => child,
+                None => { return None; }
+            };
+        }
+        Some(node)
+    }
+
+    /// The source text of a node in this document.
+    pub fn text_of<'a>(&'a self, node: &Node) -> &'a str {
+        self.text.as_slice().slice(node.start, node.end)
+    }
+
+    /// Converts the document to a `Json` value. The tree built when the
+    /// document was parsed is used, so this can't fail.
+    pub fn to_json(&self) -> Json {
+        Scanner::new(self.text.as_slice(), self.relaxed, false).to_json(&self.root)
+    }
+
+    /// Sets the value at a JSON Pointer to the compact JSON encoding of
+    /// `value`, as written by `json::to_string`. See `set_raw`.
+    pub fn set<
+        T: Serializable<Serializer<MemWriter>, IoError>
+    >(&mut self, pointer: &str, value: &T) -> Result<(), EditError> {
+        let text = super::to_string(value).ok().expect("json is always utf-8");
+        self.set_raw(pointer, text.as_slice())
+    }
+
+    /// Sets the value at a JSON Pointer to `text`, which must be a single
+    /// JSON value. An existing value is replaced in place. A missing object
+    /// member, or an array element at `-`, is added after the last one,
+    /// copying the whitespace that comes before it. It goes after the last
+    /// one's comma and any comments on the rest of its line, so those stay
+    /// with the value they belong to. The parent must exist.
+    pub fn set_raw(&mut self, pointer: &str, text: &str) -> Result<(), EditError> {
+        match parse_root(text, self.relaxed) {
+            Ok(_) => { }
+            Err(err) => { return Err(InvalidValue(err)); }
+        }
+
+        if pointer.is_empty() {
+            let (start, end) = (self.root.start, self.root.end);
+            return self.splice(start, end, text);
+        }
+
+        let (start, end, replacement) = {
+            let (parent, last) = match self.parent_of(pointer) {
+                Some((parent, last)) => (parent, last),
+                None => { return Err(PathNotFound(pointer.to_string())); }
+            };
+
+            match parent.kind {
+                ObjectNode(ref members) => {
+                    match members.iter().find(|m| m.key == last) {
+                        Some(member) => {
+                            (member.value.start, member.value.end, text.to_string())
+                        }
+                        None => {
+                            let mut key = MemWriter::new();
+                            escape_str(&mut key, last.as_slice()).unwrap();
+                            let key = string::String::from_utf8(key.unwrap()).unwrap();
+
+                            match members.last() {
+                                Some(member) => {
+                                    let only = members.len() == 1;
+                                    let indent = self.separator_before(member.key_start, only);
+                                    let colon = self.text.as_slice()
+                                        .slice(member.key_end, member.value.start);
+                                    let sibling = format!("{}{}{}{}", indent, key, colon, text);
+                                    self.append_after(member.value.end,
+                                                      member.comma,
+                                                      sibling.as_slice())
+                                }
+                                None => {
+                                    let insert = format!("{}: {}", key, text);
+                                    (parent.start + 1, parent.start + 1, insert)
+                                }
+                            }
+                        }
+                    }
+                }
+                ArrayNode(ref elements) => {
+                    if last.as_slice() == "-" {
+                        match elements.last() {
+                            Some(element) => {
+                                let only = elements.len() == 1;
+                                let indent = self.separator_before(element.value.start, only);
+                                let sibling = format!("{}{}", indent, text);
+                                self.append_after(element.value.end,
+                                                  element.comma,
+                                                  sibling.as_slice())
+                            }
+                            None => (parent.start + 1, parent.start + 1, text.to_string()),
+                        }
+                    } else {
+                        let index = parse_index(last.as_slice());
+                        match index.and_then(|i| elements.as_slice().get(i)) {
+                            Some(element) => {
+                                (element.value.start, element.value.end, text.to_string())
+                            }
+                            None => { return Err(PathNotFound(pointer.to_string())); }
+                        }
+                    }
+                }
+                ScalarNode => { return Err(PathNotFound(pointer.to_string())); }
+            }
+        };
+
+        self.splice(start, end, replacement.as_slice())
+    }
+
+    /// Removes the value at a JSON Pointer, along with its key and the comma
+    /// separating it from its neighbours.
+    pub fn remove(&mut self, pointer: &str) -> Result<(), EditError> {
+        let (start, end) = {
+            let (parent, last) = match self.parent_of(pointer) {
+                Some((parent, last)) => (parent, last),
+                None => { return Err(PathNotFound(pointer.to_string())); }
+            };
+
+            // The ranges of the siblings, and the position of the one being
+            // removed.
+            let (ranges, index) = match parent.kind {
+                ObjectNode(ref members) => {
+                    let ranges: Vec<(uint, uint, Option<uint>)> = members.iter()
+                        .map(|m| (m.key_start, m.value.end, m.comma))
+                        .collect();
+                    (ranges, members.iter().position(|m| m.key == last))
+                }
+                ArrayNode(ref elements) => {
+                    let ranges: Vec<(uint, uint, Option<uint>)> = elements.iter()
+                        .map(|e| (e.value.start, e.value.end, e.comma))
+                        .collect();
+                    let index = parse_index(last.as_slice()).and_then(|i| {
+                        if i < elements.len() { Some(i) } else { None }
+                    });
+                    (ranges, index)
+                }
+                ScalarNode => (Vec::new(), None),
+            };
+
+            let index = match index {
+                Some(index) => index,
+                None => { return Err(PathNotFound(pointer.to_string())); }
+            };
+
+            let (start, end, comma) = *ranges.get(index);
+            if index + 1 < ranges.len() {
+                // Remove up to the start of the next sibling.
+                let (next_start, _, _) = *ranges.get(index + 1);
+                (start, next_start)
+            } else if index > 0 {
+                // Remove from the end of the previous sibling, taking its
+                // comma with us.
+                let (_, prev_end, _) = *ranges.get(index - 1);
+                (prev_end, end)
+            } else {
+                // The only sibling. Take a trailing comma too, so we don't
+                // leave `{,}` behind.
+                (start, comma.map(|c| c + 1).unwrap_or(end))
+            }
+        };
+
+        self.splice(start, end, "")
+    }
+
+    /// Finds the parent of the value at a pointer, and the last token of the
+    /// pointer. Returns `None` for the root pointer.
+    fn parent_of<'a>(&'a self, pointer: &str) -> Option<(&'a Node, string::String)> {
+        let mut tokens = match parse_pointer(pointer) {
+            Some(tokens) => tokens,
+            None => { return None; }
+        };
+
+        let last = match tokens.pop() {
+            Some(last) => last,
+            None => { return None; }
+        };
+
+        let mut node = &self.root;
+        for token in tokens.iter() {
+            node = match node.child(token.as_slice()) {
+                Some(child) => child,
+                None => { return None; }
+            };
+        }
+
+        Some((node, last))
+    }
+
+    /// The whitespace directly before the last sibling at `offset`, used to
+    /// lay out a new sibling the same way. A lone sibling directly after the
+    /// bracket gets a single space.
+    fn separator_before<'a>(&'a self, offset: uint, only: bool) -> &'a str {
+        let bytes = self.text.as_bytes();
+        let mut start = offset;
+        while start > 0 && is_whitespace(bytes[start - 1]) {
+            start -= 1;
+        }
+
+        if only && start == offset {
+            " "
+        } else {
+            self.text.as_slice().slice(start, offset)
+        }
+    }
+
+    /// Adds `sibling` after the last value of an array or object, which ends
+    /// at `end` and may have a trailing comma. Returns the range to replace
+    /// and the text to replace it with. The bytes already there are kept,
+    /// and a trailing comma is carried over to the new last value.
+    fn append_after(&self,
+                    end: uint,
+                    comma: Option<uint>,
+                    sibling: &str) -> (uint, uint, string::String) {
+        let after = comma.map(|c| c + 1).unwrap_or(end);
+        let at = self.end_of_line_comments(after);
+
+        let mut insert = string::String::new();
+        if comma.is_none() {
+            insert.push_str(",");
+        }
+        insert.push_str(self.text.as_slice().slice(end, at));
+        insert.push_str(sibling);
+        if comma.is_some() {
+            insert.push_str(",");
+        }
+
+        (end, at, insert)
+    }
+
+    /// Skips the comments that follow `offset` on the same line, returning
+    /// where the last one ends, or `offset` if there are none.
+    fn end_of_line_comments(&self, offset: uint) -> uint {
+        if !self.relaxed {
+            return offset;
+        }
+
+        let bytes = self.text.as_bytes();
+        let mut end = offset;
+        let mut pos = offset;
+
+        loop {
+            while pos < bytes.len() && (bytes[pos] == b' ' || bytes[pos] == b'\t') {
+                pos += 1;
+            }
+
+            let rest = bytes.slice_from(pos);
+            if rest.starts_with(b"//") {
+                while pos < bytes.len() && bytes[pos] != b'\n' {
+                    pos += 1;
+                }
+                return pos;
+            } else if rest.starts_with(b"/*") {
+                // The document parsed, so the comment is closed.
+                pos += 2;
+                while !bytes.slice_from(pos).starts_with(b"*/") {
+                    pos += 1;
+                }
+                pos += 2;
+                end = pos;
+            } else {
+                return end;
+            }
+        }
+    }
+
+    /// Replaces a range of the text and parses the result. The document is
+    /// left untouched if the result does not parse.
+    fn splice(&mut self, start: uint, end: uint, text: &str) -> Result<(), EditError> {
+        let mut new_text = string::String::with_capacity(self.text.len() + text.len());
+        new_text.push_str(self.text.as_slice().slice_to(start));
+        new_text.push_str(text);
+        new_text.push_str(self.text.as_slice().slice_from(end));
+
+        match parse_root(new_text.as_slice(), self.relaxed) {
+            Ok(root) => {
+                self.text = new_text;
+                self.root = root;
+                Ok(())
+            }
+            Err(err) => Err(InvalidValue(err)),
+        }
+    }
+}
+
+impl Node {
+    /// Looks up a member of an object, or an element of an array by its
+    /// decimal index.
+    pub fn child<'a>(&'a self, token: &str) -> Option<&'a Node> {
+        match self.kind {
+            ObjectNode(ref members) => {
+                members.iter().find(|m| m.key.as_slice() == token).map(|m| &m.value)
+            }
+            ArrayNode(ref elements) => {
+                parse_index(token).and_then(|i| elements.as_slice().get(i)).map(|e| &e.value)
+            }
+            ScalarNode => None,
+        }
+    }
+}
+
+fn is_whitespace(ch: u8) -> bool {
+    match ch {
+        b' ' | b'\t' | b'\n' | b'\r' => true,
+        _ => false,
+    }
+}
+
+/// Bytes that end a bare scalar such as a number or `true`.
+fn is_delimiter(ch: u8) -> bool {
+    match ch {
+        b',' | b':' | b'[' | b']' | b'{' | b'}' | b'"' | b'\'' | b'/' => true,
+        ch => is_whitespace(ch),
+    }
+}
+
+fn parse_root(text: &str, relaxed: bool) -> Result<Node, ParserError> {
+    let mut scanner = Scanner::new(text, relaxed, false);
+
+    let root = try!(scanner.parse_value());
+    try!(scanner.skip_whitespace());
+
+    if scanner.pos == scanner.text.len() {
+        Ok(root)
+    } else {
+        scanner.error(TrailingCharacters)
+    }
+}
+
+/// Parses a document without stopping at the first error. After an error
+/// the parser skips ahead to the next `,`, `]` or `}` that belongs to the
+/// enclosing array or object and carries on from there. Returns what could be
+/// parsed, leaving out the values with errors, along with every error found.
+/// Errors at the same position as the one before them are only reported
+/// once, so an unclosed `[[1` is a single error.
+pub fn parse_recovering(text: &str, relaxed: bool) -> (Json, Vec<ParserError>) {
+    let mut scanner = Scanner::new(text, relaxed, true);
+
+    let root = match scanner.parse_value() {
+        Ok(root) => Some(root),
+        Err(err) => {
+            scanner.push_error(err);
+            None
+        }
+    };
+
+    // Whitespace errors are recorded rather than returned when recovering.
+    let _ = scanner.skip_whitespace();
+
+    if scanner.pos < scanner.text.len() {
+        let err = scanner.syntax_error(TrailingCharacters);
+        scanner.push_error(err);
+    }
+
+    let value = match root {
+        Some(ref root) => scanner.to_json(root),
+        None => Null,
+    };
+
+    (value, scanner.errors)
+}
+
+/// Walks the text of a document, recording where everything is. The values
+/// themselves are checked by `Parser`.
+struct Scanner<'a> {
+    text: &'a [u8],
+    pos: uint,
+    relaxed: bool,
+    /// Whether to record errors and carry on, rather than returning them.
+    recover: bool,
+    errors: Vec<ParserError>,
+}
+
+/// What follows an element of an array or a member of an object.
+enum Separator {
+    /// A comma, at this offset.
+    Comma(uint),
+    /// The end of the array or object.
+    Close,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(text: &'a str, relaxed: bool, recover: bool) -> Scanner<'a> {
+        Scanner {
+            text: text.as_bytes(),
+            pos: 0,
+            relaxed: relaxed,
+            recover: recover,
+            errors: Vec::new(),
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        if self.pos < self.text.len() {
+            Some(self.text[self.pos])
+        } else {
+            None
+        }
+    }
+
+    fn error<T>(&self, code: ErrorCode) -> Result<T, ParserError> {
+        self.error_at(self.pos, code)
+    }
+
+    fn error_at<T>(&self, pos: uint, code: ErrorCode) -> Result<T, ParserError> {
+        Err(self.syntax_error_at(pos, code))
+    }
+
+    fn syntax_error(&self, code: ErrorCode) -> ParserError {
+        self.syntax_error_at(self.pos, code)
+    }
+
+    fn syntax_error_at(&self, pos: uint, code: ErrorCode) -> ParserError {
+        let (line, col) = line_col(self.text, pos);
+        SyntaxError(code, line, col, pos)
+    }
+
+    fn skip_whitespace(&mut self) -> Result<(), ParserError> {
+        loop {
+            match self.peek() {
+                Some(ch) if is_whitespace(ch) => { self.pos += 1; }
+                Some(b'/') if self.relaxed => {
+                    let rest = self.text.slice_from(self.pos);
+                    if rest.starts_with(b"//") {
+                        while self.pos < self.text.len() && self.text[self.pos] != b'\n' {
+                            self.pos += 1;
+                        }
+                    } else if rest.starts_with(b"/*") {
+                        let start = self.pos;
+                        self.pos += 2;
+                        loop {
+                            if self.pos + 1 >= self.text.len() {
+                                self.pos = self.text.len();
+                                let err = self.syntax_error_at(start, EOFWhileParsingValue);
+                                return self.recover(err);
+                            }
+                            if self.text.slice_from(self.pos).starts_with(b"*/") {
+                                self.pos += 2;
+                                break;
+                            }
+                            self.pos += 1;
+                        }
+                    } else {
+                        // A stray `/` is reported by the caller.
+                        return Ok(());
+                    }
+                }
+                _ => { return Ok(()); }
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Node, ParserError> {
+        try!(self.skip_whitespace());
+
+        let start = self.pos;
+        match self.peek() {
+            None => self.error(EOFWhileParsingValue),
+            Some(b'[') => self.parse_array(),
+            Some(b'{') => self.parse_object(),
+            Some(b'"') => {
+                try!(self.skip_string());
+                self.parse_scalar(start).map(|_| self.scalar_node(start))
+            }
+            Some(b'\'') if self.relaxed => {
+                try!(self.skip_string());
+                self.parse_scalar(start).map(|_| self.scalar_node(start))
+            }
+            Some(_) => {
+                loop {
+                    match self.peek() {
+                        Some(ch) if !is_delimiter(ch) => { self.pos += 1; }
+                        _ => { break; }
+                    }
+                }
+
+                if self.pos == start {
+                    return self.error(InvalidSyntax(SomeValue));
+                }
+
+                self.parse_scalar(start).map(|_| self.scalar_node(start))
+            }
+        }
+    }
+
+    fn scalar_node(&self, start: uint) -> Node {
+        Node {
+            start: start,
+            end: self.pos,
+            kind: ScalarNode,
+        }
+    }
+
+    /// Checks the scalar that runs from `start` to the current position.
+    fn parse_scalar(&self, start: uint) -> Result<de::Token, ParserError> {
+        let text = self.text.slice(start, self.pos);
+        let mut parser = Parser::new(text.iter().map(|ch| *ch)).relaxed(self.relaxed);
+
+        match (parser.next(), parser.next()) {
+            (Some(Ok(token)), None) => Ok(token),
+            (Some(Err(SyntaxError(code, _, _, _))), _) => self.error_at(start, code),
+            (_, Some(Err(SyntaxError(code, _, _, _)))) => self.error_at(start, code),
+            _ => self.error_at(start, InvalidSyntax(SomeValue)),
+        }
+    }
+
+    /// Moves past a quoted string, without decoding it.
+    fn skip_string(&mut self) -> Result<(), ParserError> {
+        let quote = self.text[self.pos];
+        self.pos += 1;
+
+        loop {
+            match self.peek() {
+                None => { return self.error(EOFWhileParsingString); }
+                Some(b'\\') => { self.pos = cmp::min(self.pos + 2, self.text.len()); }
+                Some(ch) if ch == quote => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                Some(_) => { self.pos += 1; }
+            }
+        }
+    }
+
+    /// When recovering, records an error and skips to the next `,`, `]` or
+    /// `}` at the current depth. Otherwise returns the error.
+    fn recover(&mut self, err: ParserError) -> Result<(), ParserError> {
+        if !self.recover {
+            return Err(err);
+        }
+
+        self.push_error(err);
+
+        let mut depth = 0u;
+        loop {
+            match self.peek() {
+                None => { return Ok(()); }
+                Some(b'"') => { let _ = self.skip_string(); }
+                Some(b'\'') if self.relaxed => { let _ = self.skip_string(); }
+                Some(b'[') | Some(b'{') => {
+                    depth += 1;
+                    self.pos += 1;
+                }
+                Some(b']') | Some(b'}') => {
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                    depth -= 1;
+                    self.pos += 1;
+                }
+                Some(b',') if depth == 0 => { return Ok(()); }
+                Some(_) => { self.pos += 1; }
+            }
+        }
+    }
+
+    fn push_error(&mut self, err: ParserError) {
+        let duplicate = match (self.errors.last(), &err) {
+            (Some(&SyntaxError(_, _, _, prev)), &SyntaxError(_, _, _, offset)) => prev == offset,
+            _ => false,
+        };
+
+        if !duplicate {
+            self.errors.push(err);
+        }
+    }
+
+    /// Parses the `,` or closing bracket after an element or a member.
+    fn parse_separator(&mut self,
+                       close: u8,
+                       eof: ErrorCode,
+                       expected: ErrorCode) -> Result<Separator, ParserError> {
+        try!(self.skip_whitespace());
+
+        loop {
+            match self.peek() {
+                Some(b',') => {
+                    self.pos += 1;
+                    return Ok(Comma(self.pos - 1));
+                }
+                Some(ch) if ch == close => {
+                    self.pos += 1;
+                    return Ok(Close);
+                }
+                None => {
+                    let err = self.syntax_error(eof);
+                    try!(self.recover(err));
+                    return Ok(Close);
+                }
+                Some(b']') | Some(b'}') if self.recover => {
+                    // The wrong bracket. Leave it for the enclosing value.
+                    let err = self.syntax_error(expected.clone());
+                    self.push_error(err);
+                    return Ok(Close);
+                }
+                Some(_) => {
+                    let err = self.syntax_error(expected.clone());
+                    try!(self.recover(err));
+                }
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Node, ParserError> {
+        let start = self.pos;
+        self.pos += 1;
+
+        let mut elements = Vec::new();
+
+        try!(self.skip_whitespace());
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+        } else {
+            loop {
+                let value = match self.parse_value() {
+                    Ok(value) => Some(value),
+                    Err(err) => {
+                        try!(self.recover(err));
+                        None
+                    }
+                };
+
+                let separator = try!(self.parse_separator(b']',
+                                                          EOFWhileParsingList,
+                                                          InvalidSyntax(ListCommaOrEnd)));
+
+                match separator {
+                    Comma(comma) => {
+                        match value {
+                            Some(value) => {
+                                elements.push(Element { value: value, comma: Some(comma) });
+                            }
+                            None => { }
+                        }
+
+                        try!(self.skip_whitespace());
+                        if self.relaxed && self.peek() == Some(b']') {
+                            self.pos += 1;
+                            break;
+                        }
+                    }
+                    Close => {
+                        match value {
+                            Some(value) => elements.push(Element { value: value, comma: None }),
+                            None => { }
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(Node {
+            start: start,
+            end: self.pos,
+            kind: ArrayNode(elements),
+        })
+    }
+
+    fn parse_object(&mut self) -> Result<Node, ParserError> {
+        let start = self.pos;
+        self.pos += 1;
+
+        let mut members = Vec::new();
+
+        try!(self.skip_whitespace());
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+        } else {
+            loop {
+                let member = match self.parse_member() {
+                    Ok(member) => Some(member),
+                    Err(err) => {
+                        try!(self.recover(err));
+                        None
+                    }
+                };
+
+                let separator = try!(self.parse_separator(b'}',
+                                                          EOFWhileParsingObject,
+                                                          InvalidSyntax(ObjectCommaOrEnd)));
+
+                match separator {
+                    Comma(comma) => {
+                        match member {
+                            Some(mut member) => {
+                                member.comma = Some(comma);
+                                members.push(member);
+                            }
+                            None => { }
+                        }
+
+                        try!(self.skip_whitespace());
+                        if self.relaxed && self.peek() == Some(b'}') {
+                            self.pos += 1;
+                            break;
+                        }
+                    }
+                    Close => {
+                        match member {
+                            Some(member) => members.push(member),
+                            None => { }
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(Node {
+            start: start,
+            end: self.pos,
+            kind: ObjectNode(members),
+        })
+    }
+
+    fn parse_member(&mut self) -> Result<Member, ParserError> {
+        let key_start = self.pos;
+        let key = try!(self.parse_key());
+        let key_end = self.pos;
+
+        try!(self.skip_whitespace());
+        match self.peek() {
+            Some(b':') => { self.pos += 1; }
+            None => { return self.error(EOFWhileParsingObject); }
+            Some(_) => { return self.error(ExpectedColon); }
+        }
+
+        let value = try!(self.parse_value());
+
+        Ok(Member {
+            key: key,
+            key_start: key_start,
+            key_end: key_end,
+            value: value,
+            comma: None,
+        })
+    }
+
+    /// Converts a node to `Json`.
+    fn to_json(&self, node: &Node) -> Json {
+        match node.kind {
+            ScalarNode => {
+                let text = self.text.slice(node.start, node.end);
+                let mut parser = Parser::new(text.iter().map(|ch| *ch)).relaxed(self.relaxed);
+                de::Deserializable::deserialize(&mut parser).unwrap_or(Null)
+            }
+            ArrayNode(ref elements) => {
+                List(elements.iter().map(|element| self.to_json(&element.value)).collect())
+            }
+            ObjectNode(ref members) => {
+                let mut object = TreeMap::new();
+                for member in members.iter() {
+                    object.insert(member.key.clone(), self.to_json(&member.value));
+                }
+                Object(object)
+            }
+        }
+    }
+
+    /// Parses an object key, returning it decoded.
+    fn parse_key(&mut self) -> Result<string::String, ParserError> {
+        let start = self.pos;
+        match self.peek() {
+            Some(b'"') => { try!(self.skip_string()); }
+            Some(b'\'') if self.relaxed => { try!(self.skip_string()); }
+            Some(ch) if self.relaxed && is_identifier_start(ch) => {
+                while self.peek().map_or(false, |ch| is_identifier_char(ch)) {
+                    self.pos += 1;
+                }
+                let key = self.text.slice(start, self.pos);
+                return Ok(string::String::from_utf8(key.to_vec()).unwrap());
+            }
+            None => { return self.error(EOFWhileParsingString); }
+            Some(_) => { return self.error(KeyMustBeAString); }
+        }
+
+        match try!(self.parse_scalar(start)) {
+            de::String(key) => Ok(key),
+            _ => self.error_at(start, KeyMustBeAString),
+        }
+    }
+}
+
+fn is_identifier_start(ch: u8) -> bool {
+    match ch {
+        b'a' ... b'z' | b'A' ... b'Z' | b'_' | b'$' => true,
+        _ => false,
+    }
+}
+
+fn is_identifier_char(ch: u8) -> bool {
+    match ch {
+        b'0' ... b'9' => true,
+        ch => is_identifier_start(ch),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64;
+
+    use json::{
+        EOFWhileParsingList,
+        EOFWhileParsingObject,
+        EOFWhileParsingValue,
+        ExpectedColon,
+        Integer,
+        InvalidSyntax,
+        List,
+        ListCommaOrEnd,
+        Null,
+        SomeIdent,
+        SyntaxError,
+        TrailingCharacters,
+    };
+    use json;
+    use super::{Document, InvalidValue, PathNotFound, parse_recovering};
+
+    static CONFIG: &'static str = concat!(
+        "// deployment settings\n",
+        "{\n",
+        "    server: {\n",
+        "        'host': \"example.com\",\n",
+        "        port: 80, /* http */\n",
+        "    },\n",
+        "    \"tags\": [\"web\"],\n",
+        "}\n"
+    );
+
+    #[test]
+    fn test_cst_roundtrip() {
+        let doc = Document::parse_relaxed(CONFIG).unwrap();
+        assert_eq!(doc.as_str(), CONFIG);
+
+        let port = doc.get("/server/port").unwrap();
+        assert_eq!(doc.text_of(port), "80");
+        assert_eq!(doc.text_of(doc.get("/tags/0").unwrap()), "\"web\"");
+        assert!(doc.get("/tags/1").is_none());
+        assert!(doc.get("server").is_none());
+
+        assert_eq!(doc.to_json(), json::from_str(concat!(
+            "{\"server\":{\"host\":\"example.com\",\"port\":80},\"tags\":[\"web\"]}"
+        )).unwrap());
+    }
+
+    #[test]
+    fn test_cst_set() {
+        let mut doc = Document::parse_relaxed(CONFIG).unwrap();
+
+        doc.set("/server/port", &8080i).unwrap();
+        doc.set("/server/tls", &true).unwrap();
+        doc.set("/tags/-", &"api".to_string()).unwrap();
+        doc.set_raw("/tags/0", "'www'").unwrap();
+
+        assert_eq!(doc.as_str(), concat!(
+            "// deployment settings\n",
+            "{\n",
+            "    server: {\n",
+            "        'host': \"example.com\",\n",
+            "        port: 8080, /* http */\n",
+            "        \"tls\": true,\n",
+            "    },\n",
+            "    \"tags\": ['www', \"api\"],\n",
+            "}\n"
+        ));
+
+        assert_eq!(doc.set("/missing/key", &1i), Err(PathNotFound("/missing/key".to_string())));
+        assert_eq!(doc.set("/tags/5", &1i), Err(PathNotFound("/tags/5".to_string())));
+        assert_eq!(doc.set_raw("/tags/0", "1 2"),
+                   Err(InvalidValue(SyntaxError(TrailingCharacters, 1, 3, 2))));
+
+        let mut doc = Document::parse("{}").unwrap();
+        doc.set("/a~1b", &vec![1i]).unwrap();
+        doc.set("/a~1b/-", &2i).unwrap();
+        assert_eq!(doc.as_str(), "{\"a/b\": [1, 2]}");
+        assert_eq!(doc.text_of(doc.get("/a~1b/1").unwrap()), "2");
+
+        doc.set("", &"root".to_string()).unwrap();
+        assert_eq!(doc.as_str(), "\"root\"");
+
+        doc.set("", &vec![1.5f64, f64::NAN]).unwrap();
+        assert_eq!(doc.as_str(), "[1.5,null]");
+
+        let mut doc = Document::parse_relaxed("[\n  1 // one\n]").unwrap();
+        doc.set("/-", &2i).unwrap();
+        assert_eq!(doc.as_str(), "[\n  1, // one\n  2\n]");
+    }
+
+    #[test]
+    fn test_cst_remove() {
+        let mut doc = Document::parse("{ \"a\": 1, \"b\": [1, 2, 3], \"c\": 3 }").unwrap();
+
+        doc.remove("/b/1").unwrap();
+        assert_eq!(doc.as_str(), "{ \"a\": 1, \"b\": [1, 3], \"c\": 3 }");
+
+        doc.remove("/c").unwrap();
+        assert_eq!(doc.as_str(), "{ \"a\": 1, \"b\": [1, 3] }");
+
+        doc.remove("/a").unwrap();
+        assert_eq!(doc.as_str(), "{ \"b\": [1, 3] }");
+
+        doc.remove("/b").unwrap();
+        assert_eq!(doc.as_str(), "{  }");
+
+        assert_eq!(doc.remove("/b"), Err(PathNotFound("/b".to_string())));
+
+        let mut doc = Document::parse_relaxed("[1,]").unwrap();
+        doc.remove("/0").unwrap();
+        assert_eq!(doc.as_str(), "[]");
+    }
+
+    #[test]
+    fn test_cst_errors() {
+        assert_eq!(Document::parse("{\"a\" 1}"), Err(SyntaxError(ExpectedColon, 1, 6, 5)));
+        assert_eq!(Document::parse("[1]\n x"), Err(SyntaxError(TrailingCharacters, 2, 2, 5)));
+        assert!(Document::parse("// comment\n1").is_err());
+        assert!(Document::parse("[1,]").is_err());
+        assert!(Document::parse("[tru]").is_err());
+    }
+
+    #[test]
+    fn test_cst_recovering() {
+        let (value, errors) = parse_recovering(
+            "{\"a\": tru, \"b\": [1, 2 3], \"c\" 4, \"d\": 5", false);
+
+        assert_eq!(value, json::from_str("{\"b\": [1, 2], \"d\": 5}").unwrap());
+        assert_eq!(errors, vec![
+            SyntaxError(InvalidSyntax(SomeIdent), 1, 7, 6),
+            SyntaxError(InvalidSyntax(ListCommaOrEnd), 1, 23, 22),
+            SyntaxError(ExpectedColon, 1, 31, 30),
+            SyntaxError(EOFWhileParsingObject, 1, 40, 39),
+        ]);
+
+        let (value, errors) = parse_recovering("[[1, 2", false);
+        assert_eq!(value, List(vec![List(vec![Integer(1), Integer(2)])]));
+        assert_eq!(errors, vec![SyntaxError(EOFWhileParsingList, 1, 7, 6)]);
+
+        let (value, errors) = parse_recovering("[1, // two\n 2,]", true);
+        assert_eq!(value, List(vec![Integer(1), Integer(2)]));
+        assert_eq!(errors, vec![]);
+
+        let (value, errors) = parse_recovering("1 2", false);
+        assert_eq!((value, errors), (Integer(1), vec![SyntaxError(TrailingCharacters, 1, 3, 2)]));
+
+        let (value, errors) = parse_recovering("", false);
+        assert_eq!((value, errors), (Null, vec![SyntaxError(EOFWhileParsingValue, 1, 1, 0)]));
+
+        // Positions are counted the same way as the parser counts them.
+        let source = "[\"\u00e9\",\n \"\u20ac\" 1]";
+        let (_, errors) = parse_recovering(source, false);
+        assert_eq!(errors, vec![json::from_str::<json::Json>(source).unwrap_err()]);
+        assert_eq!(errors, vec![SyntaxError(InvalidSyntax(ListCommaOrEnd), 2, 6, 14)]);
+    }
+}
@@ -0,0 +1,455 @@
+// Copyright 2012-2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Deserializing typed values out of a borrowed `Json`.
+//!
+//! Unlike `JsonDeserializer`, which consumes the value it reads, the
+//! `Deserializer` here walks a `&Json`, so parts of a document can be read
+//! without giving it up or cloning it. Only strings and raw numbers are
+//! copied, when they are deserialized. Errors record the JSON Pointer of the
+//! value that failed. `Json::decode` and `Json::get_as` are the usual way in.
+//!
+//! ```rust
+//! use serde::json;
+//!
+//! let payload = json::from_str::<json::Json>(
+//!     "{\"user\": {\"id\": 7, \"tags\": [\"a\", 2]}}").unwrap();
+//!
+//! let id: u64 = payload.get_as("/user/id").unwrap();
+//! assert_eq!(id, 7);
+//!
+//! let nickname: Option<String> = payload.get_as("/user/nickname").unwrap();
+//! assert_eq!(nickname, None);
+//!
+//! let err = payload.get_as::<Vec<String>>("/user/tags").unwrap_err();
+//! assert_eq!(err.path, "/user/tags/1".to_string());
+//! ```
+
+use std::collections::treemap;
+use std::fmt;
+use std::slice;
+use std::string;
+
+use de;
+
+use super::{
+    Json,
+    Null,
+    Boolean,
+    Integer,
+    U64,
+    Floating,
+    Number,
+    String,
+    List,
+    Object,
+    ParserError,
+    SyntaxError,
+    ExpectedError,
+    MissingFieldError,
+    UnknownVariantError,
+    EOFWhileParsingValue,
+    DeserializerError,
+    ExpectTokens,
+    ExpectName,
+    ExpectConversion,
+    ExpectExactNumber,
+    escape_pointer_token,
+};
+
+enum State<'a> {
+    ValueState(&'a Json),
+    /// The remaining elements of a list and the index of the next one.
+    ListState(slice::Items<'a, Json>, uint),
+    ObjectState(treemap::Entries<'a, string::String, Json>),
+    /// The remaining fields of an enum variant. These sit two levels below
+    /// the enum object, under the variant name.
+    EnumState(slice::Items<'a, Json>, uint),
+}
+
+/// A deserializer that reads from a borrowed `Json`.
+pub struct Deserializer<'a> {
+    stack: Vec<State<'a>>,
+    base: string::String,
+    /// The tokens of the path to the value most recently read. `None` stands
+    /// for a list or object that hasn't produced an element yet.
+    path: Vec<Option<string::String>>,
+}
+
+impl<'a> Deserializer<'a> {
+    /// Creates a deserializer for `json`.
+    pub fn new(json: &'a Json) -> Deserializer<'a> {
+        Deserializer::at(json, "")
+    }
+
+    /// Creates a deserializer for `json`, which is found at the JSON Pointer
+    /// `pointer` within a larger document. Paths in errors start from there.
+    pub fn at(json: &'a Json, pointer: &str) -> Deserializer<'a> {
+        Deserializer {
+            stack: vec![ValueState(json)],
+            base: pointer.to_string(),
+            path: Vec::new(),
+        }
+    }
+
+    /// Returns a JSON Pointer to the value most recently read.
+    pub fn path(&self) -> string::String {
+        let mut path = self.base.clone();
+        for token in self.path.iter() {
+            match *token {
+                Some(ref token) => {
+                    path.push_str("/");
+                    path.push_str(escape_pointer_token(token.as_slice()).as_slice());
+                }
+                None => { }
+            }
+        }
+        path
+    }
+
+    fn set_last(&mut self, token: string::String) {
+        match self.path.last_mut() {
+            Some(last) => { *last = Some(token); }
+            None => { panic!("state machine error, path is empty"); }
+        }
+    }
+}
+
+impl<'a> Iterator<Result<de::Token, ParserError>> for Deserializer<'a> {
+    #[inline]
+    fn next(&mut self) -> Option<Result<de::Token, ParserError>> {
+        loop {
+            match self.stack.pop() {
+                Some(ValueState(value)) => {
+                    let token = match *value {
+                        Null => de::Null,
+                        Boolean(x) => de::Bool(x),
+                        Integer(x) => de::I64(x),
+                        U64(x) => de::U64(x),
+                        Floating(x) => de::F64(x),
+                        Number(ref x) => de::Number(x.clone()),
+                        String(ref x) => de::String(x.clone()),
+                        List(ref x) => {
+                            self.stack.push(ListState(x.iter(), 0));
+                            self.path.push(None);
+                            de::SeqStart(x.len())
+                        }
+                        Object(ref x) => {
+                            self.stack.push(ObjectState(x.iter()));
+                            self.path.push(None);
+                            de::MapStart(x.len())
+                        }
+                    };
+
+                    return Some(Ok(token));
+                }
+                Some(ListState(mut iter, index)) => {
+                    match iter.next() {
+                        Some(value) => {
+                            self.stack.push(ListState(iter, index + 1));
+                            self.stack.push(ValueState(value));
+                            self.set_last(index.to_string());
+                            // loop around.
+                        }
+                        None => {
+                            self.path.pop();
+                            return Some(Ok(de::End));
+                        }
+                    }
+                }
+                Some(ObjectState(mut iter)) => {
+                    match iter.next() {
+                        Some((key, value)) => {
+                            self.stack.push(ObjectState(iter));
+                            self.stack.push(ValueState(value));
+                            self.set_last(key.clone());
+                            return Some(Ok(de::String(key.clone())));
+                        }
+                        None => {
+                            self.path.pop();
+                            return Some(Ok(de::End));
+                        }
+                    }
+                }
+                Some(EnumState(mut iter, index)) => {
+                    match iter.next() {
+                        Some(value) => {
+                            self.stack.push(EnumState(iter, index + 1));
+                            self.stack.push(ValueState(value));
+                            self.set_last(index.to_string());
+                        }
+                        None => {
+                            self.path.pop();
+                            self.path.pop();
+                            return Some(Ok(de::End));
+                        }
+                    }
+                }
+                None => { return None; }
+            }
+        }
+    }
+}
+
+impl<'a> de::Deserializer<ParserError> for Deserializer<'a> {
+    fn end_of_stream_error(&mut self) -> ParserError {
+        SyntaxError(EOFWhileParsingValue, 0, 0, 0)
+    }
+
+    fn syntax_error(&mut self, token: de::Token, expected: &[de::TokenKind]) -> ParserError {
+        SyntaxError(DeserializerError(token, ExpectTokens(expected.to_vec())), 0, 0, 0)
+    }
+
+    fn unexpected_name_error(&mut self, token: de::Token) -> ParserError {
+        SyntaxError(DeserializerError(token, ExpectName), 0, 0, 0)
+    }
+
+    fn conversion_error(&mut self, token: de::Token) -> ParserError {
+        SyntaxError(DeserializerError(token, ExpectConversion), 0, 0, 0)
+    }
+
+    fn number_conversion_error(&mut self,
+                               token: de::Token,
+                               expected: de::TokenKind) -> ParserError {
+        SyntaxError(DeserializerError(token, ExpectExactNumber(expected)), 0, 0, 0)
+    }
+
+    #[inline]
+    fn missing_field<
+        T: de::Deserializable<Deserializer<'a>, ParserError>
+    >(&mut self, field: &'static str) -> Result<T, ParserError> {
+        // A missing field reads as `null`, so that optional fields may be left
+        // out. Anything else really is missing.
+        match de::Deserializable::deserialize_token(self, de::Null) {
+            Ok(value) => Ok(value),
+            Err(_) => Err(MissingFieldError(field.to_string())),
+        }
+    }
+
+    // Special case treating options as a nullable value.
+    #[inline]
+    fn expect_option_start(&mut self, token: de::Token) -> Result<de::OptionStart, ParserError> {
+        match token {
+            de::Null => Ok(de::NoValue),
+            token => Ok(de::ValueToken(token)),
+        }
+    }
+
+    // Special case treating enums as a `{"variant": [fields...]}` object.
+    #[inline]
+    fn expect_enum_start(&mut self,
+                         token: de::Token,
+                         _name: &str,
+                         variants: &[&str]) -> Result<uint, ParserError> {
+        match token {
+            de::MapStart(_) => { }
+            token => {
+                return Err(ExpectedError("String or Object".to_string(),
+                                         format!("{}", token)))
+            }
+        }
+
+        let mut iter = match self.stack.pop() {
+            Some(ObjectState(iter)) => iter,
+            _ => { panic!("state machine error, expected an object"); }
+        };
+
+        let (variant, fields) = match iter.next() {
+            Some((variant, &List(ref fields))) => (variant, fields),
+            Some((key, value)) => {
+                self.set_last(key.clone());
+                return Err(ExpectedError("List".to_string(), format!("{}", value)));
+            }
+            None => { return Err(MissingFieldError("<variant-name>".to_string())); }
+        };
+
+        // Error out if there are other fields in the enum.
+        match iter.next() {
+            Some((key, value)) => {
+                self.set_last(key.clone());
+                return Err(ExpectedError("None".to_string(), format!("{} => {}", key, value)));
+            }
+            None => { }
+        }
+
+        self.set_last(variant.clone());
+        self.path.push(None);
+        self.stack.push(EnumState(fields.iter(), 0));
+
+        match variants.iter().position(|v| *v == variant.as_slice()) {
+            Some(idx) => Ok(idx),
+            None => Err(UnknownVariantError(variant.clone())),
+        }
+    }
+
+    #[inline]
+    fn expect_struct_start(&mut self, token: de::Token, _name: &str) -> Result<(), ParserError> {
+        match token {
+            de::MapStart(_) => Ok(()),
+            _ => Err(self.syntax_error(token, [de::MapStartKind])),
+        }
+    }
+}
+
+/// An error deserializing from a `Json`, along with where it happened.
+#[deriving(Clone, PartialEq)]
+pub struct DecodeError {
+    /// A JSON Pointer to the value that couldn't be deserialized.
+    pub path: string::String,
+    pub error: ParserError,
+}
+
+impl fmt::Show for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Values have no line and column, so only show the error code.
+        match self.error {
+            SyntaxError(ref code, _, _, _) => try!(code.fmt(f)),
+            ref error => try!(error.fmt(f)),
+        }
+
+        if self.path.is_empty() {
+            write!(f, " at the root")
+        } else {
+            write!(f, " at `{}`", self.path)
+        }
+    }
+}
+
+/// Deserializes `json`, which is found at `pointer` within its document.
+pub fn decode_at<
+    'a,
+    T: de::Deserializable<Deserializer<'a>, ParserError>
+>(json: &'a Json, pointer: &str) -> Result<T, DecodeError> {
+    let mut d = Deserializer::at(json, pointer);
+    match de::Deserializable::deserialize(&mut d) {
+        Ok(value) => Ok(value),
+        Err(err) => Err(DecodeError { path: d.path(), error: err }),
+    }
+}
+
+/// Deserializes a value that isn't present at `pointer`. This only succeeds
+/// for types that can be read from `null`, such as `Option`.
+pub fn decode_missing<
+    'a,
+    T: de::Deserializable<Deserializer<'a>, ParserError>
+>(pointer: &str) -> Result<T, DecodeError> {
+    let mut d = Deserializer {
+        stack: Vec::new(),
+        base: pointer.to_string(),
+        path: Vec::new(),
+    };
+    match de::Deserializable::deserialize_token(&mut d, de::Null) {
+        Ok(value) => Ok(value),
+        Err(_) => {
+            Err(DecodeError {
+                path: pointer.to_string(),
+                error: MissingFieldError(pointer.to_string()),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::TreeMap;
+    use std::string;
+
+    use de;
+    use json::{Json, ExpectedError, MissingFieldError, UnknownVariantError, SyntaxError};
+    use json::{DeserializerError, ExpectTokens};
+    use json;
+
+    #[deriving(PartialEq, Show)]
+    #[deriving_deserializable]
+    struct Item {
+        name: string::String,
+        #[serial_name = "qty"]
+        quantity: uint,
+        note: Option<string::String>,
+    }
+
+    #[deriving(PartialEq, Show)]
+    #[deriving_deserializable]
+    enum Animal {
+        Dog,
+        Frog(string::String, Vec<int>),
+    }
+
+    fn parse(s: &str) -> Json {
+        json::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_decode() {
+        let value = parse("{\"items\": [{\"name\": \"bolt\", \"qty\": 3}], \"count\": 1}");
+
+        let items: Vec<Item> = value.get_as("/items").unwrap();
+        assert_eq!(items, vec![
+            Item { name: "bolt".to_string(), quantity: 3, note: None },
+        ]);
+
+        let item: Item = value.get_as("/items/0").unwrap();
+        assert_eq!(item.name, "bolt".to_string());
+
+        let count: u8 = value.get_as("/count").unwrap();
+        assert_eq!(count, 1);
+
+        let map: TreeMap<string::String, Json> = value.decode().unwrap();
+        assert_eq!(map.len(), 2);
+
+        // The value is only borrowed.
+        assert_eq!(value.decode::<Json>().unwrap(), value);
+
+        assert_eq!(value.get_as::<Option<uint>>("/missing").unwrap(), None);
+
+        let animal: Animal = parse("{\"Frog\": [\"Henry\", [349, 102]]}").decode().unwrap();
+        assert_eq!(animal, Frog("Henry".to_string(), vec![349, 102]));
+        let animal: Animal = parse("{\"Dog\": []}").decode().unwrap();
+        assert_eq!(animal, Dog);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let value = parse(
+            "{\"items\": [{\"name\": \"bolt\", \"qty\": 3}, {\"name\": 1, \"qty\": 3}], \
+              \"a/b\": {\"qty\": 1}, \
+              \"frog\": {\"Frog\": [\"Henry\", [349, \"x\"]]}, \
+              \"cat\": {\"Cat\": []}}");
+
+        let err = value.get_as::<Vec<Item>>("/items").unwrap_err();
+        assert_eq!(err.path, "/items/1/name".to_string());
+        assert_eq!(
+            err.error,
+            SyntaxError(DeserializerError(de::I64(1), ExpectTokens(vec![de::StrKind, de::StringKind])),
+                        0, 0, 0));
+
+        let err = value.get_as::<Item>("/a~1b").unwrap_err();
+        assert_eq!(err.path, "/a~1b".to_string());
+        assert_eq!(err.error, MissingFieldError("name".to_string()));
+        assert_eq!(err.to_string(), "missing field `name` at `/a~1b`".to_string());
+
+        let err = value.get_as::<Animal>("/frog").unwrap_err();
+        assert_eq!(err.path, "/frog/Frog/1/1".to_string());
+
+        let err = value.get_as::<Animal>("/cat").unwrap_err();
+        assert_eq!(err.error, UnknownVariantError("Cat".to_string()));
+
+        let err = value.get_as::<Animal>("/items").unwrap_err();
+        assert!(match err.error { ExpectedError(..) => true, _ => false });
+
+        let err = value.get_as::<uint>("/items/5").unwrap_err();
+        assert_eq!(err.path, "/items/5".to_string());
+        assert_eq!(err.error, MissingFieldError("/items/5".to_string()));
+
+        let err = parse("true").decode::<string::String>().unwrap_err();
+        assert_eq!(err.path, "".to_string());
+        assert_eq!(err.to_string(), "expected a str or a string, found `Bool(true)` at the root".to_string());
+    }
+}
@@ -0,0 +1,497 @@
+// Copyright 2012-2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Structural differences between two `Json` values.
+//!
+//! Objects are compared key by key. Lists are aligned on their longest
+//! common subsequence, so an insertion in the middle of a list is reported as
+//! one addition rather than a change to every element after it. Lists too
+//! long to align that way are compared element by element instead. An element
+//! that was removed in one place and added unchanged in another is reported
+//! as moved. Removed elements that line up with added ones are compared in
+//! turn, so a field changed inside the third object of a list is reported at
+//! `/2/field`.
+//!
+//! Paths are JSON Pointers. Those of removed values and the source of moves
+//! point into the old value, all others into the new one.
+//!
+//! ```rust
+//! use serde::json;
+//!
+//! let a = json::from_str::<json::Json>("{\"port\": 80, \"tags\": [\"a\", \"b\"]}").unwrap();
+//! let b = json::from_str::<json::Json>("{\"port\": 8080, \"tags\": [\"a\", \"c\", \"b\"]}").unwrap();
+//!
+//! let diff = json::diff(&a, &b);
+//! assert_eq!(diff.to_string(), concat!(
+//!     "--- old\n",
+//!     "+++ new\n",
+//!     "@@ /port @@\n",
+//!     "- 80\n",
+//!     "+ 8080\n",
+//!     "@@ /tags/1 @@\n",
+//!     "+ \"c\"\n").to_string());
+//! ```
+
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::MemWriter;
+use std::str;
+use std::string;
+
+use ser::Serializable;
+use super::{Json, List, Object, PrettySerializer, NonFiniteAsLiteral, escape_pointer_token};
+
+/// How a value differs between the old and the new `Json`.
+#[deriving(Clone, PartialEq, Show)]
+pub enum ChangeKind {
+    /// The value only exists in the new `Json`.
+    Added(Json),
+    /// The value only exists in the old `Json`.
+    Removed(Json),
+    /// The old and the new value.
+    Changed(Json, Json),
+    /// A list element that moved unchanged from the given path.
+    Moved(string::String, Json),
+}
+
+/// A single difference, at a JSON Pointer.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Change {
+    pub path: string::String,
+    pub kind: ChangeKind,
+}
+
+/// The differences between two `Json` values. It is shown as a report in
+/// the style of a unified diff, and shows nothing if the values are equal.
+#[deriving(Clone, PartialEq)]
+pub struct Diff {
+    pub changes: Vec<Change>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Show for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.changes.is_empty() {
+            return Ok(());
+        }
+
+        try!(write!(f, "--- old\n+++ new\n"));
+
+        for change in self.changes.iter() {
+            match change.kind {
+                Added(ref value) => {
+                    try!(write_header(f, change.path.as_slice()));
+                    try!(write_value(f, "+ ", value));
+                }
+                Removed(ref value) => {
+                    try!(write_header(f, change.path.as_slice()));
+                    try!(write_value(f, "- ", value));
+                }
+                Changed(ref old, ref new) => {
+                    try!(write_header(f, change.path.as_slice()));
+                    try!(write_value(f, "- ", old));
+                    try!(write_value(f, "+ ", new));
+                }
+                Moved(ref from, _) => {
+                    try!(write!(f, "@@ {} moved to {} @@\n",
+                                display_path(from.as_slice()),
+                                display_path(change.path.as_slice())));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn display_path<'a>(path: &'a str) -> &'a str {
+    if path.is_empty() { "(root)" } else { path }
+}
+
+fn write_header(f: &mut fmt::Formatter, path: &str) -> fmt::Result {
+    write!(f, "@@ {} @@\n", display_path(path))
+}
+
+// Writes the value pretty printed, with `prefix` before every line. `NaN`
+// and the infinities are shown as the bare literals, as `Json`'s `Show` does.
+fn write_value(f: &mut fmt::Formatter, prefix: &str, value: &Json) -> fmt::Result {
+    let mut serializer = PrettySerializer::new(MemWriter::new())
+        .non_finite_floats(NonFiniteAsLiteral);
+    try!(value.serialize(&mut serializer).map_err(|_| fmt::WriteError));
+    let buf = serializer.unwrap().unwrap();
+
+    for line in str::from_utf8(buf.as_slice()).unwrap().lines() {
+        try!(write!(f, "{}{}\n", prefix, line));
+    }
+    Ok(())
+}
+
+/// Compares two `Json` values.
+pub fn diff(old: &Json, new: &Json) -> Diff {
+    let mut changes = Vec::new();
+    diff_values(old, new, "", &mut changes);
+    Diff { changes: changes }
+}
+
+fn child_path(path: &str, token: &str) -> string::String {
+    format!("{}/{}", path, escape_pointer_token(token))
+}
+
+fn index_path(path: &str, index: uint) -> string::String {
+    format!("{}/{}", path, index)
+}
+
+fn diff_values(old: &Json, new: &Json, path: &str, changes: &mut Vec<Change>) {
+    match (old, new) {
+        (&Object(ref old), &Object(ref new)) => {
+            for (key, value) in old.iter() {
+                match new.find(key) {
+                    Some(new_value) => {
+                        diff_values(value, new_value, child_path(path, key.as_slice()).as_slice(),
+                                    changes);
+                    }
+                    None => {
+                        changes.push(Change {
+                            path: child_path(path, key.as_slice()),
+                            kind: Removed(value.clone()),
+                        });
+                    }
+                }
+            }
+
+            for (key, value) in new.iter() {
+                if !old.contains_key(key) {
+                    changes.push(Change {
+                        path: child_path(path, key.as_slice()),
+                        kind: Added(value.clone()),
+                    });
+                }
+            }
+        }
+        (&List(ref old), &List(ref new)) => {
+            diff_lists(old.as_slice(), new.as_slice(), path, changes);
+        }
+        _ => {
+            if old != new {
+                changes.push(Change {
+                    path: path.to_string(),
+                    kind: Changed(old.clone(), new.clone()),
+                });
+            }
+        }
+    }
+}
+
+// The elements removed from and added to a list between two elements that
+// were kept.
+struct Gap {
+    removed: Vec<uint>,
+    added: Vec<uint>,
+}
+
+fn diff_lists(old: &[Json], new: &[Json], path: &str, changes: &mut Vec<Change>) {
+    let gaps = align(old, new);
+
+    // Find the removed elements that were added back unchanged elsewhere.
+    let mut moved_to: Vec<Option<uint>> = Vec::from_elem(old.len(), None);
+    let mut moved_from: Vec<Option<uint>> = Vec::from_elem(new.len(), None);
+
+    // The added elements are grouped by their text, so that each removed
+    // element is only compared with those that are likely equal. Each group
+    // is in reverse, so the first element added is the cheapest to take.
+    let mut added_by_text: HashMap<string::String, Vec<uint>> = HashMap::new();
+    for gap in gaps.iter().rev() {
+        for &j in gap.added.iter().rev() {
+            let text = new[j].to_string();
+            let found = match added_by_text.find_mut(&text) {
+                Some(added) => {
+                    added.push(j);
+                    true
+                }
+                None => false,
+            };
+
+            if !found {
+                added_by_text.insert(text, vec![j]);
+            }
+        }
+    }
+
+    for gap in gaps.iter() {
+        for &i in gap.removed.iter() {
+            let found = match added_by_text.find_mut(&old[i].to_string()) {
+                Some(added) => {
+                    match added.iter().rposition(|&j| old[i] == new[j]) {
+                        Some(k) => added.remove(k),
+                        None => None,
+                    }
+                }
+                None => None,
+            };
+
+            match found {
+                Some(j) => {
+                    moved_to.as_mut_slice()[i] = Some(j);
+                    moved_from.as_mut_slice()[j] = Some(i);
+                }
+                None => { }
+            }
+        }
+    }
+
+    for gap in gaps.iter() {
+        for &i in gap.removed.iter() {
+            match moved_to.as_slice()[i] {
+                Some(j) => {
+                    changes.push(Change {
+                        path: index_path(path, j),
+                        kind: Moved(index_path(path, i), new[j].clone()),
+                    });
+                }
+                None => { }
+            }
+        }
+
+        let removed: Vec<uint> = gap.removed.iter()
+            .map(|&i| i)
+            .filter(|&i| moved_to.as_slice()[i].is_none())
+            .collect();
+        let added: Vec<uint> = gap.added.iter()
+            .map(|&j| j)
+            .filter(|&j| moved_from.as_slice()[j].is_none())
+            .collect();
+
+        // Elements that take each other's place are compared.
+        for (&i, &j) in removed.iter().zip(added.iter()) {
+            diff_values(&old[i], &new[j], index_path(path, j).as_slice(), changes);
+        }
+
+        let paired = cmp::min(removed.len(), added.len());
+
+        for &i in removed.slice_from(paired).iter() {
+            changes.push(Change {
+                path: index_path(path, i),
+                kind: Removed(old[i].clone()),
+            });
+        }
+
+        for &j in added.slice_from(paired).iter() {
+            changes.push(Change {
+                path: index_path(path, j),
+                kind: Added(new[j].clone()),
+            });
+        }
+    }
+}
+
+// Lists whose differing middles would need a larger table than this to
+// align are compared position by position instead.
+static MAX_TABLE: uint = 1 << 20;
+
+// Aligns two lists on their longest common subsequence, and returns what
+// differs between the elements they have in common.
+fn align(old: &[Json], new: &[Json]) -> Vec<Gap> {
+    // Common ends are skipped to keep the table small.
+    let mut start = 0;
+    while start < old.len() && start < new.len() && old[start] == new[start] {
+        start += 1;
+    }
+
+    let mut old_end = old.len();
+    let mut new_end = new.len();
+    while old_end > start && new_end > start && old[old_end - 1] == new[new_end - 1] {
+        old_end -= 1;
+        new_end -= 1;
+    }
+
+    let old_mid = old.slice(start, old_end);
+    let new_mid = new.slice(start, new_end);
+    let width = new_mid.len() + 1;
+
+    match (old_mid.len() + 1).checked_mul(&width) {
+        Some(size) if size <= MAX_TABLE => { }
+        _ => { return align_positions(start, old_end, new_end, old, new); }
+    }
+
+    // `table[i * width + j]` is the length of the longest common subsequence
+    // of `old_mid[i..]` and `new_mid[j..]`.
+    let mut table = Vec::from_elem((old_mid.len() + 1) * width, 0u);
+    for i in range(0, old_mid.len()).rev() {
+        for j in range(0, new_mid.len()).rev() {
+            let len = if old_mid[i] == new_mid[j] {
+                table.as_slice()[(i + 1) * width + j + 1] + 1
+            } else {
+                cmp::max(table.as_slice()[(i + 1) * width + j],
+                         table.as_slice()[i * width + j + 1])
+            };
+            table.as_mut_slice()[i * width + j] = len;
+        }
+    }
+
+    let mut gaps = Vec::new();
+    let mut gap = Gap { removed: Vec::new(), added: Vec::new() };
+    let (mut i, mut j) = (0, 0);
+
+    while i < old_mid.len() || j < new_mid.len() {
+        if i < old_mid.len() && j < new_mid.len() && old_mid[i] == new_mid[j] {
+            if !gap.removed.is_empty() || !gap.added.is_empty() {
+                gaps.push(gap);
+                gap = Gap { removed: Vec::new(), added: Vec::new() };
+            }
+            i += 1;
+            j += 1;
+        } else if j == new_mid.len() ||
+                  (i < old_mid.len() &&
+                   table.as_slice()[(i + 1) * width + j] >= table.as_slice()[i * width + j + 1]) {
+            gap.removed.push(start + i);
+            i += 1;
+        } else {
+            gap.added.push(start + j);
+            j += 1;
+        }
+    }
+
+    if !gap.removed.is_empty() || !gap.added.is_empty() {
+        gaps.push(gap);
+    }
+
+    gaps
+}
+
+// Pairs up the elements of two lists by their position from `start`, with
+// one gap for each pair that differs and one for the elements only one list
+// has.
+fn align_positions(start: uint,
+                   old_end: uint,
+                   new_end: uint,
+                   old: &[Json],
+                   new: &[Json]) -> Vec<Gap> {
+    let end = cmp::min(old_end, new_end);
+
+    let mut gaps: Vec<Gap> = range(start, end)
+        .filter(|&i| old[i] != new[i])
+        .map(|i| Gap { removed: vec![i], added: vec![i] })
+        .collect();
+
+    if old_end != new_end {
+        gaps.push(Gap {
+            removed: range(end, old_end).collect(),
+            added: range(end, new_end).collect(),
+        });
+    }
+
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64;
+
+    use json::{Json, Integer, Floating, Null};
+    use json;
+    use super::{diff, Change, Added, Removed, Changed, Moved};
+
+    fn parse(s: &str) -> Json {
+        json::from_str(s).unwrap()
+    }
+
+    fn changes(old: &str, new: &str) -> Vec<Change> {
+        diff(&parse(old), &parse(new)).changes
+    }
+
+    fn change(path: &str, kind: super::ChangeKind) -> Change {
+        Change { path: path.to_string(), kind: kind }
+    }
+
+    #[test]
+    fn test_diff_objects() {
+        assert!(diff(&parse("{\"a\": [1, {}]}"), &parse("{\"a\": [1, {}]}")).is_empty());
+        assert_eq!(diff(&parse("1"), &parse("1")).to_string(), "".to_string());
+
+        assert_eq!(changes("{\"a\": 1, \"b/c\": 2, \"d\": {\"e\": true}}",
+                           "{\"a\": 1, \"d\": {\"e\": false}, \"f\": null}"),
+                   vec![
+                       change("/b~1c", Removed(Integer(2))),
+                       change("/d/e", Changed(json::Boolean(true), json::Boolean(false))),
+                       change("/f", Added(json::Null)),
+                   ]);
+
+        assert_eq!(changes("{\"a\": 1}", "[1]"),
+                   vec![change("", Changed(parse("{\"a\": 1}"), parse("[1]")))]);
+    }
+
+    #[test]
+    fn test_diff_lists() {
+        assert_eq!(changes("[1, 2, 3, 4]", "[1, 3, 5, 4, 6]"),
+                   vec![
+                       change("/1", Removed(Integer(2))),
+                       change("/2", Added(Integer(5))),
+                       change("/4", Added(Integer(6))),
+                   ]);
+
+        assert_eq!(changes("[{\"id\": 1, \"x\": 1}, {\"id\": 2, \"x\": 1}]",
+                           "[{\"id\": 1, \"x\": 1}, {\"id\": 2, \"x\": 2}]"),
+                   vec![change("/1/x", Changed(Integer(1), Integer(2)))]);
+
+        assert_eq!(changes("[\"a\", \"b\", \"c\", \"d\"]", "[\"d\", \"a\", \"b\", \"c\"]"),
+                   vec![change("/0", Moved("/3".to_string(), json::String("d".to_string())))]);
+    }
+
+    #[test]
+    fn test_diff_long_lists() {
+        // Too long to align, so the elements are compared by position.
+        let mut old = Vec::from_elem(1100, Integer(0));
+        *old.get_mut(0) = Integer(1);
+        *old.get_mut(1099) = Integer(1);
+
+        let mut new = old.clone();
+        *new.get_mut(0) = Integer(3);
+        *new.get_mut(550) = Integer(2);
+        *new.get_mut(1099) = Integer(3);
+        new.push(Integer(4));
+
+        assert_eq!(diff(&json::List(old), &json::List(new)).changes,
+                   vec![
+                       change("/0", Changed(Integer(1), Integer(3))),
+                       change("/550", Changed(Integer(0), Integer(2))),
+                       change("/1099", Changed(Integer(1), Integer(3))),
+                       change("/1100", Added(Integer(4))),
+                   ]);
+    }
+
+    #[test]
+    fn test_diff_report() {
+        let diff = diff(&parse("{\"a\": {\"b\": 1}, \"l\": [1, 2, 3]}"),
+                        &parse("{\"a\": [true], \"l\": [3, 1, 2]}"));
+        assert_eq!(diff.to_string(), concat!(
+            "--- old\n",
+            "+++ new\n",
+            "@@ /a @@\n",
+            "- {\n",
+            "-   \"b\": 1\n",
+            "- }\n",
+            "+ [\n",
+            "+   true\n",
+            "+ ]\n",
+            "@@ /l/2 moved to /l/0 @@\n").to_string());
+
+        assert_eq!(super::diff(&parse("1"), &parse("2")).to_string(),
+                   "--- old\n+++ new\n@@ (root) @@\n- 1\n+ 2\n".to_string());
+
+        assert_eq!(super::diff(&Floating(f64::NAN), &Null).to_string(),
+                   "--- old\n+++ new\n@@ (root) @@\n- NaN\n+ null\n".to_string());
+    }
+}
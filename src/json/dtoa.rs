@@ -0,0 +1,684 @@
+// Copyright 2012-2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Formats floats as the shortest decimal string that parses back to the
+//! exact same value.
+//!
+//! Digits are generated with Grisu3, which is fast but gives up on roughly
+//! half a percent of inputs. Those are handled by an exact algorithm in the
+//! style of Steele & White's Dragon4, which works on big integers.
+
+use std::io::{IoResult, Writer};
+use std::mem;
+
+use super::bignum::Big;
+
+/// A finite, positive float decomposed into `mant * 2^exp`, with the values
+/// that still round to it lying in `(mant - minus) * 2^exp` to
+/// `(mant + plus) * 2^exp`.
+pub struct Decoded {
+    pub mant: u64,
+    pub minus: u64,
+    pub plus: u64,
+    pub exp: int,
+    /// Whether the boundaries themselves round to the value, which happens
+    /// when the mantissa is even because of round-half-to-even.
+    pub inclusive: bool,
+}
+
+/// Decodes a finite, non-zero `f64`. The sign is ignored.
+pub fn decode_f64(v: f64) -> Decoded {
+    let bits: u64 = unsafe { mem::transmute(v) };
+    let biased = ((bits >> 52) & 0x7ff) as int;
+    let frac = bits & ((1u64 << 52) - 1);
+    decode(frac, biased, 52, 1075)
+}
+
+/// Decodes a finite, non-zero `f32`. The sign is ignored.
+pub fn decode_f32(v: f32) -> Decoded {
+    let bits: u32 = unsafe { mem::transmute(v) };
+    let biased = ((bits >> 23) & 0xff) as int;
+    let frac = (bits & ((1u32 << 23) - 1)) as u64;
+    decode(frac, biased, 23, 150)
+}
+
+fn decode(frac: u64, biased: int, frac_bits: uint, bias: int) -> Decoded {
+    if biased == 0 {
+        // Subnormals have evenly spaced neighbours.
+        Decoded {
+            mant: frac << 1,
+            minus: 1,
+            plus: 1,
+            exp: -bias,
+            inclusive: frac & 1 == 0,
+        }
+    } else {
+        let mant = frac | (1u64 << frac_bits);
+        let exp = biased - bias;
+
+        if frac == 0 && biased > 1 {
+            // The predecessor of a power of two is twice as close as its
+            // successor.
+            Decoded {
+                mant: mant << 2,
+                minus: 1,
+                plus: 2,
+                exp: exp - 2,
+                inclusive: true,
+            }
+        } else {
+            Decoded {
+                mant: mant << 1,
+                minus: 1,
+                plus: 1,
+                exp: exp - 1,
+                inclusive: mant & 1 == 0,
+            }
+        }
+    }
+}
+
+/// Writes the shortest digits of `d` into `buf`, which must hold at least
+/// 17 bytes. Returns the number of digits and the exponent
+/// `k` such that the value is `0.d1d2...dn * 10^k`.
+pub fn shortest(d: &Decoded, buf: &mut [u8]) -> (uint, int) {
+    match grisu(d, buf) {
+        Some(result) => result,
+        None => dragon(d, buf),
+    }
+}
+
+/// Writes a finite `f64` in the shortest form that round trips. Integral
+/// values get a trailing `.0` so that they are read back as floats.
+pub fn write_f64<W: Writer>(wr: &mut W, v: f64) -> IoResult<()> {
+    let bits: u64 = unsafe { mem::transmute(v) };
+    let negative = bits >> 63 == 1;
+
+    if v == 0.0 {
+        return write_digits(wr, negative, b"0", 1, true);
+    }
+
+    let mut buf = [0u8, .. 17];
+    let (len, exp) = shortest(&decode_f64(v), &mut buf);
+    write_digits(wr, negative, buf.slice_to(len), exp, true)
+}
+
+/// Like `write_f64`, but only as precise as an `f32` needs.
+pub fn write_f32<W: Writer>(wr: &mut W, v: f32) -> IoResult<()> {
+    let bits: u32 = unsafe { mem::transmute(v) };
+    let negative = bits >> 31 == 1;
+
+    if v == 0.0 {
+        return write_digits(wr, negative, b"0", 1, true);
+    }
+
+    let mut buf = [0u8, .. 17];
+    let (len, exp) = shortest(&decode_f32(v), &mut buf);
+    write_digits(wr, negative, buf.slice_to(len), exp, true)
+}
+
+/// Writes a finite `f64` the way ECMAScript's `Number.prototype.toString`
+/// does. Unlike `write_f64`, integral values have no `.0` and `-0` is written
+/// as `0`.
+pub fn write_f64_ecmascript<W: Writer>(wr: &mut W, v: f64) -> IoResult<()> {
+    if v == 0.0 {
+        return wr.write(b"0");
+    }
+
+    let mut buf = [0u8, .. 17];
+    let (len, exp) = shortest(&decode_f64(v), &mut buf);
+    write_digits(wr, v < 0.0, buf.slice_to(len), exp, false)
+}
+
+/// Lays out the digits of `0.d1d2...dn * 10^exp` the way ECMAScript's
+/// `Number.prototype.toString` does: plain decimal notation for exponents
+/// from -6 to 21, scientific notation otherwise. With `keep_fraction`,
+/// integral values written in decimal notation get a trailing `.0`.
+pub fn write_digits<W: Writer>(wr: &mut W,
+                               negative: bool,
+                               digits: &[u8],
+                               exp: int,
+                               keep_fraction: bool) -> IoResult<()> {
+    let len = digits.len() as int;
+
+    if negative {
+        try!(wr.write_u8(b'-'));
+    }
+
+    if 0 < exp && exp <= 21 {
+        if len <= exp {
+            try!(wr.write(digits));
+            try!(write_zeros(wr, exp - len));
+
+            if keep_fraction {
+                try!(wr.write(b".0"));
+            }
+
+            Ok(())
+        } else {
+            try!(wr.write(digits.slice_to(exp as uint)));
+            try!(wr.write_u8(b'.'));
+            wr.write(digits.slice_from(exp as uint))
+        }
+    } else if -6 < exp && exp <= 0 {
+        try!(wr.write(b"0."));
+        try!(write_zeros(wr, -exp));
+        wr.write(digits)
+    } else {
+        try!(wr.write(digits.slice_to(1)));
+
+        if len > 1 {
+            try!(wr.write_u8(b'.'));
+            try!(wr.write(digits.slice_from(1)));
+        }
+
+        if exp - 1 >= 0 {
+            write!(wr, "e+{}", exp - 1)
+        } else {
+            write!(wr, "e{}", exp - 1)
+        }
+    }
+}
+
+fn write_zeros<W: Writer>(wr: &mut W, mut n: int) -> IoResult<()> {
+    while n > 0 {
+        try!(wr.write_u8(b'0'));
+        n -= 1;
+    }
+    Ok(())
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+/// A float with a 64 bit mantissa, `f * 2^e`.
+struct Fp {
+    f: u64,
+    e: int,
+}
+
+impl Fp {
+    /// Multiplies the mantissas, keeping the rounded upper 64 bits.
+    fn mul(&self, other: &Fp) -> Fp {
+        let mask = 0xffffffffu64;
+        let a = self.f >> 32;
+        let b = self.f & mask;
+        let c = other.f >> 32;
+        let d = other.f & mask;
+        let ac = a * c;
+        let bc = b * c;
+        let ad = a * d;
+        let bd = b * d;
+        let tmp = (bd >> 32) + (ad & mask) + (bc & mask) + (1u64 << 31);
+        Fp {
+            f: ac + (ad >> 32) + (bc >> 32) + (tmp >> 32),
+            e: self.e + other.e + 64,
+        }
+    }
+
+    fn normalize(&self) -> Fp {
+        let mut f = self.f;
+        let mut e = self.e;
+        while f & (1u64 << 63) == 0 {
+            f <<= 1;
+            e -= 1;
+        }
+        Fp { f: f, e: e }
+    }
+
+    fn normalize_to(&self, e: int) -> Fp {
+        let shift = (self.e - e) as uint;
+        let f = self.f << shift;
+        debug_assert_eq!(f >> shift, self.f);
+        Fp { f: f, e: e }
+    }
+}
+
+/// Normalized approximations of `10^k` as `(f, e, k)`, with `10^k ~= f * 2^e`.
+static CACHED_POW10: [(u64, i16, i16), .. 87] = [
+    (0xfa8fd5a0081c0288, -1220, -348),
+    (0xbaaee17fa23ebf76, -1193, -340),
+    (0x8b16fb203055ac76, -1166, -332),
+    (0xcf42894a5dce35ea, -1140, -324),
+    (0x9a6bb0aa55653b2d, -1113, -316),
+    (0xe61acf033d1a45df, -1087, -308),
+    (0xab70fe17c79ac6ca, -1060, -300),
+    (0xff77b1fcbebcdc4f, -1034, -292),
+    (0xbe5691ef416bd60c, -1007, -284),
+    (0x8dd01fad907ffc3c, -980, -276),
+    (0xd3515c2831559a83, -954, -268),
+    (0x9d71ac8fada6c9b5, -927, -260),
+    (0xea9c227723ee8bcb, -901, -252),
+    (0xaecc49914078536d, -874, -244),
+    (0x823c12795db6ce57, -847, -236),
+    (0xc21094364dfb5637, -821, -228),
+    (0x9096ea6f3848984f, -794, -220),
+    (0xd77485cb25823ac7, -768, -212),
+    (0xa086cfcd97bf97f4, -741, -204),
+    (0xef340a98172aace5, -715, -196),
+    (0xb23867fb2a35b28e, -688, -188),
+    (0x84c8d4dfd2c63f3b, -661, -180),
+    (0xc5dd44271ad3cdba, -635, -172),
+    (0x936b9fcebb25c996, -608, -164),
+    (0xdbac6c247d62a584, -582, -156),
+    (0xa3ab66580d5fdaf6, -555, -148),
+    (0xf3e2f893dec3f126, -529, -140),
+    (0xb5b5ada8aaff80b8, -502, -132),
+    (0x87625f056c7c4a8b, -475, -124),
+    (0xc9bcff6034c13053, -449, -116),
+    (0x964e858c91ba2655, -422, -108),
+    (0xdff9772470297ebd, -396, -100),
+    (0xa6dfbd9fb8e5b88f, -369, -92),
+    (0xf8a95fcf88747d94, -343, -84),
+    (0xb94470938fa89bcf, -316, -76),
+    (0x8a08f0f8bf0f156b, -289, -68),
+    (0xcdb02555653131b6, -263, -60),
+    (0x993fe2c6d07b7fac, -236, -52),
+    (0xe45c10c42a2b3b06, -210, -44),
+    (0xaa242499697392d3, -183, -36),
+    (0xfd87b5f28300ca0e, -157, -28),
+    (0xbce5086492111aeb, -130, -20),
+    (0x8cbccc096f5088cc, -103, -12),
+    (0xd1b71758e219652c, -77, -4),
+    (0x9c40000000000000, -50, 4),
+    (0xe8d4a51000000000, -24, 12),
+    (0xad78ebc5ac620000, 3, 20),
+    (0x813f3978f8940984, 30, 28),
+    (0xc097ce7bc90715b3, 56, 36),
+    (0x8f7e32ce7bea5c70, 83, 44),
+    (0xd5d238a4abe98068, 109, 52),
+    (0x9f4f2726179a2245, 136, 60),
+    (0xed63a231d4c4fb27, 162, 68),
+    (0xb0de65388cc8ada8, 189, 76),
+    (0x83c7088e1aab65db, 216, 84),
+    (0xc45d1df942711d9a, 242, 92),
+    (0x924d692ca61be758, 269, 100),
+    (0xda01ee641a708dea, 295, 108),
+    (0xa26da3999aef774a, 322, 116),
+    (0xf209787bb47d6b85, 348, 124),
+    (0xb454e4a179dd1877, 375, 132),
+    (0x865b86925b9bc5c2, 402, 140),
+    (0xc83553c5c8965d3d, 428, 148),
+    (0x952ab45cfa97a0b3, 455, 156),
+    (0xde469fbd99a05fe3, 481, 164),
+    (0xa59bc234db398c25, 508, 172),
+    (0xf6c69a72a3989f5c, 534, 180),
+    (0xb7dcbf5354e9bece, 561, 188),
+    (0x88fcf317f22241e2, 588, 196),
+    (0xcc20ce9bd35c78a5, 614, 204),
+    (0x98165af37b2153df, 641, 212),
+    (0xe2a0b5dc971f303a, 667, 220),
+    (0xa8d9d1535ce3b396, 694, 228),
+    (0xfb9b7cd9a4a7443c, 720, 236),
+    (0xbb764c4ca7a44410, 747, 244),
+    (0x8bab8eefb6409c1a, 774, 252),
+    (0xd01fef10a657842c, 800, 260),
+    (0x9b10a4e5e9913129, 827, 268),
+    (0xe7109bfba19c0c9d, 853, 276),
+    (0xac2820d9623bf429, 880, 284),
+    (0x80444b5e7aa7cf85, 907, 292),
+    (0xbf21e44003acdd2d, 933, 300),
+    (0x8e679c2f5e44ff8f, 960, 308),
+    (0xd433179d9c8cb841, 986, 316),
+    (0x9e19db92b4e31ba9, 1013, 324),
+    (0xeb96bf6ebadf77d9, 1039, 332),
+    (0xaf87023b9bf0ee6b, 1066, 340),
+];
+
+static ALPHA: int = -60;
+static GAMMA: int = -32;
+
+/// Finds a cached `10^k` whose binary exponent lies in `alpha..gamma`.
+fn cached_power(alpha: int, gamma: int) -> (int, Fp) {
+    for &(f, e, k) in CACHED_POW10.iter() {
+        if e as int >= alpha {
+            debug_assert!(e as int <= gamma);
+            return (k as int, Fp { f: f, e: e as int });
+        }
+    }
+
+    panic!("no cached power of ten for {}", alpha)
+}
+
+/// Returns the largest `kappa` with `10^kappa <= x`, along with `10^kappa`.
+fn max_pow10_no_more_than(x: u32) -> (uint, u32) {
+    let mut kappa = 0u;
+    let mut ten_kappa = 1u32;
+    while kappa < 9 && ten_kappa * 10 <= x {
+        kappa += 1;
+        ten_kappa *= 10;
+    }
+    (kappa, ten_kappa)
+}
+
+/// Grisu3. Returns `None` when it can't prove the result is the shortest
+/// correctly rounded one.
+fn grisu(d: &Decoded, buf: &mut [u8]) -> Option<(uint, int)> {
+    let plus = Fp { f: d.mant + d.plus, e: d.exp }.normalize();
+    let minus = Fp { f: d.mant - d.minus, e: d.exp }.normalize_to(plus.e);
+    let v = Fp { f: d.mant, e: d.exp }.normalize_to(plus.e);
+
+    // Scale everything so that the binary exponent lands in a small window,
+    // which lets us split the upper boundary into integral and fractional
+    // parts that both fit in a u64.
+    let (minusk, cached) = cached_power(ALPHA - plus.e - 64, GAMMA - plus.e - 64);
+    let plus = plus.mul(&cached);
+    let minus = minus.mul(&cached);
+    let v = v.mul(&cached);
+
+    // Widen the interval by one unit to account for the rounding errors of
+    // the multiplications. This is what makes the result uncertain.
+    let plus1 = plus.f + 1;
+    let minus1 = minus.f - 1;
+    let e = (-plus.e) as uint;
+    let one = 1u64 << e;
+    let plus1int = (plus1 >> e) as u32;
+    let plus1frac = plus1 & (one - 1);
+
+    let (max_kappa, max_ten_kappa) = max_pow10_no_more_than(plus1int);
+    let exp = max_kappa as int - minusk + 1;
+
+    let delta1 = plus1 - minus1;
+    let delta1frac = delta1 & (one - 1);
+
+    // Generate digits from the integral part until we fall inside the
+    // interval.
+    let mut i = 0u;
+    let mut ten_kappa = max_ten_kappa;
+    let mut remainder = plus1int;
+    loop {
+        let q = remainder / ten_kappa;
+        let r = remainder % ten_kappa;
+        buf[i] = b'0' + q as u8;
+        i += 1;
+
+        let plus1rem = ((r as u64) << e) + plus1frac;
+        if plus1rem < delta1 {
+            let ten_kappa = (ten_kappa as u64) << e;
+            return round_and_weed(buf, i, exp, plus1rem, delta1, plus1 - v.f, ten_kappa, 1);
+        }
+
+        if i > max_kappa {
+            break;
+        }
+
+        ten_kappa /= 10;
+        remainder = r;
+    }
+
+    // Then from the fractional part, tracking the error as it grows.
+    let mut remainder = plus1frac;
+    let mut threshold = delta1frac;
+    let mut ulp = 1u64;
+    loop {
+        remainder *= 10;
+        threshold *= 10;
+        ulp *= 10;
+
+        let q = remainder >> e;
+        let r = remainder & (one - 1);
+        buf[i] = b'0' + q as u8;
+        i += 1;
+
+        if r < threshold {
+            return round_and_weed(buf, i, exp, r, threshold, (plus1 - v.f) * ulp, one, ulp);
+        }
+
+        remainder = r;
+    }
+}
+
+/// Moves the last digit down towards the exact value while that stays
+/// inside the interval, then checks that the result is unambiguous.
+fn round_and_weed(buf: &mut [u8],
+                  len: uint,
+                  exp: int,
+                  remainder: u64,
+                  threshold: u64,
+                  plus1v: u64,
+                  ten_kappa: u64,
+                  ulp: u64) -> Option<(uint, int)> {
+    let plus1v_down = plus1v + ulp;
+    let plus1v_up = plus1v - ulp;
+
+    let mut plus1w = remainder;
+    while plus1w < plus1v_up &&
+          threshold - plus1w >= ten_kappa &&
+          (plus1w + ten_kappa < plus1v_up ||
+           plus1v_up - plus1w >= plus1w + ten_kappa - plus1v_up) {
+        buf[len - 1] -= 1;
+        plus1w += ten_kappa;
+    }
+
+    // If the same could be done for the other end of the uncertainty, we
+    // can't tell which one is closer.
+    if plus1w < plus1v_down &&
+       threshold - plus1w >= ten_kappa &&
+       (plus1w + ten_kappa < plus1v_down ||
+        plus1v_down - plus1w >= plus1w + ten_kappa - plus1v_down) {
+        return None;
+    }
+
+    if 2 * ulp <= plus1w && plus1w <= threshold - 4 * ulp {
+        Some((len, exp))
+    } else {
+        None
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+
+/// Returns `k` such that `10^(k-1) <= mant * 2^exp < 10^(k+1)`.
+fn estimate_scaling_factor(mant: u64, exp: int) -> int {
+    let mut nbits = 0i64;
+    let mut m = mant - 1;
+    while m > 0 {
+        nbits += 1;
+        m >>= 1;
+    }
+
+    // 1292913986 is floor(2^32 * log10(2)), so this never overestimates.
+    (((nbits + exp as i64) * 1292913986) >> 32) as int
+}
+
+/// Generates digits exactly, stopping as soon as they uniquely identify the
+/// value.
+fn dragon(d: &Decoded, buf: &mut [u8]) -> (uint, int) {
+    let mut k = estimate_scaling_factor(d.mant + d.plus, d.exp);
+
+    // Everything is kept scaled so that the value is `mant / scale`.
+    let mut mant = Big::from_u64(d.mant);
+    let mut minus = Big::from_u64(d.minus);
+    let mut plus = Big::from_u64(d.plus);
+    let mut scale = Big::from_u64(1);
+
+    if d.exp < 0 {
+        scale.mul_pow2((-d.exp) as uint);
+    } else {
+        mant.mul_pow2(d.exp as uint);
+        minus.mul_pow2(d.exp as uint);
+        plus.mul_pow2(d.exp as uint);
+    }
+
+    if k >= 0 {
+        scale.mul_pow10(k as uint);
+    } else {
+        mant.mul_pow10((-k) as uint);
+        minus.mul_pow10((-k) as uint);
+        plus.mul_pow10((-k) as uint);
+    }
+
+    // Fix up the estimate so that the first digit is non-zero.
+    if reaches(d, &scale, &mant.add(&plus)) {
+        k += 1;
+    } else {
+        mant.mul_small(10);
+        minus.mul_small(10);
+        plus.mul_small(10);
+    }
+
+    let mut i = 0u;
+    let mut down;
+    let mut up;
+    loop {
+        let mut digit = 0u8;
+        while mant >= scale {
+            mant.sub(&scale);
+            digit += 1;
+        }
+        buf[i] = b'0' + digit;
+        i += 1;
+
+        // Stop once rounding down or up would stay inside the interval.
+        down = reaches(d, &mant, &minus);
+        up = reaches(d, &scale, &mant.add(&plus));
+        if down || up {
+            break;
+        }
+
+        mant.mul_small(10);
+        minus.mul_small(10);
+        plus.mul_small(10);
+    }
+
+    // Round up if that's the only option or if it's the closer one.
+    if up {
+        let mut twice = mant.clone();
+        twice.mul_pow2(1);
+
+        if !down || twice >= scale {
+            let mut j = i;
+            while j > 0 && buf[j - 1] == b'9' {
+                j -= 1;
+            }
+
+            if j == 0 {
+                // All nines, so the digits become `100...` one place up.
+                buf[0] = b'1';
+                for digit in buf.slice_mut(1, i).iter_mut() {
+                    *digit = b'0';
+                }
+                k += 1;
+            } else {
+                buf[j - 1] += 1;
+                for digit in buf.slice_mut(j, i).iter_mut() {
+                    *digit = b'0';
+                }
+            }
+        }
+    }
+
+    (i, k)
+}
+
+/// Whether `a < b`, or `a <= b` if the boundaries are inclusive.
+#[inline]
+fn reaches(d: &Decoded, a: &Big, b: &Big) -> bool {
+    if d.inclusive { a <= b } else { a < b }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use std::f64;
+    use std::io::MemWriter;
+    use std::str;
+
+    use super::{write_f32, write_f64, decode_f64, grisu, dragon};
+
+    fn to_string(v: f64) -> String {
+        let mut wr = MemWriter::new();
+        write_f64(&mut wr, v).unwrap();
+        str::from_utf8(wr.unwrap().as_slice()).unwrap().to_string()
+    }
+
+    fn f32_to_string(v: f32) -> String {
+        let mut wr = MemWriter::new();
+        write_f32(&mut wr, v).unwrap();
+        str::from_utf8(wr.unwrap().as_slice()).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_write_f64() {
+        let tests = [
+            (0.0, "0.0"),
+            (-0.0, "-0.0"),
+            (1.0, "1.0"),
+            (-3.0, "-3.0"),
+            (0.1, "0.1"),
+            (0.3, "0.3"),
+            (1.5, "1.5"),
+            (123.456, "123.456"),
+            (1e20, "100000000000000000000.0"),
+            (1e21, "1e+21"),
+            (1e23, "1e+23"),
+            (0.000001, "0.000001"),
+            (1.5e-7, "1.5e-7"),
+            (9007199254740993.0, "9007199254740992.0"),
+            (f64::MAX_VALUE, "1.7976931348623157e+308"),
+            (f64::MIN_POS_VALUE, "2.2250738585072014e-308"),
+            (5e-324, "5e-324"),
+        ];
+
+        for &(v, s) in tests.iter() {
+            assert_eq!(to_string(v), s.to_string());
+        }
+    }
+
+    #[test]
+    fn test_write_f32() {
+        let tests = [
+            (0.0f32, "0.0"),
+            (1.0, "1.0"),
+            (0.1, "0.1"),
+            (3.4028235e38, "3.4028235e+38"),
+            (f32::MIN_POS_VALUE, "1.1754944e-38"),
+            (1e-45, "1e-45"),
+        ];
+
+        for &(v, s) in tests.iter() {
+            assert_eq!(f32_to_string(v), s.to_string());
+        }
+    }
+
+    #[test]
+    fn test_grisu_matches_dragon() {
+        let mut grisu_buf = [0u8, .. 17];
+        let mut dragon_buf = [0u8, .. 17];
+
+        let mut v = 1.0e-300f64;
+        while v < 1.0e300 {
+            let d = decode_f64(v);
+            let (len, exp) = dragon(&d, &mut dragon_buf);
+
+            match grisu(&d, &mut grisu_buf) {
+                Some((grisu_len, grisu_exp)) => {
+                    assert_eq!(grisu_buf.slice_to(grisu_len), dragon_buf.slice_to(len));
+                    assert_eq!(grisu_exp, exp);
+                }
+                None => { }
+            }
+
+            v *= 1.0123;
+        }
+    }
+
+    #[test]
+    fn test_grisu_fallback() {
+        // Grisu3 can't tell whether 1e23 is the closest, so this one is left
+        // to the exact algorithm.
+        let mut buf = [0u8, .. 17];
+        let d = decode_f64(1e23);
+        assert!(grisu(&d, &mut buf).is_none());
+
+        let (len, exp) = dragon(&d, &mut buf);
+        assert_eq!(buf.slice_to(len), b"1");
+        assert_eq!(exp, 24);
+    }
+}
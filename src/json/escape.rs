@@ -0,0 +1,235 @@
+// Copyright 2012-2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Escapes strings and formats floats for the JSON writers.
+
+use std::default::Default;
+use std::io::{IoResult, Writer};
+use std::io;
+use std::num::{FPNaN, FPInfinite};
+
+use super::dtoa;
+
+/// Characters the JSON writers escape on top of the ones JSON requires.
+#[deriving(Clone, Default, PartialEq, Show)]
+pub struct Escaping {
+    /// Escape every non-ASCII character as `\uXXXX`, using a surrogate pair
+    /// outside the Basic Multilingual Plane, so the output is plain ASCII.
+    pub ascii_only: bool,
+    /// Escape `<`, `>`, `&`, U+2028 and U+2029, so the output can be embedded
+    /// in an HTML `<script>` element or evaluated as JavaScript.
+    pub html_safe: bool,
+}
+
+pub fn escape_bytes<W: Writer>(wr: &mut W, bytes: &[u8]) -> IoResult<()> {
+    try!(wr.write_str("\""));
+
+    let mut start = 0;
+
+    for (i, byte) in bytes.iter().enumerate() {
+        let escaped = match *byte {
+            b'"' => "\\\"",
+            b'\\' => "\\\\",
+            b'\x08' => "\\b",
+            b'\x0c' => "\\f",
+            b'\n' => "\\n",
+            b'\r' => "\\r",
+            b'\t' => "\\t",
+            0x00 ... 0x1f => "",
+            _ => { continue; }
+        };
+
+        if start < i {
+            try!(wr.write(bytes.slice(start, i)));
+        }
+
+        if escaped.is_empty() {
+            try!(write!(wr, "\\u{:04x}", *byte));
+        } else {
+            try!(wr.write_str(escaped));
+        }
+
+        start = i + 1;
+    }
+
+    if start != bytes.len() {
+        try!(wr.write(bytes.slice_from(start)));
+    }
+
+    wr.write_str("\"")
+}
+
+/// Like `escape_bytes`, but also escapes the characters selected by
+/// `escaping`. Bytes that aren't valid UTF-8 are written unchanged.
+pub fn escape_bytes_with<W: Writer>(wr: &mut W,
+                                    bytes: &[u8],
+                                    escaping: Escaping) -> IoResult<()> {
+    // Only the extra escapes need whole characters, so leave everything else
+    // to the byte scan.
+    if !escaping.ascii_only && !escaping.html_safe {
+        return escape_bytes(wr, bytes);
+    }
+
+    try!(wr.write_str("\""));
+
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let (ch, width) = decode_utf8_at(bytes, i);
+
+        let escaped = match ch {
+            0x22 => "\\\"",
+            0x5c => "\\\\",
+            0x08 => "\\b",
+            0x0c => "\\f",
+            0x0a => "\\n",
+            0x0d => "\\r",
+            0x09 => "\\t",
+            0x00 ... 0x1f => "",
+            0x3c | 0x3e | 0x26 | 0x2028 | 0x2029 if escaping.html_safe => "",
+            0x80 ... 0x10ffff if escaping.ascii_only => "",
+            _ => {
+                i += width;
+                continue;
+            }
+        };
+
+        if start < i {
+            try!(wr.write(bytes.slice(start, i)));
+        }
+
+        if !escaped.is_empty() {
+            try!(wr.write_str(escaped));
+        } else if ch > 0xffff {
+            let ch = ch - 0x10000;
+            try!(write!(wr, "\\u{:04x}", 0xd800 + (ch >> 10)));
+            try!(write!(wr, "\\u{:04x}", 0xdc00 + (ch & 0x3ff)));
+        } else {
+            try!(write!(wr, "\\u{:04x}", ch));
+        }
+
+        i += width;
+        start = i;
+    }
+
+    if start != bytes.len() {
+        try!(wr.write(bytes.slice_from(start)));
+    }
+
+    wr.write_str("\"")
+}
+
+// Decodes the character starting at `bytes[i]`, returning its code point and
+// its length in bytes. Bytes that don't start a valid UTF-8 sequence are
+// returned as a one byte character above U+10FFFF, so they are never escaped.
+fn decode_utf8_at(bytes: &[u8], i: uint) -> (u32, uint) {
+    let first = bytes[i];
+
+    let (width, init) = match first {
+        0x00 ... 0x7f => { return (first as u32, 1); }
+        0xc2 ... 0xdf => (2, (first & 0x1f) as u32),
+        0xe0 ... 0xef => (3, (first & 0x0f) as u32),
+        0xf0 ... 0xf4 => (4, (first & 0x07) as u32),
+        _ => { return (0x110000, 1); }
+    };
+
+    if i + width > bytes.len() {
+        return (0x110000, 1);
+    }
+
+    let mut ch = init;
+    for byte in bytes.slice(i + 1, i + width).iter() {
+        if *byte & 0xc0 != 0x80 {
+            return (0x110000, 1);
+        }
+        ch = (ch << 6) | (*byte & 0x3f) as u32;
+    }
+
+    (ch, width)
+}
+
+pub fn escape_str<W: Writer>(wr: &mut W, v: &str) -> IoResult<()> {
+    escape_bytes(wr, v.as_bytes())
+}
+
+/// Like `escape_str`, but also escapes the characters selected by `escaping`.
+pub fn escape_str_with<W: Writer>(wr: &mut W, v: &str, escaping: Escaping) -> IoResult<()> {
+    escape_bytes_with(wr, v.as_bytes(), escaping)
+}
+
+pub fn escape_char<W: Writer>(wr: &mut W, v: char) -> IoResult<()> {
+    escape_char_with(wr, v, Default::default())
+}
+
+/// Like `escape_char`, but also escapes the characters selected by
+/// `escaping`.
+pub fn escape_char_with<W: Writer>(wr: &mut W, v: char, escaping: Escaping) -> IoResult<()> {
+    let s = v.to_string();
+    escape_bytes_with(wr, s.as_bytes(), escaping)
+}
+
+/// How the JSON writers write floats that JSON has no syntax for. See the
+/// parsers for which of these they accept back.
+#[deriving(Clone, PartialEq, Show)]
+pub enum NonFiniteFloats {
+    /// Refuse to write `NaN` and the infinities.
+    NonFiniteError,
+    /// Write `null`.
+    NonFiniteAsNull,
+    /// Write the strings `"NaN"`, `"Infinity"` and `"-Infinity"`.
+    NonFiniteAsString,
+    /// Write the bare JavaScript literals `NaN`, `Infinity` and `-Infinity`.
+    NonFiniteAsLiteral,
+}
+
+fn fmt_non_finite<W: Writer>(wr: &mut W,
+                             nan: bool,
+                             negative: bool,
+                             non_finite: NonFiniteFloats) -> IoResult<()> {
+    let literal = match (nan, negative) {
+        (true, _) => "NaN",
+        (false, false) => "Infinity",
+        (false, true) => "-Infinity",
+    };
+
+    match non_finite {
+        NonFiniteError => {
+            Err(io::IoError {
+                kind: io::InvalidInput,
+                desc: "JSON cannot represent NaN or infinite floats",
+                detail: Some(literal.to_string()),
+            })
+        }
+        NonFiniteAsNull => wr.write_str("null"),
+        NonFiniteAsString => {
+            try!(wr.write_str("\""));
+            try!(wr.write_str(literal));
+            wr.write_str("\"")
+        }
+        NonFiniteAsLiteral => wr.write_str(literal),
+    }
+}
+
+pub fn fmt_f32<W: Writer>(wr: &mut W, v: f32, non_finite: NonFiniteFloats) -> IoResult<()> {
+    match v.classify() {
+        FPNaN => fmt_non_finite(wr, true, false, non_finite),
+        FPInfinite => fmt_non_finite(wr, false, v < 0.0, non_finite),
+        _ => dtoa::write_f32(wr, v),
+    }
+}
+
+pub fn fmt_f64<W: Writer>(wr: &mut W, v: f64, non_finite: NonFiniteFloats) -> IoResult<()> {
+    match v.classify() {
+        FPNaN => fmt_non_finite(wr, true, false, non_finite),
+        FPInfinite => fmt_non_finite(wr, false, v < 0.0, non_finite),
+        _ => dtoa::write_f64(wr, v),
+    }
+}
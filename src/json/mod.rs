@@ -264,14 +264,15 @@ fn main() {
 */
 
 use std::char;
+use std::cmp;
 use std::collections::{HashMap, TreeMap, treemap};
-use std::f32;
-use std::f64;
+use std::default::Default;
 use std::fmt;
+use std::f64;
+use std::i64;
 use std::io::{IoResult, MemWriter, AsRefWriter};
 use std::io;
-use std::num::{FPNaN, FPInfinite};
-use std::num;
+use std::mem;
 use std::str::ScalarValue;
 use std::str;
 use std::string;
@@ -283,6 +284,27 @@ use ser::Serializable;
 use ser;
 
 pub mod builder;
+pub mod canonical;
+pub mod cst;
+pub mod decode;
+pub mod diff;
+pub mod raw;
+pub mod schema;
+pub mod value;
+pub mod writer;
+mod bignum;
+mod dtoa;
+mod escape;
+mod regex;
+mod strtod;
+
+pub use self::diff::diff;
+pub use self::escape::{Escaping, escape_bytes, escape_bytes_with};
+pub use self::escape::{escape_str, escape_str_with, escape_char, escape_char_with};
+pub use self::escape::{NonFiniteFloats, NonFiniteError, NonFiniteAsNull};
+pub use self::escape::{NonFiniteAsString, NonFiniteAsLiteral};
+pub use self::raw::RawJson;
+pub use self::value::to_json_value;
 
 /// Represents a json value
 #[deriving(Clone, PartialEq, PartialOrd)]
@@ -290,7 +312,12 @@ pub enum Json {
     Null,
     Boolean(bool),
     Integer(i64),
+    /// An unsigned integer too large to fit in an `Integer`.
+    U64(u64),
     Floating(f64),
+    /// A number kept in its exact textual form, as produced by a `Parser` in
+    /// raw number mode. The text must be a valid JSON number.
+    Number(string::String),
     String(string::String),
     List(JsonList),
     Object(JsonObject),
@@ -313,11 +340,10 @@ impl Json {
         self.serialize(&mut serializer)
     }
 
-    /// Serializes a json value into a string
+    /// Serializes a json value into a string. `NaN` and the infinities are
+    /// written as `null`, as `json::to_pretty_string` does.
     pub fn to_pretty_string(&self) -> string::String {
-        let mut wr = MemWriter::new();
-        self.to_pretty_writer(wr.by_ref()).unwrap();
-        str::from_utf8(wr.unwrap().as_slice()).unwrap().to_string()
+        to_pretty_string(self).ok().expect("json is always utf-8")
     }
 
      /// If the Json value is an Object, returns the value associated with the provided key.
@@ -343,6 +369,54 @@ impl Json {
         Some(target)
     }
 
+    /// Looks up a value by a JSON Pointer (RFC 6901), such as `/servers/0/port`.
+    /// The empty pointer refers to the whole value.
+    pub fn pointer<'a>(&'a self, pointer: &str) -> Option<&'a Json> {
+        let tokens = match parse_pointer(pointer) {
+            Some(tokens) => tokens,
+            None => { return None; }
+        };
+
+        let mut target = self;
+        for token in tokens.iter() {
+            target = match *target {
+                Object(ref map) => match map.find(token) {
+                    Some(value) => value,
+                    None => { return None; }
+                },
+                List(ref list) => {
+                    match parse_index(token.as_slice()).and_then(|i| list.as_slice().get(i)) {
+                        Some(value) => value,
+                        None => { return None; }
+                    }
+                }
+                _ => { return None; }
+            };
+        }
+        Some(target)
+    }
+
+    /// Deserializes the value without consuming it.
+    pub fn decode<
+        'a,
+        T: de::Deserializable<decode::Deserializer<'a>, ParserError>
+    >(&'a self) -> Result<T, decode::DecodeError> {
+        decode::decode_at(self, "")
+    }
+
+    /// Deserializes the value at a JSON Pointer without consuming it. A
+    /// missing value is read as `null`, so it is only an error if `T` can't
+    /// be read from `null`.
+    pub fn get_as<
+        'a,
+        T: de::Deserializable<decode::Deserializer<'a>, ParserError>
+    >(&'a self, pointer: &str) -> Result<T, decode::DecodeError> {
+        match self.pointer(pointer) {
+            Some(value) => decode::decode_at(value, pointer),
+            None => decode::decode_missing(pointer),
+        }
+    }
+
     /// If the Json value is an Object, performs a depth-first search until
     /// a value associated with the provided key is found. If no value is found
     /// or the Json value is not an Object, returns None.
@@ -409,10 +483,10 @@ impl Json {
         }
     }
 
-    /// Returns true if the Json value is a i64 or f64. Returns false otherwise.
+    /// Returns true if the Json value is a number. Returns false otherwise.
     pub fn is_number(&self) -> bool {
         match *self {
-            Integer(_) | Floating(_) => true,
+            Integer(_) | U64(_) | Floating(_) | Number(_) => true,
             _ => false,
         }
     }
@@ -425,6 +499,14 @@ impl Json {
         }
     }
 
+    /// Returns true if the Json value is a u64. Returns false otherwise.
+    pub fn is_u64(&self) -> bool {
+        match *self {
+            U64(_) => true,
+            _ => false,
+        }
+    }
+
     /// Returns true if the Json value is a f64. Returns false otherwise.
     pub fn is_f64(&self) -> bool {
         match *self {
@@ -439,6 +521,18 @@ impl Json {
         match *self {
             Integer(n) => Some(n),
             Floating(n) => Some(n as i64),
+            Number(ref n) => from_str(n.as_slice()),
+            _ => None
+        }
+    }
+
+    /// If the Json value is a non-negative integer, returns the associated
+    /// u64. Returns None otherwise.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Integer(n) if n >= 0 => Some(n as u64),
+            U64(n) => Some(n),
+            Number(ref n) => from_str(n.as_slice()),
             _ => None
         }
     }
@@ -448,7 +542,9 @@ impl Json {
     pub fn as_f64(&self) -> Option<f64> {
         match *self {
             Integer(n) => Some(n as f64),
+            U64(n) => Some(n as f64),
             Floating(n) => Some(n),
+            Number(ref n) => from_str(n.as_slice()),
             _ => None
         }
     }
@@ -492,9 +588,12 @@ impl<'a, 'b> Writer for WriterFormatter<'a, 'b> {
 }
 
 impl fmt::Show for Json {
-    /// Serializes a json value into a string
+    /// Serializes a json value into a string. `NaN` and the infinities are
+    /// shown as the bare literals, rather than cutting the output short.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.to_writer(WriterFormatter(f)).map_err(|_| fmt::WriteError)
+        let mut serializer = Serializer::new(WriterFormatter(f))
+            .non_finite_floats(NonFiniteAsLiteral);
+        self.serialize(&mut serializer).map_err(|_| fmt::WriteError)
     }
 }
 
@@ -511,9 +610,15 @@ impl<S: ser::Serializer<E>, E> ser::Serializable<S, E> for Json {
             Integer(v) => {
                 v.serialize(s)
             }
+            U64(v) => {
+                v.serialize(s)
+            }
             Floating(v) => {
                 v.serialize(s)
             }
+            Number(ref v) => {
+                s.serialize_number(v.as_slice())
+            }
             String(ref v) => {
                 v.serialize(s)
             }
@@ -538,13 +643,14 @@ impl<D: de::Deserializer<E>, E> de::Deserializable<D, E> for Json {
             de::I16(x) => Ok(Integer(x as i64)),
             de::I32(x) => Ok(Integer(x as i64)),
             de::I64(x) => Ok(Integer(x)),
-            de::Uint(x) => Ok(Integer(x as i64)),
+            de::Uint(x) => Ok(from_u64(x as u64)),
             de::U8(x) => Ok(Integer(x as i64)),
             de::U16(x) => Ok(Integer(x as i64)),
             de::U32(x) => Ok(Integer(x as i64)),
-            de::U64(x) => Ok(Integer(x as i64)),
+            de::U64(x) => Ok(from_u64(x)),
             de::F32(x) => Ok(Floating(x as f64)),
             de::F64(x) => Ok(Floating(x)),
+            de::Number(x) => Ok(Number(x)),
             de::Char(x) => Ok(String(x.to_string())),
             de::Str(x) => Ok(String(x.to_string())),
             de::String(x) => Ok(String(x)),
@@ -570,6 +676,17 @@ impl<D: de::Deserializer<E>, E> de::Deserializable<D, E> for Json {
     }
 }
 
+/// Unsigned integers are only stored as a `U64` when they don't fit in an
+/// `Integer`, so that equal values always compare equal.
+#[inline]
+fn from_u64(v: u64) -> Json {
+    if v <= i64::MAX as u64 {
+        Integer(v as i64)
+    } else {
+        U64(v)
+    }
+}
+
 enum JsonDeserializerState {
     JsonDeserializerValueState(Json),
     JsonDeserializerListState(vec::MoveItems<Json>),
@@ -600,7 +717,9 @@ impl Iterator<Result<de::Token, ParserError>> for JsonDeserializer {
                         Null => de::Null,
                         Boolean(x) => de::Bool(x),
                         Integer(x) => de::I64(x),
+                        U64(x) => de::U64(x),
                         Floating(x) => de::F64(x),
+                        Number(x) => de::Number(x),
                         String(x) => de::String(x),
                         List(x) => {
                             let len = x.len();
@@ -651,19 +770,25 @@ impl Iterator<Result<de::Token, ParserError>> for JsonDeserializer {
 
 impl de::Deserializer<ParserError> for JsonDeserializer {
     fn end_of_stream_error(&mut self) -> ParserError {
-        SyntaxError(EOFWhileParsingValue, 0, 0)
+        SyntaxError(EOFWhileParsingValue, 0, 0, 0)
     }
 
     fn syntax_error(&mut self, token: de::Token, expected: &[de::TokenKind]) -> ParserError {
-        SyntaxError(DeserializerError(token, ExpectTokens(expected.to_vec())), 0, 0)
+        SyntaxError(DeserializerError(token, ExpectTokens(expected.to_vec())), 0, 0, 0)
     }
 
     fn unexpected_name_error(&mut self, token: de::Token) -> ParserError {
-        SyntaxError(DeserializerError(token, ExpectName), 0, 0)
+        SyntaxError(DeserializerError(token, ExpectName), 0, 0, 0)
     }
 
     fn conversion_error(&mut self, token: de::Token) -> ParserError {
-        SyntaxError(DeserializerError(token, ExpectConversion), 0, 0)
+        SyntaxError(DeserializerError(token, ExpectConversion), 0, 0, 0)
+    }
+
+    fn number_conversion_error(&mut self,
+                               token: de::Token,
+                               expected: de::TokenKind) -> ParserError {
+        SyntaxError(DeserializerError(token, ExpectExactNumber(expected)), 0, 0, 0)
     }
 
     #[inline]
@@ -677,15 +802,10 @@ impl de::Deserializer<ParserError> for JsonDeserializer {
 
     // Special case treating options as a nullable value.
     #[inline]
-    fn expect_option<
-        U: de::Deserializable<JsonDeserializer, ParserError>
-    >(&mut self, token: de::Token) -> Result<Option<U>, ParserError> {
+    fn expect_option_start(&mut self, token: de::Token) -> Result<de::OptionStart, ParserError> {
         match token {
-            de::Null => Ok(None),
-            token => {
-                let value: U = try!(de::Deserializable::deserialize_token(self, token));
-                Ok(Some(value))
-            }
+            de::Null => Ok(de::NoValue),
+            token => Ok(de::ValueToken(token)),
         }
     }
 
@@ -766,12 +886,30 @@ pub enum SyntaxExpectation {
     EnumEnd,
 }
 
+impl SyntaxExpectation {
+    /// Describes what was expected, for error messages.
+    pub fn description(&self) -> &'static str {
+        match *self {
+            ListCommaOrEnd => "expected `,` or `]`",
+            ObjectCommaOrEnd => "expected `,` or `}`",
+            SomeValue => "expected a value",
+            SomeIdent => "expected `null`, `true` or `false`",
+            EnumMapStart => "expected an object holding an enum variant",
+            EnumVariantString => "expected the name of an enum variant",
+            EnumToken => "expected a list of enum variant fields",
+            EnumEndToken => "expected the end of the enum object",
+            EnumEnd => "expected the end of the enum variant fields",
+        }
+    }
+}
+
 /// JSON deserializer expectations
 #[deriving(Clone, PartialEq, Show)]
 pub enum DeserializerExpectation {
     ExpectTokens(Vec<de::TokenKind>),
     ExpectName,
     ExpectConversion,
+    ExpectExactNumber(de::TokenKind),
 }
 
 /// The errors that can arise while parsing a JSON stream.
@@ -798,10 +936,10 @@ pub enum ErrorCode {
     UnrecognizedHex,
 }
 
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, PartialEq)]
 pub enum ParserError {
-    /// msg, line, col
-    SyntaxError(ErrorCode, uint, uint),
+    /// code, line, col, byte offset
+    SyntaxError(ErrorCode, uint, uint, uint),
     IoError(io::IoErrorKind, &'static str),
     ExpectedError(string::String, string::String),
     MissingFieldError(string::String),
@@ -824,16 +962,34 @@ pub enum DecoderError {
 impl fmt::Show for ErrorCode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            DeserializerError(ref token, ref expect) => write!(f,
-                "deserializer found {} when {}", token, expect),
-            EOFWhileParsingList => "EOF While parsing list".fmt(f),
-            EOFWhileParsingObject => "EOF While parsing object".fmt(f),
-            EOFWhileParsingString => "EOF While parsing string".fmt(f),
-            EOFWhileParsingValue => "EOF While parsing value".fmt(f),
+            DeserializerError(ref token, ExpectTokens(ref kinds)) => {
+                if kinds.is_empty() {
+                    return write!(f, "unexpected `{}`", token);
+                }
+
+                try!("expected ".fmt(f));
+                for (i, kind) in kinds.iter().enumerate() {
+                    if i != 0 {
+                        try!((if i + 1 == kinds.len() { " or " } else { ", " }).fmt(f));
+                    }
+                    try!(kind.description().fmt(f));
+                }
+                write!(f, ", found `{}`", token)
+            }
+            DeserializerError(ref token, ExpectName) => write!(f,
+                "expected a known field or variant name, found `{}`", token),
+            DeserializerError(ref token, ExpectConversion) => write!(f,
+                "`{}` is out of range for the expected type", token),
+            DeserializerError(ref token, ExpectExactNumber(kind)) => write!(f,
+                "`{}` cannot be converted exactly into {}", token, kind.description()),
+            EOFWhileParsingList => "unexpected end of input while parsing a list".fmt(f),
+            EOFWhileParsingObject => "unexpected end of input while parsing an object".fmt(f),
+            EOFWhileParsingString => "unexpected end of input while parsing a string".fmt(f),
+            EOFWhileParsingValue => "unexpected end of input while parsing a value".fmt(f),
             ExpectedColon => "expected `:`".fmt(f),
             InvalidEscape => "invalid escape".fmt(f),
             InvalidNumber => "invalid number".fmt(f),
-            InvalidSyntax(expect) => write!(f, "invalid syntax, expected: {}", expect),
+            InvalidSyntax(expect) => expect.description().fmt(f),
             InvalidUnicodeCodePoint => "invalid unicode code point".fmt(f),
             KeyMustBeAString => "key must be a string".fmt(f),
             LoneLeadingSurrogateInHexEscape => "lone leading surrogate in hex escape".fmt(f),
@@ -848,87 +1004,129 @@ impl fmt::Show for ErrorCode {
     }
 }
 
-/*
-fn io_error_to_error(io: io::IoError) -> ParserError {
-    IoError(io.kind, io.desc)
+impl fmt::Show for ParserError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SyntaxError(ref code, line, col, _) => write!(f,
+                "{} at line {}, column {}", code, line, col),
+            IoError(ref kind, desc) => write!(f, "{} ({})", desc, kind),
+            ExpectedError(ref expected, ref found) => write!(f,
+                "expected {}, found {}", expected, found),
+            MissingFieldError(ref field) => write!(f, "missing field `{}`", field),
+            UnknownVariantError(ref variant) => write!(f, "unknown variant `{}`", variant),
+        }
+    }
 }
-*/
 
-pub type SerializeResult = io::IoResult<()>;
+impl ParserError {
+    /// Renders the error for display to a user, followed by the line of
+    /// `source` it occurred on with a caret under the offending byte. The
+    /// line and column are worked out from the byte offset. Errors without a
+    /// position are rendered on their own.
+    pub fn render(&self, source: &str) -> string::String {
+        let (code, offset) = match *self {
+            SyntaxError(ref code, _, _, offset) => (code, offset),
+            _ => { return self.to_string(); }
+        };
 
-pub fn escape_bytes<W: Writer>(wr: &mut W, bytes: &[u8]) -> IoResult<()> {
-    try!(wr.write_str("\""));
-
-    let mut start = 0;
-
-    for (i, byte) in bytes.iter().enumerate() {
-        let escaped = match *byte {
-            b'"' => "\\\"",
-            b'\\' => "\\\\",
-            b'\x08' => "\\b",
-            b'\x0c' => "\\f",
-            b'\n' => "\\n",
-            b'\r' => "\\r",
-            b'\t' => "\\t",
-            _ => { continue; }
+        let bytes = source.as_bytes();
+        let offset = cmp::min(offset, bytes.len());
+
+        let start = match bytes.slice_to(offset).iter().rposition(|ch| *ch == b'\n') {
+            Some(newline) => newline + 1,
+            None => 0,
+        };
+        let mut end = match bytes.slice_from(offset).iter().position(|ch| *ch == b'\n') {
+            Some(newline) => offset + newline,
+            None => bytes.len(),
         };
+        if end > start && bytes[end - 1] == b'\r' {
+            end -= 1;
+        }
 
-        if start < i {
-            try!(wr.write(bytes.slice(start, i)));
+        // Keep tabs so the caret lines up, and count each character once.
+        let mut caret = string::String::new();
+        for ch in bytes.slice(start, offset).iter() {
+            match *ch {
+                b'\t' => caret.push('\t'),
+                ch if ch & 0xC0 == 0x80 => { }
+                _ => caret.push(' '),
+            }
         }
+        caret.push('^');
 
-        try!(wr.write_str(escaped));
+        let (line, col) = line_col(bytes, offset);
 
-        start = i + 1;
+        format!("{} at line {}, column {}\n{}\n{}",
+                code,
+                line,
+                col,
+                string::String::from_utf8_lossy(bytes.slice(start, end)),
+                caret)
     }
+}
 
-    if start != bytes.len() {
-        try!(wr.write(bytes.slice_from(start)));
-    }
+/// The line and column of a byte offset into `source`, counted the way
+/// `Parser` counts them: both from 1, with columns in characters.
+fn line_col(source: &[u8], offset: uint) -> (uint, uint) {
+    let before = source.slice_to(offset);
+    let start = match before.iter().rposition(|ch| *ch == b'\n') {
+        Some(newline) => newline + 1,
+        None => 0,
+    };
 
-    wr.write_str("\"")
+    let line = before.slice_to(start).iter().filter(|ch| **ch == b'\n').count() + 1;
+    let col = before.slice_from(start).iter().filter(|ch| **ch & 0xC0 != 0x80).count() + 1;
+    (line, col)
 }
 
-pub fn escape_str<W: Writer>(wr: &mut W, v: &str) -> IoResult<()> {
-    escape_bytes(wr, v.as_bytes())
+/*
+fn io_error_to_error(io: io::IoError) -> ParserError {
+    IoError(io.kind, io.desc)
 }
+*/
 
-pub fn escape_char<W: Writer>(wr: &mut W, v: char) -> IoResult<()> {
-    let mut buf = [0, .. 4];
-    v.encode_utf8(buf);
-    escape_bytes(wr, buf)
-}
+pub type SerializeResult = io::IoResult<()>;
 
-fn fmt_f32_or_null<W: Writer>(wr: &mut W, v: f32) -> IoResult<()> {
-    match v.classify() {
-        FPNaN | FPInfinite => wr.write_str("null"),
-        _ => wr.write_str(f32::to_str_digits(v, 6).as_slice()),
+/// Splits a JSON Pointer into its unescaped reference tokens.
+fn parse_pointer(pointer: &str) -> Option<Vec<string::String>> {
+    if pointer.is_empty() {
+        Some(Vec::new())
+    } else if pointer.starts_with("/") {
+        Some(pointer.slice_from(1).split('/').map(|token| {
+            token.replace("~1", "/").replace("~0", "~")
+        }).collect())
+    } else {
+        None
     }
 }
 
-fn fmt_f64_or_null<W: Writer>(wr: &mut W, v: f64) -> IoResult<()> {
-    match v.classify() {
-        FPNaN | FPInfinite => wr.write_str("null"),
-        _ => wr.write_str(f64::to_str_digits(v, 6).as_slice()),
-    }
+/// Escapes a reference token for use in a JSON Pointer.
+fn escape_pointer_token(token: &str) -> string::String {
+    token.replace("~", "~0").replace("/", "~1")
 }
 
-fn spaces<W: Writer>(wr: &mut W, mut n: uint) -> IoResult<()> {
-    const LEN: uint = 16;
-    const BUF: [u8, ..LEN] = [b' ', ..LEN];
-
-    while n >= LEN {
-        try!(wr.write(BUF));
-        n -= LEN;
-    }
-
-    if n > 0 {
-        wr.write(BUF.slice_to(n))
+/// Parses an array index in a JSON Pointer.
+fn parse_index(token: &str) -> Option<uint> {
+    // Leading zeros are not allowed in array indices.
+    if token.len() > 1 && token.starts_with("0") {
+        None
     } else {
-        Ok(())
+        ::std::from_str::from_str(token)
     }
 }
 
+/// Parses the text of a `RawJson` into a `Json` that writes its numbers out
+/// exactly as they are, for the writers to escape its strings. The text was
+/// checked by a parser with any options, so all of them are allowed.
+fn raw_json_value(text: &str) -> Json {
+    let mut parser = Parser::new(text.bytes())
+        .relaxed(true)
+        .raw_numbers(true)
+        .non_finite_floats(NonFiniteAsLiteral);
+    de::Deserializable::deserialize(&mut parser).ok().expect("RawJson holds invalid JSON")
+}
+
 /*
 #[deriving(Show)]
 enum SerializerState {
@@ -943,6 +1141,8 @@ enum SerializerState {
 pub struct Serializer<W> {
     wr: W,
     first: bool,
+    non_finite: NonFiniteFloats,
+    escaping: Escaping,
 }
 
 impl<W: Writer> Serializer<W> {
@@ -952,9 +1152,33 @@ impl<W: Writer> Serializer<W> {
         Serializer {
             wr: wr,
             first: true,
+            non_finite: NonFiniteError,
+            escaping: Default::default(),
         }
     }
 
+    /// Choose how `NaN` and infinite floats are written. By default they are
+    /// refused with an `InvalidInput` error, the same way `Parser` refuses
+    /// them, so that they are never silently turned into something else.
+    pub fn non_finite_floats(mut self, non_finite: NonFiniteFloats) -> Serializer<W> {
+        self.non_finite = non_finite;
+        self
+    }
+
+    /// Escapes every non-ASCII character in strings, so the output is plain
+    /// ASCII.
+    pub fn ascii_only(mut self, ascii_only: bool) -> Serializer<W> {
+        self.escaping.ascii_only = ascii_only;
+        self
+    }
+
+    /// Escapes `<`, `>`, `&`, U+2028 and U+2029 in strings, so the output can
+    /// be embedded in an HTML `<script>` element.
+    pub fn html_safe(mut self, html_safe: bool) -> Serializer<W> {
+        self.escaping.html_safe = html_safe;
+        self
+    }
+
     /// Unwrap the Writer from the Serializer.
     pub fn unwrap(self) -> W {
         self.wr
@@ -1028,22 +1252,36 @@ impl<W: Writer> ser::Serializer<io::IoError> for Serializer<W> {
 
     #[inline]
     fn serialize_f32(&mut self, v: f32) -> IoResult<()> {
-        fmt_f32_or_null(&mut self.wr, v)
+        escape::fmt_f32(&mut self.wr, v, self.non_finite)
     }
 
     #[inline]
     fn serialize_f64(&mut self, v: f64) -> IoResult<()> {
-        fmt_f64_or_null(&mut self.wr, v)
+        escape::fmt_f64(&mut self.wr, v, self.non_finite)
+    }
+
+    #[inline]
+    fn serialize_number(&mut self, v: &str) -> IoResult<()> {
+        self.wr.write_str(v)
+    }
+
+    #[inline]
+    fn serialize_raw_json(&mut self, v: &str) -> Option<IoResult<()>> {
+        // The text may hold characters these options escape.
+        if self.escaping.ascii_only || self.escaping.html_safe {
+            return Some(raw_json_value(v).serialize(self));
+        }
+        Some(self.wr.write_str(v))
     }
 
     #[inline]
     fn serialize_char(&mut self, v: char) -> IoResult<()> {
-        escape_char(&mut self.wr, v)
+        escape_char_with(&mut self.wr, v, self.escaping)
     }
 
     #[inline]
     fn serialize_str(&mut self, v: &str) -> IoResult<()> {
-        escape_str(&mut self.wr, v)
+        escape_str_with(&mut self.wr, v, self.escaping)
     }
 
     #[inline]
@@ -1179,206 +1417,658 @@ impl<W: Writer> ser::Serializer<io::IoError> for Serializer<W> {
 /// Another serializer for JSON, but prints out human-readable JSON instead of
 /// compact data
 pub struct PrettySerializer<W> {
-    wr: W,
-    indent: uint,
+    wr: LayoutWriter<W>,
+    level: uint,
+    depth: uint,
     first: bool,
+    non_finite: NonFiniteFloats,
+    escaping: Escaping,
+    indent: string::String,
+    trailing_newline: bool,
+    space_after_colon: bool,
+    sort_keys: bool,
+    max_width: Option<uint>,
+    frames: Vec<LayoutFrame>,
+    // The last string written in an object whose keys are sorted, which is
+    // its key if a colon follows.
+    last_str: Option<string::String>,
 }
 
 impl<W: Writer> PrettySerializer<W> {
     /// Creates a new serializer whose output will be written to the specified writer
     pub fn new(wr: W) -> PrettySerializer<W> {
         PrettySerializer {
-            wr: wr,
-            indent: 0,
+            wr: LayoutWriter { wr: wr, buf: None },
+            level: 0,
+            depth: 0,
             first: true,
+            non_finite: NonFiniteError,
+            escaping: Default::default(),
+            indent: "  ".to_string(),
+            trailing_newline: false,
+            space_after_colon: true,
+            sort_keys: false,
+            max_width: None,
+            frames: Vec::new(),
+            last_str: None,
         }
     }
 
-    /// Unwrap the Writer from the Serializer.
-    pub fn unwrap(self) -> W {
-        self.wr
+    /// Choose how `NaN` and infinite floats are written. By default they are
+    /// refused with an `InvalidInput` error, as with `Serializer`.
+    pub fn non_finite_floats(mut self, non_finite: NonFiniteFloats) -> PrettySerializer<W> {
+        self.non_finite = non_finite;
+        self
     }
 
-    #[inline]
-    fn serialize_sep(&mut self) -> IoResult<()> {
-        if self.first {
-            self.first = false;
-            self.indent += 2;
-            try!(self.wr.write_str("\n"));
-        } else {
-            try!(self.wr.write_str(",\n"));
-        }
-
-        spaces(&mut self.wr, self.indent)
+    /// Escapes every non-ASCII character in strings, so the output is plain
+    /// ASCII.
+    pub fn ascii_only(mut self, ascii_only: bool) -> PrettySerializer<W> {
+        self.escaping.ascii_only = ascii_only;
+        self
     }
 
-    #[inline]
-    fn serialize_end(&mut self, s: &str) -> IoResult<()> {
-        if !self.first {
-            try!(self.wr.write_str("\n"));
-            self.indent -= 2;
-            try!(spaces(&mut self.wr, self.indent));
-        }
-
-        self.first = false;
-
-        self.wr.write_str(s)
+    /// Escapes `<`, `>`, `&`, U+2028 and U+2029 in strings, so the output can
+    /// be embedded in an HTML `<script>` element.
+    pub fn html_safe(mut self, html_safe: bool) -> PrettySerializer<W> {
+        self.escaping.html_safe = html_safe;
+        self
     }
-}
 
-impl<W: Writer> ser::Serializer<io::IoError> for PrettySerializer<W> {
-    #[inline]
-    fn serialize_null(&mut self) -> IoResult<()> {
-        self.wr.write_str("null")
+    /// Sets the string written once per level of nesting, such as `"\t"` or
+    /// four spaces. Defaults to two spaces.
+    pub fn indent(mut self, indent: &str) -> PrettySerializer<W> {
+        self.indent = indent.to_string();
+        self
     }
 
-    #[inline]
-    fn serialize_bool(&mut self, v: bool) -> IoResult<()> {
-        if v {
-            self.wr.write_str("true")
-        } else {
-            self.wr.write_str("false")
-        }
+    /// Writes a newline after every top-level value.
+    pub fn trailing_newline(mut self, trailing_newline: bool) -> PrettySerializer<W> {
+        self.trailing_newline = trailing_newline;
+        self
     }
 
-    #[inline]
-    fn serialize_int(&mut self, v: int) -> IoResult<()> {
-        write!(self.wr, "{}", v)
+    /// Writes a space between the colon and the value of an object member.
+    /// Enabled by default.
+    pub fn space_after_colon(mut self, space_after_colon: bool) -> PrettySerializer<W> {
+        self.space_after_colon = space_after_colon;
+        self
     }
 
-    #[inline]
-    fn serialize_i8(&mut self, v: i8) -> IoResult<()> {
-        write!(self.wr, "{}", v)
+    /// Writes the entries of maps, such as a `HashMap`, sorted by the text of
+    /// their keys. Struct fields keep their declaration order.
+    pub fn sort_keys(mut self, sort_keys: bool) -> PrettySerializer<W> {
+        self.sort_keys = sort_keys;
+        self
     }
 
-    #[inline]
-    fn serialize_i16(&mut self, v: i16) -> IoResult<()> {
-        write!(self.wr, "{}", v)
+    /// Keeps an array or object on a single line when it fits within `width`
+    /// columns. Every character of the indent counts as one column.
+    pub fn max_width(mut self, width: uint) -> PrettySerializer<W> {
+        self.max_width = Some(width);
+        self
     }
 
-    #[inline]
-    fn serialize_i32(&mut self, v: i32) -> IoResult<()> {
-        write!(self.wr, "{}", v)
+    /// Unwrap the Writer from the Serializer.
+    pub fn unwrap(self) -> W {
+        self.wr.wr
     }
 
+    // Containers are streamed straight to the writer unless they have to be
+    // laid out as a whole, in which case a tree is built and rendered once
+    // the outermost one ends.
     #[inline]
-    fn serialize_i64(&mut self, v: i64) -> IoResult<()> {
-        write!(self.wr, "{}", v)
+    fn buffered(&self) -> bool {
+        self.sort_keys || self.max_width.is_some()
     }
 
     #[inline]
-    fn serialize_uint(&mut self, v: uint) -> IoResult<()> {
-        write!(self.wr, "{}", v)
+    fn colon(&self) -> &'static str {
+        if self.space_after_colon { ": " } else { ":" }
     }
 
     #[inline]
-    fn serialize_u8(&mut self, v: u8) -> IoResult<()> {
-        write!(self.wr, "{}", v)
+    fn write_indent(&mut self, level: uint) -> IoResult<()> {
+        for _ in range(0, level) {
+            try!(self.wr.write_str(self.indent.as_slice()));
+        }
+        Ok(())
     }
 
     #[inline]
-    fn serialize_u16(&mut self, v: u16) -> IoResult<()> {
-        write!(self.wr, "{}", v)
-    }
+    fn serialize_start(&mut self, open: &'static str, sort: bool) -> IoResult<()> {
+        self.depth += 1;
 
-    #[inline]
-    fn serialize_u32(&mut self, v: u32) -> IoResult<()> {
-        write!(self.wr, "{}", v)
-    }
+        if self.frames.is_empty() && !self.buffered() {
+            self.first = true;
+            return self.wr.write_str(open);
+        }
 
-    #[inline]
-    fn serialize_u64(&mut self, v: u64) -> IoResult<()> {
-        write!(self.wr, "{}", v)
-    }
+        if self.wr.buf.is_none() {
+            self.wr.buf = Some(Vec::new());
+        }
 
-    #[inline]
-    fn serialize_f32(&mut self, v: f32) -> IoResult<()> {
-        fmt_f32_or_null(&mut self.wr, v)
-    }
+        self.frames.push(LayoutFrame {
+            open: open,
+            sort: sort,
+            entries: Vec::new(),
+        });
 
-    #[inline]
-    fn serialize_f64(&mut self, v: f64) -> IoResult<()> {
-        fmt_f64_or_null(&mut self.wr, v)
+        Ok(())
     }
 
     #[inline]
-    fn serialize_char(&mut self, v: char) -> IoResult<()> {
-        escape_char(&mut self.wr, v)
-    }
+    fn serialize_sep(&mut self) -> IoResult<()> {
+        if self.frames.is_empty() {
+            if self.first {
+                self.first = false;
+                self.level += 1;
+                try!(self.wr.write_str("\n"));
+            } else {
+                try!(self.wr.write_str(",\n"));
+            }
 
-    #[inline]
-    fn serialize_str(&mut self, v: &str) -> IoResult<()> {
-        escape_str(&mut self.wr, v)
-    }
+            let level = self.level;
+            return self.write_indent(level);
+        }
 
-    #[inline]
-    fn serialize_tuple_start(&mut self, _len: uint) -> IoResult<()> {
-        self.first = true;
-        self.wr.write_str("[")
-    }
+        self.finish_entry();
+        self.last_str = None;
 
-    #[inline]
-    fn serialize_tuple_elt<
-        T: Serializable<PrettySerializer<W>, io::IoError>
-    >(&mut self, value: &T) -> IoResult<()> {
-        try!(self.serialize_sep());
-        value.serialize(self)
-    }
+        let frame = self.frames.last_mut().unwrap();
+        frame.entries.push(LayoutEntry { key: None, sort_key: None, value: None });
 
-    #[inline]
-    fn serialize_tuple_end(&mut self) -> IoResult<()> {
-        self.serialize_end("]")
+        Ok(())
     }
 
     #[inline]
-    fn serialize_struct_start(&mut self, _name: &str, _len: uint) -> IoResult<()> {
-        self.first = true;
-        self.wr.write_str("{")
-    }
+    fn serialize_colon(&mut self) -> IoResult<()> {
+        if self.frames.is_empty() {
+            let colon = self.colon();
+            return self.wr.write_str(colon);
+        }
 
-    #[inline]
-    fn serialize_struct_elt<
-        T: Serializable<PrettySerializer<W>, io::IoError>
-    >(&mut self, name: &str, value: &T) -> IoResult<()> {
-        try!(self.serialize_sep());
-        try!(self.serialize_str(name));
-        try!(self.wr.write_str(": "));
-        value.serialize(self)
+        let key = self.wr.take();
+        let sort_key = self.last_str.take();
+
+        match self.frames.last_mut().unwrap().entries.last_mut() {
+            Some(entry) => {
+                entry.key = Some(key);
+                entry.sort_key = sort_key;
+            }
+            None => { }
+        }
+
+        Ok(())
     }
 
     #[inline]
-    fn serialize_struct_end(&mut self) -> IoResult<()> {
-        self.serialize_end("}")
+    fn serialize_end(&mut self, close: &'static str) -> IoResult<()> {
+        self.depth -= 1;
+
+        if self.frames.is_empty() {
+            if !self.first {
+                try!(self.wr.write_str("\n"));
+                self.level -= 1;
+                let level = self.level;
+                try!(self.write_indent(level));
+            }
+
+            self.first = false;
+
+            try!(self.wr.write_str(close));
+            return self.serialize_value_end();
+        }
+
+        self.finish_entry();
+
+        let frame = self.frames.pop().unwrap();
+        let mut entries = frame.entries;
+
+        if frame.sort {
+            entries.sort_by(|a, b| layout_sort_key(a).cmp(&layout_sort_key(b)));
+        }
+
+        let node = LayoutContainer(frame.open, entries, close);
+
+        match self.frames.last_mut() {
+            Some(parent) => {
+                match parent.entries.last_mut() {
+                    Some(entry) => { entry.value = Some(node); }
+                    None => { }
+                }
+                return Ok(());
+            }
+            None => { }
+        }
+
+        self.wr.buf = None;
+        try!(self.render(&node, 0, 0, 0));
+        self.serialize_value_end()
     }
 
+    // Keeps `v` to sort on in case it is a key.
     #[inline]
-    fn serialize_enum_start(&mut self, _name: &str, variant: &str, _len: uint) -> IoResult<()> {
-        self.first = true;
-        try!(self.wr.write_str("{"));
-        try!(self.serialize_sep());
-        try!(self.serialize_str(variant));
-        self.first = true;
-        self.wr.write_str(": [")
+    fn record_str(&mut self, v: &str) {
+        match self.frames.last() {
+            Some(frame) if frame.sort => { self.last_str = Some(v.to_string()); }
+            _ => { }
+        }
     }
 
     #[inline]
-    fn serialize_enum_elt<
-        T: Serializable<PrettySerializer<W>, io::IoError>
-    >(&mut self, value: &T) -> IoResult<()> {
-        try!(self.serialize_sep());
-        value.serialize(self)
+    fn serialize_value_end(&mut self) -> IoResult<()> {
+        if self.depth == 0 && self.trailing_newline {
+            self.wr.write_str("\n")
+        } else {
+            Ok(())
+        }
     }
 
-    #[inline]
-    fn serialize_enum_end(&mut self) -> IoResult<()> {
-        try!(self.serialize_tuple_end());
-        self.serialize_struct_end()
+    // Stores whatever scalar was written since the last separator as the
+    // value of the current entry.
+    fn finish_entry(&mut self) {
+        let value = self.wr.take();
+
+        match self.frames.last_mut().unwrap().entries.last_mut() {
+            Some(entry) => {
+                if entry.value.is_none() {
+                    entry.value = Some(LayoutScalar(value));
+                }
+            }
+            None => { }
+        }
     }
 
-    #[inline]
-    fn serialize_option<
-        T: Serializable<PrettySerializer<W>, io::IoError>
-    >(&mut self, v: &Option<T>) -> IoResult<()> {
+    // Returns the single line length of `node`, or `None` if it is longer
+    // than `limit`.
+    fn flat_len(&self, node: &LayoutNode, limit: uint) -> Option<uint> {
+        let (open, entries, close) = match *node {
+            LayoutScalar(ref v) => {
+                let len = display_len(v.as_slice());
+                return if len > limit { None } else { Some(len) };
+            }
+            LayoutContainer(open, ref entries, close) => (open, entries, close),
+        };
+
+        let mut len = open.len() + close.len();
+
+        for (i, entry) in entries.iter().enumerate() {
+            if i != 0 {
+                len += 2;
+            }
+
+            match entry.key {
+                Some(ref key) => { len += display_len(key.as_slice()) + self.colon().len(); }
+                None => { }
+            }
+
+            if len > limit {
+                return None;
+            }
+
+            match entry.value {
+                Some(ref value) => {
+                    match self.flat_len(value, limit - len) {
+                        Some(value_len) => { len += value_len; }
+                        None => { return None; }
+                    }
+                }
+                None => { }
+            }
+        }
+
+        if len > limit { None } else { Some(len) }
+    }
+
+    fn render_flat(&mut self, node: &LayoutNode) -> IoResult<()> {
+        match *node {
+            LayoutScalar(ref v) => self.wr.write(v.as_slice()),
+            LayoutContainer(open, ref entries, close) => {
+                try!(self.wr.write_str(open));
+
+                for (i, entry) in entries.iter().enumerate() {
+                    if i != 0 {
+                        try!(self.wr.write_str(", "));
+                    }
+
+                    try!(self.render_key(&entry.key));
+
+                    match entry.value {
+                        Some(ref value) => { try!(self.render_flat(value)); }
+                        None => { }
+                    }
+                }
+
+                self.wr.write_str(close)
+            }
+        }
+    }
+
+    // Renders `node` starting at `column`, where `suffix` more characters
+    // have to fit on the same line after it.
+    fn render(&mut self,
+              node: &LayoutNode,
+              level: uint,
+              column: uint,
+              suffix: uint) -> IoResult<()> {
+        let (open, entries, close) = match *node {
+            LayoutScalar(ref v) => { return self.wr.write(v.as_slice()); }
+            LayoutContainer(open, ref entries, close) => (open, entries, close),
+        };
+
+        let fits = match self.max_width {
+            Some(width) if width >= column + suffix => {
+                self.flat_len(node, width - column - suffix).is_some()
+            }
+            _ => false,
+        };
+
+        if fits || entries.is_empty() {
+            return self.render_flat(node);
+        }
+
+        try!(self.wr.write_str(open));
+
+        for (i, entry) in entries.iter().enumerate() {
+            if i != 0 {
+                try!(self.wr.write_str(","));
+            }
+
+            try!(self.wr.write_str("\n"));
+            try!(self.write_indent(level + 1));
+            try!(self.render_key(&entry.key));
+
+            let mut column = display_len(self.indent.as_bytes()) * (level + 1);
+            match entry.key {
+                Some(ref key) => { column += display_len(key.as_slice()) + self.colon().len(); }
+                None => { }
+            }
+
+            let suffix = if i + 1 == entries.len() { 0 } else { 1 };
+
+            match entry.value {
+                Some(ref value) => { try!(self.render(value, level + 1, column, suffix)); }
+                None => { }
+            }
+        }
+
+        try!(self.wr.write_str("\n"));
+        try!(self.write_indent(level));
+        self.wr.write_str(close)
+    }
+
+    #[inline]
+    fn render_key(&mut self, key: &Option<Vec<u8>>) -> IoResult<()> {
+        match *key {
+            Some(ref key) => {
+                try!(self.wr.write(key.as_slice()));
+                let colon = self.colon();
+                self.wr.write_str(colon)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Passes writes through to the underlying writer, or collects them while
+/// `PrettySerializer` is building a layout tree.
+struct LayoutWriter<W> {
+    wr: W,
+    buf: Option<Vec<u8>>,
+}
+
+impl<W: Writer> LayoutWriter<W> {
+    #[inline]
+    fn take(&mut self) -> Vec<u8> {
+        match self.buf {
+            Some(ref mut buf) => mem::replace(buf, Vec::new()),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl<W: Writer> Writer for LayoutWriter<W> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> IoResult<()> {
+        match self.buf {
+            Some(ref mut b) => {
+                b.push_all(buf);
+                Ok(())
+            }
+            None => self.wr.write(buf),
+        }
+    }
+}
+
+enum LayoutNode {
+    LayoutScalar(Vec<u8>),
+    LayoutContainer(&'static str, Vec<LayoutEntry>, &'static str),
+}
+
+struct LayoutEntry {
+    key: Option<Vec<u8>>,
+    /// The key before it was escaped, if it was a string.
+    sort_key: Option<string::String>,
+    value: Option<LayoutNode>,
+}
+
+struct LayoutFrame {
+    open: &'static str,
+    sort: bool,
+    entries: Vec<LayoutEntry>,
+}
+
+// String keys are compared as they were before escaping, as in a `TreeMap`.
+// Other keys are compared as written, without any quotes, so that `"1"`
+// sorts before `"1 2"`.
+fn layout_sort_key<'a>(entry: &'a LayoutEntry) -> &'a [u8] {
+    match entry.sort_key {
+        Some(ref key) => { return key.as_bytes(); }
+        None => { }
+    }
+
+    match entry.key {
+        Some(ref key) => {
+            let key = key.as_slice();
+            if key.len() >= 2 && key[0] == b'"' && key[key.len() - 1] == b'"' {
+                key.slice(1, key.len() - 1)
+            } else {
+                key
+            }
+        }
+        None => &[],
+    }
+}
+
+// The number of characters in a UTF-8 byte string.
+fn display_len(bytes: &[u8]) -> uint {
+    bytes.iter().filter(|b| **b & 0xC0 != 0x80).count()
+}
+
+impl<W: Writer> ser::Serializer<io::IoError> for PrettySerializer<W> {
+    #[inline]
+    fn serialize_null(&mut self) -> IoResult<()> {
+        try!(self.wr.write_str("null"));
+        self.serialize_value_end()
+    }
+
+    #[inline]
+    fn serialize_bool(&mut self, v: bool) -> IoResult<()> {
+        if v {
+            try!(self.wr.write_str("true"));
+        } else {
+            try!(self.wr.write_str("false"));
+        }
+        self.serialize_value_end()
+    }
+
+    #[inline]
+    fn serialize_int(&mut self, v: int) -> IoResult<()> {
+        try!(write!(self.wr, "{}", v));
+        self.serialize_value_end()
+    }
+
+    #[inline]
+    fn serialize_i8(&mut self, v: i8) -> IoResult<()> {
+        try!(write!(self.wr, "{}", v));
+        self.serialize_value_end()
+    }
+
+    #[inline]
+    fn serialize_i16(&mut self, v: i16) -> IoResult<()> {
+        try!(write!(self.wr, "{}", v));
+        self.serialize_value_end()
+    }
+
+    #[inline]
+    fn serialize_i32(&mut self, v: i32) -> IoResult<()> {
+        try!(write!(self.wr, "{}", v));
+        self.serialize_value_end()
+    }
+
+    #[inline]
+    fn serialize_i64(&mut self, v: i64) -> IoResult<()> {
+        try!(write!(self.wr, "{}", v));
+        self.serialize_value_end()
+    }
+
+    #[inline]
+    fn serialize_uint(&mut self, v: uint) -> IoResult<()> {
+        try!(write!(self.wr, "{}", v));
+        self.serialize_value_end()
+    }
+
+    #[inline]
+    fn serialize_u8(&mut self, v: u8) -> IoResult<()> {
+        try!(write!(self.wr, "{}", v));
+        self.serialize_value_end()
+    }
+
+    #[inline]
+    fn serialize_u16(&mut self, v: u16) -> IoResult<()> {
+        try!(write!(self.wr, "{}", v));
+        self.serialize_value_end()
+    }
+
+    #[inline]
+    fn serialize_u32(&mut self, v: u32) -> IoResult<()> {
+        try!(write!(self.wr, "{}", v));
+        self.serialize_value_end()
+    }
+
+    #[inline]
+    fn serialize_u64(&mut self, v: u64) -> IoResult<()> {
+        try!(write!(self.wr, "{}", v));
+        self.serialize_value_end()
+    }
+
+    #[inline]
+    fn serialize_f32(&mut self, v: f32) -> IoResult<()> {
+        try!(escape::fmt_f32(&mut self.wr, v, self.non_finite));
+        self.serialize_value_end()
+    }
+
+    #[inline]
+    fn serialize_f64(&mut self, v: f64) -> IoResult<()> {
+        try!(escape::fmt_f64(&mut self.wr, v, self.non_finite));
+        self.serialize_value_end()
+    }
+
+    #[inline]
+    fn serialize_number(&mut self, v: &str) -> IoResult<()> {
+        try!(self.wr.write_str(v));
+        self.serialize_value_end()
+    }
+
+    #[inline]
+    fn serialize_raw_json(&mut self, v: &str) -> Option<IoResult<()>> {
+        // The text may hold characters these options escape.
+        if self.escaping.ascii_only || self.escaping.html_safe {
+            return Some(raw_json_value(v).serialize(self));
+        }
+        Some(self.wr.write_str(v).and_then(|()| self.serialize_value_end()))
+    }
+
+    #[inline]
+    fn serialize_char(&mut self, v: char) -> IoResult<()> {
+        self.record_str(v.to_string().as_slice());
+        try!(escape_char_with(&mut self.wr, v, self.escaping));
+        self.serialize_value_end()
+    }
+
+    #[inline]
+    fn serialize_str(&mut self, v: &str) -> IoResult<()> {
+        self.record_str(v);
+        try!(escape_str_with(&mut self.wr, v, self.escaping));
+        self.serialize_value_end()
+    }
+
+    #[inline]
+    fn serialize_tuple_start(&mut self, _len: uint) -> IoResult<()> {
+        self.serialize_start("[", false)
+    }
+
+    #[inline]
+    fn serialize_tuple_elt<
+        T: Serializable<PrettySerializer<W>, io::IoError>
+    >(&mut self, value: &T) -> IoResult<()> {
+        try!(self.serialize_sep());
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_tuple_end(&mut self) -> IoResult<()> {
+        self.serialize_end("]")
+    }
+
+    #[inline]
+    fn serialize_struct_start(&mut self, _name: &str, _len: uint) -> IoResult<()> {
+        self.serialize_start("{", false)
+    }
+
+    #[inline]
+    fn serialize_struct_elt<
+        T: Serializable<PrettySerializer<W>, io::IoError>
+    >(&mut self, name: &str, value: &T) -> IoResult<()> {
+        try!(self.serialize_sep());
+        try!(self.serialize_str(name));
+        try!(self.serialize_colon());
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_struct_end(&mut self) -> IoResult<()> {
+        self.serialize_end("}")
+    }
+
+    #[inline]
+    fn serialize_enum_start(&mut self, _name: &str, variant: &str, _len: uint) -> IoResult<()> {
+        try!(self.serialize_start("{", false));
+        try!(self.serialize_sep());
+        try!(self.serialize_str(variant));
+        try!(self.serialize_colon());
+        self.serialize_start("[", false)
+    }
+
+    #[inline]
+    fn serialize_enum_elt<
+        T: Serializable<PrettySerializer<W>, io::IoError>
+    >(&mut self, value: &T) -> IoResult<()> {
+        try!(self.serialize_sep());
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_enum_end(&mut self) -> IoResult<()> {
+        try!(self.serialize_tuple_end());
+        self.serialize_struct_end()
+    }
+
+    #[inline]
+    fn serialize_option<
+        T: Serializable<PrettySerializer<W>, io::IoError>
+    >(&mut self, v: &Option<T>) -> IoResult<()> {
         match *v {
             Some(ref v) => {
                 v.serialize(self)
@@ -1394,9 +2084,8 @@ impl<W: Writer> ser::Serializer<io::IoError> for PrettySerializer<W> {
         T: Serializable<PrettySerializer<W>, io::IoError>,
         Iter: Iterator<T>
     >(&mut self, mut iter: Iter) -> IoResult<()> {
-        try!(self.wr.write_str("["));
+        try!(self.serialize_start("[", false));
 
-        self.first = true;
         for elt in iter {
             try!(self.serialize_sep());
             try!(elt.serialize(self));
@@ -1411,13 +2100,13 @@ impl<W: Writer> ser::Serializer<io::IoError> for PrettySerializer<W> {
         V: Serializable<PrettySerializer<W>, io::IoError>,
         Iter: Iterator<(K, V)>
     >(&mut self, mut iter: Iter) -> IoResult<()> {
-        try!(self.wr.write_str("{"));
+        let sort_keys = self.sort_keys;
+        try!(self.serialize_start("{", sort_keys));
 
-        self.first = true;
         for (key, value) in iter {
             try!(self.serialize_sep());
             try!(key.serialize(self));
-            try!(self.wr.write_str(": "));
+            try!(self.serialize_colon());
             try!(value.serialize(self));
         }
 
@@ -1425,15 +2114,17 @@ impl<W: Writer> ser::Serializer<io::IoError> for PrettySerializer<W> {
     }
 }
 
-/// Encode the specified struct into a json `[u8]` buffer.
+/// Encode the specified struct into a json `[u8]` buffer. `NaN` and the
+/// infinities are written as `null`; use a `Serializer` with
+/// `non_finite_floats` to refuse them or write them some other way.
 #[inline]
 pub fn to_vec<
     T: ser::Serializable<Serializer<MemWriter>, io::IoError>
 >(value: &T) -> Vec<u8> {
     let wr = MemWriter::with_capacity(1024);
-    let mut serializer = Serializer::new(wr);
-    // We are writing to a MemWriter, which doesn't fail. So we can ignore
-    // the error.
+    let mut serializer = Serializer::new(wr).non_finite_floats(NonFiniteAsNull);
+    // We are writing to a MemWriter, and non-finite floats don't fail, so
+    // this can't fail.
     value.serialize(&mut serializer).unwrap();
     serializer.unwrap().unwrap()
 }
@@ -1447,12 +2138,13 @@ pub fn to_string<
     string::String::from_utf8(buf)
 }
 
-/// Encode the specified struct into a json `[u8]` buffer.
+/// Encode the specified struct into a json `[u8]` buffer. `NaN` and the
+/// infinities are written as `null`, as `to_vec` does.
 pub fn to_pretty_vec<
     T: ser::Serializable<PrettySerializer<MemWriter>, io::IoError>
 >(value: &T) -> Vec<u8> {
     let wr = MemWriter::new();
-    let mut serializer = PrettySerializer::new(wr);
+    let mut serializer = PrettySerializer::new(wr).non_finite_floats(NonFiniteAsNull);
     value.serialize(&mut serializer).unwrap();
     serializer.unwrap().unwrap()
 }
@@ -1650,9 +2342,18 @@ pub struct Parser<Iter> {
     ch: Option<u8>,
     line: uint,
     col: uint,
+    // The number of bytes read so far.
+    read: uint,
     // A state machine is kept to make it possible to interupt and resume parsing.
     state_stack: Vec<ParserState>,
     buf: Vec<u8>,
+    raw_numbers: bool,
+    non_finite: NonFiniteFloats,
+    relaxed: bool,
+    // Where the most recently parsed value or key starts.
+    token_start: de::Position,
+    // The byte offset of the most recently parsed value.
+    token_offset: uint,
 }
 
 impl<Iter: Iterator<u8>> Iterator<Result<de::Token, ParserError>> for Parser<Iter> {
@@ -1664,7 +2365,10 @@ impl<Iter: Iterator<u8>> Iterator<Result<de::Token, ParserError>> for Parser<Ite
                 // If we have no state left, then we're expecting the structure
                 // to be done, so make sure there are no trailing characters.
 
-                self.parse_whitespace();
+                match self.parse_whitespace() {
+                    Ok(()) => { }
+                    Err(err) => { return Some(Err(err)); }
+                }
 
                 if self.eof() {
                     return None;
@@ -1707,27 +2411,79 @@ impl<Iter: Iterator<u8>> Parser<Iter> {
             ch: Some(b'\x00'),
             line: 1,
             col: 0,
+            read: 0,
             state_stack: vec!(ParseValue),
             buf: Vec::with_capacity(100),
+            raw_numbers: false,
+            non_finite: NonFiniteError,
+            relaxed: false,
+            token_start: de::Position { line: 1, col: 1 },
+            token_offset: 0,
         };
         p.bump();
         return p;
     }
 
+    /// Makes the parser emit every number as a `de::Number` holding its exact
+    /// text, instead of converting it to an integer or a float.
+    #[inline]
+    pub fn raw_numbers(mut self, raw_numbers: bool) -> Parser<Iter> {
+        self.raw_numbers = raw_numbers;
+        self
+    }
+
+    /// Accepts `NaN` and the infinities in the form chosen for
+    /// `Serializer::non_finite_floats`. By default they are rejected. With
+    /// `NonFiniteAsNull`, a `null` read as a float becomes `NaN`. With
+    /// `NonFiniteAsString`, the strings are accepted where a number is
+    /// expected, and with `NonFiniteAsLiteral` the literals anywhere a value
+    /// is.
+    #[inline]
+    pub fn non_finite_floats(mut self, non_finite: NonFiniteFloats) -> Parser<Iter> {
+        self.non_finite = non_finite;
+        self
+    }
+
+    /// Accepts a relaxed, JSON5 style syntax: `//` and `/* */` comments,
+    /// trailing commas, single quoted strings, unquoted ASCII identifiers as
+    /// object keys, hexadecimal integers and a leading `+` on numbers. The
+    /// tokens produced are the same as for the equivalent strict JSON.
+    #[inline]
+    pub fn relaxed(mut self, relaxed: bool) -> Parser<Iter> {
+        self.relaxed = relaxed;
+        self
+    }
+
     #[inline(always)]
     fn eof(&self) -> bool { self.ch.is_none() }
 
     #[inline]
     fn ch_or_null(&self) -> u8 { self.ch.unwrap_or(b'\x00') }
 
+    /// The byte offset of the current character, or the length of the input
+    /// once it has all been read.
+    #[inline]
+    fn offset(&self) -> uint {
+        if self.ch.is_some() { self.read - 1 } else { self.read }
+    }
+
     #[inline(always)]
     fn bump(&mut self) {
+        // A newline ends its line, so the next line starts with the
+        // character after it.
+        let newline = self.ch_is(b'\n');
         self.ch = self.rdr.next();
 
-        if self.ch_is(b'\n') {
+        if self.ch.is_some() {
+            self.read += 1;
+        }
+
+        // Columns count characters, so the rest of a UTF-8 sequence stays in
+        // the column of its first byte.
+        if newline {
             self.line += 1;
             self.col = 1;
-        } else {
+        } else if self.ch.map_or(true, |ch| ch & 0xC0 != 0x80) {
             self.col += 1;
         }
     }
@@ -1745,50 +2501,129 @@ impl<Iter: Iterator<u8>> Parser<Iter> {
 
     #[inline]
     fn error<T>(&self, reason: ErrorCode) -> Result<T, ParserError> {
-        Err(SyntaxError(reason, self.line, self.col))
+        Err(SyntaxError(reason, self.line, self.col, self.offset()))
     }
 
     #[inline]
-    fn parse_whitespace(&mut self) {
-        while self.ch_is(b' ') ||
-              self.ch_is(b'\n') ||
-              self.ch_is(b'\t') ||
-              self.ch_is(b'\r') { self.bump(); }
+    fn parse_whitespace(&mut self) -> Result<(), ParserError> {
+        loop {
+            while self.ch_is(b' ') ||
+                  self.ch_is(b'\n') ||
+                  self.ch_is(b'\t') ||
+                  self.ch_is(b'\r') { self.bump(); }
+
+            if self.relaxed && self.ch_is(b'/') {
+                try!(self.parse_comment());
+            } else {
+                return Ok(());
+            }
+        }
     }
 
-    #[inline]
-    fn parse_number(&mut self) -> Result<de::Token, ParserError> {
-        let mut neg = 1;
+    // Skips a comment. A `/` that doesn't start one, and a block comment
+    // that runs to the end of the input, are reported where they start.
+    fn parse_comment(&mut self) -> Result<(), ParserError> {
+        let (line, col, offset) = (self.line, self.col, self.offset());
+        self.bump();
 
-        if self.ch_is(b'-') {
-            self.bump();
-            neg = -1;
+        if self.ch_is(b'/') {
+            while !self.eof() && !self.ch_is(b'\n') {
+                self.bump();
+            }
+            return Ok(());
         }
 
-        let res = try!(self.parse_integer());
+        if !self.ch_is(b'*') {
+            return Err(SyntaxError(InvalidSyntax(SomeValue), line, col, offset));
+        }
 
-        if self.ch_is(b'.') || self.ch_is(b'e') || self.ch_is(b'E') {
-            let neg = neg as f64;
-            let mut res = res as f64;
+        self.bump();
 
-            if self.ch_is(b'.') {
-                res = try!(self.parse_decimal(res));
+        while !self.eof() {
+            if self.ch_is(b'*') {
+                self.bump();
+
+                if self.ch_is(b'/') {
+                    self.bump();
+                    return Ok(());
+                }
+            } else {
+                self.bump();
+            }
+        }
+
+        Err(SyntaxError(EOFWhileParsingValue, line, col, offset))
+    }
+
+    #[inline]
+    fn parse_number(&mut self) -> Result<de::Token, ParserError> {
+        let mut neg = false;
+
+        if self.relaxed && self.ch_is(b'+') {
+            self.bump();
+        } else if self.ch_is(b'-') {
+            self.bump();
+            neg = true;
+
+            if self.ch_is(b'I') && self.non_finite == NonFiniteAsLiteral {
+                return self.parse_ident(b"nfinity", de::F64(f64::NEG_INFINITY));
+            }
+        }
+
+        if self.raw_numbers {
+            return self.parse_raw_number(neg);
+        }
+
+        // The digits are gathered in `buf` so that floats can be converted
+        // with a single rounding.
+        self.buf.clear();
+        try!(self.parse_integer());
+
+        if self.ch_is(b'.') || self.ch_is(b'e') || self.ch_is(b'E') {
+            let mut exp = 0;
+
+            if self.ch_is(b'.') {
+                exp = try!(self.parse_decimal());
             }
 
             if self.ch_is(b'e') || self.ch_is(b'E') {
-                res = try!(self.parse_exponent(res));
+                exp += try!(self.parse_exponent());
             }
 
-            Ok(de::F64(neg * res))
+            let res = strtod::parse_f64(self.buf.as_slice(), exp);
+            Ok(de::F64(if neg { -res } else { res }))
         } else {
-            Ok(de::I64(neg * res))
+            let mut res = Some(0u64);
+            for &c in self.buf.iter() {
+                let digit = (c - b'0') as u64;
+                res = res
+                    .and_then(|res| res.checked_mul(&10))
+                    .and_then(|res| res.checked_add(&digit));
+            }
+
+            match res {
+                Some(res) if neg => {
+                    if res <= i64::MAX as u64 {
+                        Ok(de::I64(-(res as i64)))
+                    } else if res == i64::MAX as u64 + 1 {
+                        Ok(de::I64(i64::MIN))
+                    } else {
+                        Ok(de::F64(-strtod::parse_f64(self.buf.as_slice(), 0)))
+                    }
+                }
+                Some(res) if res > i64::MAX as u64 => Ok(de::U64(res)),
+                Some(res) => Ok(de::I64(res as i64)),
+                None => {
+                    let res = strtod::parse_f64(self.buf.as_slice(), 0);
+                    Ok(de::F64(if neg { -res } else { res }))
+                }
+            }
         }
     }
 
+    /// Gathers the digits of the integer part of a number into `buf`.
     #[inline]
-    fn parse_integer(&mut self) -> Result<i64, ParserError> {
-        let mut res = 0;
-
+    fn parse_integer(&mut self) -> Result<(), ParserError> {
         match self.ch_or_null() {
             b'0' => {
                 self.bump();
@@ -1796,15 +2631,18 @@ impl<Iter: Iterator<u8>> Parser<Iter> {
                 // There can be only one leading '0'.
                 match self.ch_or_null() {
                     b'0' ... b'9' => return self.error(InvalidNumber),
-                    _ => ()
+                    b'x' | b'X' if self.relaxed => {
+                        let value = try!(self.parse_hex_integer());
+                        self.buf.push_all(value.to_string().as_bytes());
+                    }
+                    _ => self.buf.push(b'0'),
                 }
             },
             b'1' ... b'9' => {
                 while !self.eof() {
                     match self.ch_or_null() {
                         c @ b'0' ... b'9' => {
-                            res *= 10;
-                            res += (c as i64) - ('0' as i64);
+                            self.buf.push(c);
                             self.bump();
                         }
                         _ => break,
@@ -1814,11 +2652,113 @@ impl<Iter: Iterator<u8>> Parser<Iter> {
             _ => return self.error(InvalidNumber),
         }
 
-        Ok(res)
+        Ok(())
     }
 
+    /// Parses a number without converting it, keeping its exact text.
+    fn parse_raw_number(&mut self, neg: bool) -> Result<de::Token, ParserError> {
+        let mut number = string::String::new();
+
+        if neg {
+            number.push('-');
+        }
+
+        match self.ch_or_null() {
+            b'0' => {
+                self.bump();
+
+                // There can be only one leading '0'.
+                match self.ch_or_null() {
+                    b'0' ... b'9' => return self.error(InvalidNumber),
+                    b'x' | b'X' if self.relaxed => {
+                        let value = try!(self.parse_hex_integer());
+                        number.push_str(value.to_string().as_slice());
+                    }
+                    _ => number.push('0'),
+                }
+            }
+            b'1' ... b'9' => {
+                try!(self.parse_raw_digits(&mut number));
+            }
+            _ => return self.error(InvalidNumber),
+        }
+
+        if self.ch_is(b'.') {
+            number.push('.');
+            self.bump();
+            try!(self.parse_raw_digits(&mut number));
+        }
+
+        if self.ch_is(b'e') || self.ch_is(b'E') {
+            number.push(self.ch_or_null() as char);
+            self.bump();
+
+            if self.ch_is(b'+') || self.ch_is(b'-') {
+                number.push(self.ch_or_null() as char);
+                self.bump();
+            }
+
+            try!(self.parse_raw_digits(&mut number));
+        }
+
+        Ok(de::Number(number))
+    }
+
+    /// Appends one or more digits to `number`.
     #[inline]
-    fn parse_decimal(&mut self, res: f64) -> Result<f64, ParserError> {
+    fn parse_raw_digits(&mut self, number: &mut string::String) -> Result<(), ParserError> {
+        // Make sure there is at least one digit.
+        match self.ch_or_null() {
+            b'0' ... b'9' => (),
+            _ => return self.error(InvalidNumber)
+        }
+
+        while !self.eof() {
+            match self.ch_or_null() {
+                c @ b'0' ... b'9' => {
+                    number.push(c as char);
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parses the digits of a hexadecimal integer, after its `0x` prefix.
+    fn parse_hex_integer(&mut self) -> Result<u64, ParserError> {
+        self.bump();
+
+        let mut value = None;
+
+        loop {
+            let digit = match self.ch_or_null() {
+                c @ b'0' ... b'9' => c - b'0',
+                c @ b'a' ... b'f' => c - b'a' + 10,
+                c @ b'A' ... b'F' => c - b'A' + 10,
+                _ => break,
+            };
+
+            value = match value.unwrap_or(0u64).checked_mul(&16) {
+                Some(value) => Some(value + digit as u64),
+                None => { return self.error(InvalidNumber); }
+            };
+
+            self.bump();
+        }
+
+        // Hexadecimal numbers have neither a fraction nor an exponent.
+        match value {
+            Some(value) if !self.ch_is(b'.') => Ok(value),
+            _ => self.error(InvalidNumber),
+        }
+    }
+
+    /// Gathers the digits of the fractional part of a number into `buf`,
+    /// returning the power of ten they have to be scaled by.
+    #[inline]
+    fn parse_decimal(&mut self) -> Result<int, ParserError> {
         self.bump();
 
         // Make sure a digit follows the decimal place.
@@ -1827,27 +2767,26 @@ impl<Iter: Iterator<u8>> Parser<Iter> {
              _ => return self.error(InvalidNumber)
         }
 
-        let mut res = res;
-        let mut dec = 1.0;
+        let mut exp = 0;
         while !self.eof() {
             match self.ch_or_null() {
                 c @ b'0' ... b'9' => {
-                    dec /= 10.0;
-                    res += (((c as int) - (b'0' as int)) as f64) * dec;
+                    self.buf.push(c);
+                    exp -= 1;
                     self.bump();
                 }
                 _ => break,
             }
         }
 
-        Ok(res)
+        Ok(exp)
     }
 
     #[inline]
-    fn parse_exponent(&mut self, mut res: f64) -> Result<f64, ParserError> {
+    fn parse_exponent(&mut self) -> Result<int, ParserError> {
         self.bump();
 
-        let mut exp = 0u;
+        let mut exp = 0;
         let mut neg_exp = false;
 
         if self.ch_is(b'+') {
@@ -1865,8 +2804,11 @@ impl<Iter: Iterator<u8>> Parser<Iter> {
         while !self.eof() {
             match self.ch_or_null() {
                 c @ b'0' ... b'9' => {
-                    exp *= 10;
-                    exp += (c as uint) - (b'0' as uint);
+                    // Anything this large is already out of range, so stop
+                    // growing rather than overflow.
+                    if exp < 1000000 {
+                        exp = exp * 10 + (c - b'0') as int;
+                    }
 
                     self.bump();
                 }
@@ -1874,14 +2816,11 @@ impl<Iter: Iterator<u8>> Parser<Iter> {
             }
         }
 
-        let exp: f64 = num::pow(10u as f64, exp);
         if neg_exp {
-            res /= exp;
+            Ok(-exp)
         } else {
-            res *= exp;
+            Ok(exp)
         }
-
-        Ok(res)
     }
 
     #[inline]
@@ -1916,6 +2855,8 @@ impl<Iter: Iterator<u8>> Parser<Iter> {
     fn parse_string(&mut self) -> Result<&str, ParserError> {
         self.buf.clear();
 
+        // Relaxed mode also allows single quoted strings.
+        let quote = self.ch_or_null();
         let mut escape = false;
 
 
@@ -1928,6 +2869,7 @@ impl<Iter: Iterator<u8>> Parser<Iter> {
             if escape {
                 match ch {
                     b'"' => self.buf.push(b'"'),
+                    b'\'' if self.relaxed => self.buf.push(b'\''),
                     b'\\' => self.buf.push(b'\\'),
                     b'/' => self.buf.push(b'/'),
                     b'b' => self.buf.push(b'\x08'),
@@ -1971,7 +2913,7 @@ impl<Iter: Iterator<u8>> Parser<Iter> {
                 escape = false;
             } else {
                 match ch {
-                    b'"' => {
+                    ch if ch == quote => {
                         self.bump();
                         return Ok(str::from_utf8(self.buf.as_slice()).unwrap());
                     }
@@ -1988,38 +2930,60 @@ impl<Iter: Iterator<u8>> Parser<Iter> {
 
     #[inline]
     fn parse_list_start(&mut self) -> Result<de::Token, ParserError> {
-        self.parse_whitespace();
-
-        if self.ch_is(b']') {
-            self.bump();
-            Ok(de::End)
-        } else {
-            self.state_stack.push(ParseListCommaOrEnd);
+        if try!(self.parse_list_elt_start(true)) {
             self.parse_value()
+        } else {
+            Ok(de::End)
         }
     }
 
     #[inline]
     fn parse_list_comma_or_end(&mut self) -> Result<de::Token, ParserError> {
-        self.parse_whitespace();
-
-        if self.ch_is(b',') {
-            self.bump();
-            self.state_stack.push(ParseListCommaOrEnd);
+        if try!(self.parse_list_elt_start(false)) {
             self.parse_value()
-        } else if self.ch_is(b']') {
-            self.bump();
-            Ok(de::End)
-        } else if self.eof() {
-            self.error_event(EOFWhileParsingList)
         } else {
-            self.error_event(InvalidSyntax(ListCommaOrEnd))
+            Ok(de::End)
         }
     }
 
+    // Reads up to the next element of a list, or past the end of the list,
+    // returning whether there is an element. `first` is true at the start of
+    // the list, where there is no comma.
+    #[inline]
+    fn parse_list_elt_start(&mut self, first: bool) -> Result<bool, ParserError> {
+        try!(self.parse_whitespace());
+
+        if self.ch_is(b']') {
+            self.bump();
+            return Ok(false);
+        }
+
+        if !first {
+            if self.ch_is(b',') {
+                self.bump();
+
+                if self.relaxed {
+                    try!(self.parse_whitespace());
+
+                    if self.ch_is(b']') {
+                        self.bump();
+                        return Ok(false);
+                    }
+                }
+            } else if self.eof() {
+                return self.error_event(EOFWhileParsingList);
+            } else {
+                return self.error_event(InvalidSyntax(ListCommaOrEnd));
+            }
+        }
+
+        self.state_stack.push(ParseListCommaOrEnd);
+        Ok(true)
+    }
+
     #[inline]
     fn parse_object_start(&mut self) -> Result<Option<&str>, ParserError> {
-        self.parse_whitespace();
+        try!(self.parse_whitespace());
 
         if self.ch_is(b'}') {
             self.bump();
@@ -2031,10 +2995,20 @@ impl<Iter: Iterator<u8>> Parser<Iter> {
 
     #[inline]
     fn parse_object_comma_or_end(&mut self) -> Result<Option<&str>, ParserError> {
-        self.parse_whitespace();
+        try!(self.parse_whitespace());
 
         if self.ch_is(b',') {
             self.bump();
+
+            if self.relaxed {
+                try!(self.parse_whitespace());
+
+                if self.ch_is(b'}') {
+                    self.bump();
+                    return Ok(None);
+                }
+            }
+
             Ok(Some(try!(self.parse_object_key())))
         } else if self.ch_is(b'}') {
             self.bump();
@@ -2048,7 +3022,8 @@ impl<Iter: Iterator<u8>> Parser<Iter> {
 
     #[inline]
     fn parse_object_key(&mut self) -> Result<&str, ParserError> {
-        self.parse_whitespace();
+        try!(self.parse_whitespace());
+        self.token_start = de::Position { line: self.line, col: self.col };
 
         if self.eof() {
             return self.error_event(EOFWhileParsingString);
@@ -2058,91 +3033,395 @@ impl<Iter: Iterator<u8>> Parser<Iter> {
             b'"' => {
                 self.state_stack.push(ParseObjectValue);
 
-                Ok(try!(self.parse_string()))
+                Ok(try!(self.parse_string()))
+            }
+            b'\'' if self.relaxed => {
+                self.state_stack.push(ParseObjectValue);
+
+                Ok(try!(self.parse_string()))
+            }
+            b'a' ... b'z' | b'A' ... b'Z' | b'_' | b'$' if self.relaxed => {
+                self.state_stack.push(ParseObjectValue);
+
+                Ok(self.parse_identifier())
+            }
+            _ => self.error_event(KeyMustBeAString),
+        }
+    }
+
+    // Reads an unquoted object key in relaxed mode.
+    #[inline]
+    fn parse_identifier(&mut self) -> &str {
+        self.buf.clear();
+
+        loop {
+            match self.ch_or_null() {
+                c @ b'a' ... b'z' | c @ b'A' ... b'Z' | c @ b'0' ... b'9' | c @ b'_' | c @ b'$' => {
+                    self.buf.push(c);
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+
+        str::from_utf8(self.buf.as_slice()).unwrap()
+    }
+
+    #[inline]
+    fn parse_object_value(&mut self) -> Result<de::Token, ParserError> {
+        try!(self.parse_object_colon());
+        self.parse_value()
+    }
+
+    #[inline]
+    fn parse_object_colon(&mut self) -> Result<(), ParserError> {
+        try!(self.parse_whitespace());
+
+        if self.ch_is(b':') {
+            self.bump();
+            self.state_stack.push(ParseObjectCommaOrEnd);
+            Ok(())
+        } else if self.eof() {
+            self.error_event(EOFWhileParsingObject)
+        } else {
+            self.error_event(ExpectedColon)
+        }
+    }
+
+    /// Reads up to the start of the next value, which is left for a
+    /// `ParseValue` state on top of the stack. Returns false if the list
+    /// being read ends instead, or if no value is expected next.
+    fn parse_to_value(&mut self) -> Result<bool, ParserError> {
+        let more = match self.state_stack.last() {
+            Some(&ParseValue) => { return Ok(true); }
+            Some(&ParseListStart) => {
+                self.state_stack.pop();
+                try!(self.parse_list_elt_start(true))
+            }
+            Some(&ParseListCommaOrEnd) => {
+                self.state_stack.pop();
+                try!(self.parse_list_elt_start(false))
+            }
+            Some(&ParseObjectValue) => {
+                self.state_stack.pop();
+                try!(self.parse_object_colon());
+                true
+            }
+            _ => false,
+        };
+
+        if more {
+            self.state_stack.push(ParseValue);
+        }
+
+        Ok(more)
+    }
+
+    #[inline]
+    fn parse_value(&mut self) -> Result<de::Token, ParserError> {
+        try!(self.parse_whitespace());
+        self.token_start = de::Position { line: self.line, col: self.col };
+        self.token_offset = self.offset();
+
+        if self.eof() {
+            return self.error_event(EOFWhileParsingValue);
+        }
+
+        match self.ch_or_null() {
+            b'n' => self.parse_ident(b"ull", de::Null),
+            b't' => self.parse_ident(b"rue", de::Bool(true)),
+            b'f' => self.parse_ident(b"alse", de::Bool(false)),
+            b'0' ... b'9' | b'-' => self.parse_number(),
+            b'N' if self.non_finite == NonFiniteAsLiteral => {
+                self.parse_ident(b"aN", de::F64(f64::NAN))
+            }
+            b'I' if self.non_finite == NonFiniteAsLiteral => {
+                self.parse_ident(b"nfinity", de::F64(f64::INFINITY))
+            }
+            b'+' if self.relaxed => self.parse_number(),
+            b'"' => {
+                Ok(de::String(try!(self.parse_string()).to_string()))
+            }
+            b'\'' if self.relaxed => {
+                Ok(de::String(try!(self.parse_string()).to_string()))
+            }
+            b'[' => {
+                self.bump();
+                self.state_stack.push(ParseListStart);
+                Ok(de::SeqStart(0))
+            }
+            b'{' => {
+                self.bump();
+                self.state_stack.push(ParseObjectStart);
+                Ok(de::MapStart(0))
+            }
+            _ => {
+                self.error_event(InvalidSyntax(SomeValue))
+            }
+        }
+    }
+
+    #[inline]
+    fn parse_ident(&mut self, ident: &[u8], token: de::Token) -> Result<de::Token, ParserError> {
+        if ident.iter().all(|c| Some(*c) == self.next_char()) {
+            self.bump();
+            Ok(token)
+        } else {
+            self.error_event(InvalidSyntax(SomeIdent))
+        }
+    }
+
+    #[inline]
+    fn error_event<T>(&mut self, reason: ErrorCode) -> Result<T, ParserError> {
+        self.state_stack.clear();
+        Err(SyntaxError(reason, self.line, self.col, self.offset()))
+    }
+
+    /// Reads the next value and returns its source text unchanged.
+    fn parse_raw_value(&mut self) -> Result<string::String, ParserError> {
+        if !try!(self.parse_to_value()) {
+            return self.error_event(InvalidSyntax(SomeValue));
+        }
+
+        self.state_stack.pop();
+        try!(self.parse_whitespace());
+        self.token_start = de::Position { line: self.line, col: self.col };
+        self.token_offset = self.offset();
+
+        let raw = self.scan_raw_value(Vec::new(), 0);
+        self.check_raw_value(raw)
+    }
+
+    /// Returns the text of the value starting with `token`, which has already
+    /// been read. Lists and objects are read on from their opening bracket;
+    /// other values have been read in full, so they are written out again.
+    fn parse_raw_token(&mut self, token: de::Token) -> Result<string::String, ParserError> {
+        let open = match token {
+            de::SeqStart(_) => b'[',
+            de::MapStart(_) => b'{',
+            token => {
+                let value: Json = try!(de::Deserializable::deserialize_token(self, token));
+                return Ok(value.to_string());
+            }
+        };
+
+        // Drop the state that would have read the rest of the list or object.
+        self.state_stack.pop();
+
+        let raw = self.scan_raw_value(vec![open], 1);
+        self.check_raw_value(raw)
+    }
+
+    // Copies the bytes of a value out of the input, following strings,
+    // comments and brackets to find its end but not otherwise checking it.
+    // `depth` is the number of brackets already opened in `raw`.
+    fn scan_raw_value(&mut self, mut raw: Vec<u8>, mut depth: uint) -> Vec<u8> {
+        loop {
+            let ch = match self.ch {
+                Some(ch) => ch,
+                None => { return raw; }
+            };
+
+            match ch {
+                b'"' => { self.scan_raw_string(&mut raw, ch); }
+                b'\'' if self.relaxed => { self.scan_raw_string(&mut raw, ch); }
+                b'[' | b'{' => {
+                    raw.push(ch);
+                    self.bump();
+                    depth += 1;
+                    continue;
+                }
+                b']' | b'}' => {
+                    if depth == 0 {
+                        return raw;
+                    }
+
+                    raw.push(ch);
+                    self.bump();
+                    depth -= 1;
+                }
+                b'/' if self.relaxed && depth > 0 => {
+                    self.scan_raw_comment(&mut raw);
+                    continue;
+                }
+                b' ' | b'\n' | b'\r' | b'\t' | b',' | b':' | b'/' if depth == 0 => {
+                    return raw;
+                }
+                _ => {
+                    raw.push(ch);
+                    self.bump();
+                    continue;
+                }
+            }
+
+            // A string or a closing bracket may have ended the value.
+            if depth == 0 {
+                return raw;
+            }
+        }
+    }
+
+    fn scan_raw_string(&mut self, raw: &mut Vec<u8>, quote: u8) {
+        raw.push(quote);
+        self.bump();
+
+        loop {
+            match self.ch {
+                None => { return; }
+                Some(b'\\') => {
+                    raw.push(b'\\');
+                    self.bump();
+
+                    match self.ch {
+                        Some(ch) => {
+                            raw.push(ch);
+                            self.bump();
+                        }
+                        None => { return; }
+                    }
+                }
+                Some(ch) => {
+                    raw.push(ch);
+                    self.bump();
+
+                    if ch == quote {
+                        return;
+                    }
+                }
             }
-            _ => self.error_event(KeyMustBeAString),
         }
     }
 
-    #[inline]
-    fn parse_object_value(&mut self) -> Result<de::Token, ParserError> {
-        self.parse_whitespace();
+    fn scan_raw_comment(&mut self, raw: &mut Vec<u8>) {
+        raw.push(b'/');
+        self.bump();
 
-        if self.ch_is(b':') {
+        if self.ch_is(b'/') {
+            while !self.eof() && !self.ch_is(b'\n') {
+                raw.push(self.ch_or_null());
+                self.bump();
+            }
+        } else if self.ch_is(b'*') {
+            raw.push(b'*');
             self.bump();
-            self.state_stack.push(ParseObjectCommaOrEnd);
-            self.parse_value()
-        } else if self.eof() {
-            self.error_event(EOFWhileParsingObject)
-        } else {
-            self.error_event(ExpectedColon)
+
+            while !self.eof() {
+                let ch = self.ch_or_null();
+                raw.push(ch);
+                self.bump();
+
+                if ch == b'*' && self.ch_is(b'/') {
+                    raw.push(b'/');
+                    self.bump();
+                    return;
+                }
+            }
         }
     }
 
-    #[inline]
-    fn parse_value(&mut self) -> Result<de::Token, ParserError> {
-        self.parse_whitespace();
+    // Checks the text of a value copied by `scan_raw_value` by parsing it on
+    // its own, and reports any error at its place in the whole input.
+    fn check_raw_value(&mut self, raw: Vec<u8>) -> Result<string::String, ParserError> {
+        let result = {
+            let mut parser = Parser::new(raw.iter().map(|c| *c))
+                .non_finite_floats(self.non_finite)
+                .relaxed(self.relaxed);
 
-        if self.eof() {
-            return self.error_event(EOFWhileParsingValue);
-        }
+            let value: Result<de::IgnoreTokens, ParserError> =
+                de::Deserializable::deserialize(&mut parser);
 
-        match self.ch_or_null() {
-            b'n' => self.parse_ident(b"ull", de::Null),
-            b't' => self.parse_ident(b"rue", de::Bool(true)),
-            b'f' => self.parse_ident(b"alse", de::Bool(false)),
-            b'0' ... b'9' | b'-' => self.parse_number(),
-            b'"' => {
-                Ok(de::String(try!(self.parse_string()).to_string()))
-            }
-            b'[' => {
-                self.bump();
-                self.state_stack.push(ParseListStart);
-                Ok(de::SeqStart(0))
-            }
-            b'{' => {
-                self.bump();
-                self.state_stack.push(ParseObjectStart);
-                Ok(de::MapStart(0))
+            match value {
+                Ok(_) => {
+                    match parser.next() {
+                        Some(Err(err)) => Err(err),
+                        _ => Ok(()),
+                    }
+                }
+                Err(err) => Err(err),
             }
-            _ => {
-                self.error_event(InvalidSyntax(SomeValue))
+        };
+
+        match result {
+            Ok(()) => { }
+            Err(SyntaxError(code, line, col, offset)) => {
+                let start = self.token_start;
+                let col = if line == 1 { start.col + col - 1 } else { col };
+
+                self.state_stack.clear();
+                return Err(SyntaxError(code,
+                                       start.line + line - 1,
+                                       col,
+                                       self.token_offset + offset));
             }
+            Err(err) => { return Err(err); }
         }
-    }
 
-    #[inline]
-    fn parse_ident(&mut self, ident: &[u8], token: de::Token) -> Result<de::Token, ParserError> {
-        if ident.iter().all(|c| Some(*c) == self.next_char()) {
-            self.bump();
-            Ok(token)
-        } else {
-            self.error_event(InvalidSyntax(SomeIdent))
+        match string::String::from_utf8(raw) {
+            Ok(raw) => Ok(raw),
+            Err(_) => self.error(NotUtf8),
         }
     }
-
-    #[inline]
-    fn error_event<T>(&mut self, reason: ErrorCode) -> Result<T, ParserError> {
-        self.state_stack.clear();
-        Err(SyntaxError(reason, self.line, self.col))
-    }
 }
 
 impl<Iter: Iterator<u8>> de::Deserializer<ParserError> for Parser<Iter> {
     fn end_of_stream_error(&mut self) -> ParserError {
-        SyntaxError(EOFWhileParsingValue, self.line, self.col)
+        SyntaxError(EOFWhileParsingValue, self.line, self.col, self.offset())
     }
 
     fn syntax_error(&mut self, token: de::Token, expected: &[de::TokenKind]) -> ParserError {
-        SyntaxError(DeserializerError(token, ExpectTokens(expected.to_vec())), self.line, self.col)
+        SyntaxError(DeserializerError(token, ExpectTokens(expected.to_vec())),
+                    self.line,
+                    self.col,
+                    self.offset())
     }
 
     fn unexpected_name_error(&mut self, token: de::Token) -> ParserError {
-        SyntaxError(DeserializerError(token, ExpectName), self.line, self.col)
+        SyntaxError(DeserializerError(token, ExpectName), self.line, self.col, self.offset())
     }
 
     fn conversion_error(&mut self, token: de::Token) -> ParserError {
-        SyntaxError(DeserializerError(token, ExpectConversion), self.line, self.col)
+        SyntaxError(DeserializerError(token, ExpectConversion),
+                    self.line,
+                    self.col,
+                    self.offset())
+    }
+
+    fn number_conversion_error(&mut self,
+                               token: de::Token,
+                               expected: de::TokenKind) -> ParserError {
+        SyntaxError(DeserializerError(token, ExpectExactNumber(expected)),
+                    self.line,
+                    self.col,
+                    self.offset())
+    }
+
+    #[inline]
+    fn token_start(&self) -> Option<de::Position> {
+        Some(self.token_start)
+    }
+
+    #[inline]
+    fn token_end(&self) -> Option<de::Position> {
+        Some(de::Position { line: self.line, col: self.col })
+    }
+
+    // Non-finite floats may be written as strings or `null`.
+    #[inline]
+    fn expect_num<T: de::FromNumber>(&mut self, token: de::Token) -> Result<T, ParserError> {
+        let value = match (self.non_finite, token) {
+            (NonFiniteAsString, de::String(value)) => {
+                match value.as_slice() {
+                    "NaN" => f64::NAN,
+                    "Infinity" => f64::INFINITY,
+                    "-Infinity" => f64::NEG_INFINITY,
+                    _ => { return de::default_expect_num(self, de::String(value)); }
+                }
+            }
+            (NonFiniteAsNull, de::Null) => f64::NAN,
+            (_, token) => { return de::default_expect_num(self, token); }
+        };
+
+        de::default_expect_num(self, de::F64(value))
     }
 
     #[inline]
@@ -2156,14 +3435,86 @@ impl<Iter: Iterator<u8>> de::Deserializer<ParserError> for Parser<Iter> {
 
     // Special case treating options as a nullable value.
     #[inline]
-    fn expect_option<
-        U: de::Deserializable<Parser<Iter>, ParserError>
-    >(&mut self, token: de::Token) -> Result<Option<U>, ParserError> {
+    fn expect_option_start(&mut self, token: de::Token) -> Result<de::OptionStart, ParserError> {
         match token {
-            de::Null => Ok(None),
-            token => {
-                let value: U = try!(de::Deserializable::deserialize_token(self, token));
-                Ok(Some(value))
+            de::Null => Ok(de::NoValue),
+            token => Ok(de::ValueToken(token)),
+        }
+    }
+
+    // Looks ahead for a `null`, so that any other value is read by `U` from
+    // its start, which a `RawJson` needs to keep its text.
+    #[inline]
+    fn expect_option_value<
+        U: de::Deserializable<Parser<Iter>, ParserError>
+    >(&mut self) -> Result<Option<U>, ParserError> {
+        match self.state_stack.last() {
+            Some(&ParseValue) | Some(&ParseObjectValue) => {
+                try!(self.parse_to_value());
+                try!(self.parse_whitespace());
+
+                if !self.ch_is(b'n') {
+                    let value: U = try!(de::Deserializable::deserialize(self));
+                    return Ok(Some(value));
+                }
+            }
+            _ => { }
+        }
+
+        let token = try!(self.expect_token());
+        self.expect_option(token)
+    }
+
+    // Reads up to the next element before deciding, so that the element is
+    // read by `T` from its start, as for `expect_option_value`.
+    #[inline]
+    fn expect_seq_elt_or_end<
+        T: de::Deserializable<Parser<Iter>, ParserError>
+    >(&mut self) -> Result<Option<T>, ParserError> {
+        match self.state_stack.last() {
+            Some(&ParseListStart) | Some(&ParseListCommaOrEnd) => {
+                if try!(self.parse_to_value()) {
+                    let value: T = try!(de::Deserializable::deserialize(self));
+                    Ok(Some(value))
+                } else {
+                    Ok(None)
+                }
+            }
+            _ => {
+                match try!(self.expect_token()) {
+                    de::End => Ok(None),
+                    token => {
+                        let value = try!(de::Deserializable::deserialize_token(self, token));
+                        Ok(Some(value))
+                    }
+                }
+            }
+        }
+    }
+
+    // Like `expect_seq_elt_or_end`, for deserializing over an element.
+    #[inline]
+    fn expect_seq_elt_into_or_end<
+        T: de::Deserializable<Parser<Iter>, ParserError>
+    >(&mut self, value: &mut T) -> Result<bool, ParserError> {
+        match self.state_stack.last() {
+            Some(&ParseListStart) | Some(&ParseListCommaOrEnd) => {
+                if try!(self.parse_to_value()) {
+                    use de::Deserializable;
+                    try!(value.deserialize_into(self));
+                    Ok(true)
+                } else {
+                    Ok(false)
+                }
+            }
+            _ => {
+                match try!(self.expect_token()) {
+                    de::End => Ok(false),
+                    token => {
+                        try!(de::Deserializable::deserialize_token_into(value, self, token));
+                        Ok(true)
+                    }
+                }
             }
         }
     }
@@ -2323,7 +3674,7 @@ impl ToJson for i64 {
 }
 
 impl ToJson for uint {
-    fn to_json(&self) -> Json { Integer(*self as i64) }
+    fn to_json(&self) -> Json { from_u64(*self as u64) }
 }
 
 impl ToJson for u8 {
@@ -2339,7 +3690,7 @@ impl ToJson for u32 {
 }
 
 impl ToJson for u64 {
-    fn to_json(&self) -> Json { Integer(*self as i64) }
+    fn to_json(&self) -> Json { from_u64(*self) }
 }
 
 impl ToJson for f32 {
@@ -2435,16 +3786,28 @@ impl<A:ToJson> ToJson for Option<A> {
 
 #[cfg(test)]
 mod tests {
+    use std::f64;
     use std::fmt::Show;
     use std::io;
     use std::str;
     use std::string;
     use std::collections::TreeMap;
 
-    use super::{Json, Null, Boolean, Floating, String, List, Object};
+    use super::{Json, Null, Boolean, Integer, U64, Floating, Number, String, List, Object};
     use super::{Parser, ParserError, from_str};
     use super::{JsonDeserializer, ToJson, from_json};
     use super::{
+        NonFiniteFloats,
+        NonFiniteError,
+        NonFiniteAsNull,
+        NonFiniteAsString,
+        NonFiniteAsLiteral,
+    };
+    use super::{
+        DeserializerError,
+        ErrorCode,
+        ExpectExactNumber,
+        ExpectTokens,
         EOFWhileParsingList,
         EOFWhileParsingObject,
         EOFWhileParsingString,
@@ -2587,28 +3950,126 @@ mod tests {
         test_pretty_encode_ok(tests);
     }
 
+    #[test]
+    fn test_write_u64() {
+        let tests = [
+            (3u64, "3"),
+            (18446744073709551615u64, "18446744073709551615"),
+        ];
+        test_encode_ok(tests);
+        test_pretty_encode_ok(tests);
+    }
+
+    #[test]
+    fn test_write_number() {
+        let value = List(vec![
+            Number("1.000000000000000000001".to_string()),
+            Number("-12345678901234567890123".to_string()),
+        ]);
+        assert_eq!(
+            super::to_string(&value).unwrap(),
+            "[1.000000000000000000001,-12345678901234567890123]".to_string());
+    }
+
     #[test]
     fn test_write_f64() {
         let tests = [
-            (3.0f64, "3"),
+            (3.0f64, "3.0"),
             (3.1, "3.1"),
             (-1.5, "-1.5"),
             (0.5, "0.5"),
+            (0.1, "0.1"),
+            (1e21, "1e+21"),
+            (1.7976931348623157e308, "1.7976931348623157e+308"),
         ];
         test_encode_ok(tests);
         test_pretty_encode_ok(tests);
     }
 
+    #[test]
+    fn test_write_non_finite_floats() {
+        fn write(v: f64, non_finite: NonFiniteFloats) -> Result<string::String, io::IoError> {
+            let mut serializer = super::Serializer::new(io::MemWriter::new())
+                .non_finite_floats(non_finite);
+            try!(v.serialize(&mut serializer));
+            Ok(string::String::from_utf8(serializer.unwrap().unwrap()).unwrap())
+        }
+
+        let value = vec![f64::NAN, f64::INFINITY, f64::NEG_INFINITY];
+
+        // Both writers refuse them unless told otherwise, as the parser does.
+        let mut serializer = super::Serializer::new(io::MemWriter::new());
+        assert_eq!(value.serialize(&mut serializer).unwrap_err().kind, io::InvalidInput);
+        let mut serializer = super::PrettySerializer::new(io::MemWriter::new());
+        assert_eq!(value.serialize(&mut serializer).unwrap_err().kind, io::InvalidInput);
+        assert!(Floating(f64::NAN).to_writer(io::MemWriter::new()).is_err());
+        assert_eq!(Floating(f64::NAN).to_string(), "NaN".to_string());
+
+        // The helpers that can't report an error write them as `null`.
+        assert_eq!(super::to_string(&f64::NAN).unwrap(), "null".to_string());
+        assert_eq!(super::to_string(&value).unwrap(), "[null,null,null]".to_string());
+        assert_eq!(super::to_pretty_string(&value).unwrap(),
+                   "[\n  null,\n  null,\n  null\n]".to_string());
+        assert_eq!(Floating(f64::INFINITY).to_pretty_string(), "null".to_string());
+
+        assert_eq!(write(f64::NAN, NonFiniteAsNull).unwrap(), "null".to_string());
+        assert_eq!(write(f64::NAN, NonFiniteAsString).unwrap(), "\"NaN\"".to_string());
+        assert_eq!(write(f64::INFINITY, NonFiniteAsString).unwrap(),
+                   "\"Infinity\"".to_string());
+        assert_eq!(write(f64::NEG_INFINITY, NonFiniteAsLiteral).unwrap(),
+                   "-Infinity".to_string());
+        assert_eq!(write(1.5, NonFiniteError).unwrap(), "1.5".to_string());
+        assert_eq!(write(f64::NAN, NonFiniteError).unwrap_err().kind, io::InvalidInput);
+
+        let mut serializer = super::PrettySerializer::new(io::MemWriter::new())
+            .non_finite_floats(NonFiniteAsLiteral);
+        value.serialize(&mut serializer).unwrap();
+        assert_eq!(
+            string::String::from_utf8(serializer.unwrap().unwrap()).unwrap(),
+            "[\n  NaN,\n  Infinity,\n  -Infinity\n]".to_string());
+    }
+
     #[test]
     fn test_write_str() {
         let tests = [
             ("", "\"\""),
             ("foo", "\"foo\""),
+            ("\x01\x1f", "\"\\u0001\\u001f\""),
+            ("caf\u00e9", "\"caf\u00e9\""),
         ];
         test_encode_ok(tests);
         test_pretty_encode_ok(tests);
     }
 
+    #[test]
+    fn test_write_str_escaping() {
+        fn write(v: &str, ascii_only: bool, html_safe: bool) -> string::String {
+            let mut serializer = super::Serializer::new(io::MemWriter::new())
+                .ascii_only(ascii_only)
+                .html_safe(html_safe);
+            v.serialize(&mut serializer).unwrap();
+            string::String::from_utf8(serializer.unwrap().unwrap()).unwrap()
+        }
+
+        let s = "<a href=\"x\">caf\u00e9 & \U0001d11e\u2028</a>";
+
+        assert_eq!(write(s, false, false), concat!(
+            "\"<a href=\\\"x\\\">caf\u00e9 & \U0001d11e\u2028</a>\"").to_string());
+        assert_eq!(write(s, true, false), concat!(
+            "\"<a href=\\\"x\\\">caf\\u00e9 & \\ud834\\udd1e\\u2028</a>\"").to_string());
+        assert_eq!(write(s, false, true), concat!(
+            "\"\\u003ca href=\\\"x\\\"\\u003ecaf\u00e9 \\u0026 ",
+            "\U0001d11e\\u2028\\u003c/a\\u003e\"").to_string());
+        assert_eq!(write(s, true, true), concat!(
+            "\"\\u003ca href=\\\"x\\\"\\u003ecaf\\u00e9 \\u0026 ",
+            "\\ud834\\udd1e\\u2028\\u003c/a\\u003e\"").to_string());
+
+        let mut serializer = super::PrettySerializer::new(io::MemWriter::new()).ascii_only(true);
+        vec!['\u00e9'].serialize(&mut serializer).unwrap();
+        assert_eq!(string::String::from_utf8(serializer.unwrap().unwrap()).unwrap(),
+                   "[\n  \"\\u00e9\"\n]".to_string());
+    }
+
     #[test]
     fn test_write_bool() {
         let tests = [
@@ -2754,6 +4215,102 @@ mod tests {
         ]);
     }
 
+    fn pretty_with<
+        T: ser::Serializable<super::PrettySerializer<io::MemWriter>, io::IoError>
+    >(value: &T,
+      f: |super::PrettySerializer<io::MemWriter>| -> super::PrettySerializer<io::MemWriter>
+     ) -> string::String {
+        let mut serializer = f(super::PrettySerializer::new(io::MemWriter::new()));
+        value.serialize(&mut serializer).unwrap();
+        string::String::from_utf8(serializer.unwrap().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_write_pretty_config() {
+        let mut object = TreeMap::new();
+        object.insert("a".to_string(), List(vec![Integer(1), Integer(2)]));
+        object.insert("b".to_string(), Object(TreeMap::new()));
+        let value = Object(object);
+
+        assert_eq!(
+            pretty_with(&value, |s| s.indent("\t").space_after_colon(false).trailing_newline(true)),
+            concat!(
+                "{\n",
+                "\t\"a\":[\n",
+                "\t\t1,\n",
+                "\t\t2\n",
+                "\t],\n",
+                "\t\"b\":{}\n",
+                "}\n"
+            ).to_string());
+
+        assert_eq!(pretty_with(&1i, |s| s.trailing_newline(true)), "1\n".to_string());
+        assert_eq!(pretty_with(&value, |s| s), super::to_pretty_string(&value).unwrap());
+    }
+
+    #[test]
+    fn test_write_pretty_sort_keys() {
+        use std::collections::HashMap;
+
+        let mut map = HashMap::new();
+        map.insert("b".to_string(), 1i);
+        map.insert("a b".to_string(), 2i);
+        map.insert("a".to_string(), 3i);
+
+        assert_eq!(
+            pretty_with(&map, |s| s.sort_keys(true)),
+            concat!(
+                "{\n",
+                "  \"a\": 3,\n",
+                "  \"a b\": 2,\n",
+                "  \"b\": 1\n",
+                "}"
+            ).to_string());
+
+        // Keys are sorted as they were before escaping, as in a `TreeMap`.
+        let mut map = HashMap::new();
+        map.insert("a#".to_string(), 1i);
+        map.insert("a\"b".to_string(), 2i);
+        map.insert("a!".to_string(), 3i);
+        map.insert("z".to_string(), 4i);
+        map.insert("\u00e9".to_string(), 5i);
+
+        let sorted: TreeMap<string::String, int> = map.clone().into_iter().collect();
+        assert_eq!(pretty_with(&map, |s| s.sort_keys(true).ascii_only(true)),
+                   pretty_with(&sorted, |s| s.ascii_only(true)));
+        assert_eq!(
+            pretty_with(&map, |s| s.sort_keys(true).ascii_only(true).max_width(100)),
+            "{\"a!\": 3, \"a\\\"b\": 2, \"a#\": 1, \"z\": 4, \"\\u00e9\": 5}".to_string());
+    }
+
+    #[test]
+    fn test_write_pretty_max_width() {
+        let mut object = TreeMap::new();
+        object.insert("key".to_string(), String("a long string value".to_string()));
+
+        let value = List(vec![
+            List(vec![Integer(1), Integer(2), Integer(3)]),
+            Object(object),
+            Integer(3),
+        ]);
+
+        assert_eq!(
+            pretty_with(&value, |s| s.max_width(20)),
+            concat!(
+                "[\n",
+                "  [1, 2, 3],\n",
+                "  {\n",
+                "    \"key\": \"a long string value\"\n",
+                "  },\n",
+                "  3\n",
+                "]"
+            ).to_string());
+
+        assert_eq!(
+            pretty_with(&value, |s| s.max_width(80).trailing_newline(true)),
+            "[[1, 2, 3], {\"key\": \"a long string value\"}, 3]\n".to_string());
+    }
+
     #[test]
     fn test_write_tuple() {
         test_encode_ok([
@@ -2928,9 +4485,9 @@ mod tests {
     #[test]
     fn test_parse_null() {
         test_parse_err::<()>([
-            ("n", SyntaxError(InvalidSyntax(SomeIdent), 1, 2)),
-            ("nul", SyntaxError(InvalidSyntax(SomeIdent), 1, 4)),
-            ("nulla", SyntaxError(TrailingCharacters, 1, 5)),
+            ("n", SyntaxError(InvalidSyntax(SomeIdent), 1, 2, 1)),
+            ("nul", SyntaxError(InvalidSyntax(SomeIdent), 1, 4, 3)),
+            ("nulla", SyntaxError(TrailingCharacters, 1, 5, 4)),
         ]);
 
         test_parse_ok([
@@ -2948,12 +4505,12 @@ mod tests {
     #[test]
     fn test_parse_bool() {
         test_parse_err::<bool>([
-            ("t", SyntaxError(InvalidSyntax(SomeIdent), 1, 2)),
-            ("truz", SyntaxError(InvalidSyntax(SomeIdent), 1, 4)),
-            ("f", SyntaxError(InvalidSyntax(SomeIdent), 1, 2)),
-            ("faz", SyntaxError(InvalidSyntax(SomeIdent), 1, 3)),
-            ("truea", SyntaxError(TrailingCharacters, 1, 5)),
-            ("falsea", SyntaxError(TrailingCharacters, 1, 6)),
+            ("t", SyntaxError(InvalidSyntax(SomeIdent), 1, 2, 1)),
+            ("truz", SyntaxError(InvalidSyntax(SomeIdent), 1, 4, 3)),
+            ("f", SyntaxError(InvalidSyntax(SomeIdent), 1, 2, 1)),
+            ("faz", SyntaxError(InvalidSyntax(SomeIdent), 1, 3, 2)),
+            ("truea", SyntaxError(TrailingCharacters, 1, 5, 4)),
+            ("falsea", SyntaxError(TrailingCharacters, 1, 6, 5)),
         ]);
 
         test_parse_ok([
@@ -2973,14 +4530,14 @@ mod tests {
     #[test]
     fn test_parse_number_errors() {
         test_parse_err::<f64>([
-            ("+", SyntaxError(InvalidSyntax(SomeValue), 1, 1)),
-            (".", SyntaxError(InvalidSyntax(SomeValue), 1, 1)),
-            ("-", SyntaxError(InvalidNumber, 1, 2)),
-            ("00", SyntaxError(InvalidNumber, 1, 2)),
-            ("1.", SyntaxError(InvalidNumber, 1, 3)),
-            ("1e", SyntaxError(InvalidNumber, 1, 3)),
-            ("1e+", SyntaxError(InvalidNumber, 1, 4)),
-            ("1a", SyntaxError(TrailingCharacters, 1, 2)),
+            ("+", SyntaxError(InvalidSyntax(SomeValue), 1, 1, 0)),
+            (".", SyntaxError(InvalidSyntax(SomeValue), 1, 1, 0)),
+            ("-", SyntaxError(InvalidNumber, 1, 2, 1)),
+            ("00", SyntaxError(InvalidNumber, 1, 2, 1)),
+            ("1.", SyntaxError(InvalidNumber, 1, 3, 2)),
+            ("1e", SyntaxError(InvalidNumber, 1, 3, 2)),
+            ("1e+", SyntaxError(InvalidNumber, 1, 4, 3)),
+            ("1a", SyntaxError(TrailingCharacters, 1, 2, 1)),
         ]);
     }
 
@@ -2990,7 +4547,89 @@ mod tests {
             ("3", 3i64),
             ("-2", -2),
             ("-1234", -1234),
+            ("-9223372036854775808", -9223372036854775808),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_u64() {
+        test_parse_ok([
+            ("3", 3u64),
+            ("9223372036854775808", 9223372036854775808),
+            ("18446744073709551615", 18446744073709551615),
         ]);
+
+        let v: Json = from_str("18446744073709551615").unwrap();
+        assert_eq!(v, U64(18446744073709551615));
+        assert_eq!(v.as_u64(), Some(18446744073709551615));
+
+        let v: Json = from_str("9223372036854775807").unwrap();
+        assert_eq!(v, Integer(9223372036854775807));
+    }
+
+    #[test]
+    fn test_parse_exact_numbers() {
+        fn code<T>(result: Result<T, ParserError>) -> ErrorCode {
+            match result {
+                Err(SyntaxError(code, _, _, _)) => code,
+                _ => panic!("expected a syntax error"),
+            }
+        }
+
+        assert_eq!(from_str::<int>("3.0"), Ok(3));
+        assert_eq!(from_str::<i64>("-2e3"), Ok(-2000));
+        assert_eq!(from_str::<f32>("0.1"), Ok(0.1));
+        assert_eq!(from_str::<f64>("300"), Ok(300.0));
+
+        assert_eq!(code(from_str::<int>("3.7")),
+                   DeserializerError(de::F64(3.7), ExpectExactNumber(de::IntKind)));
+        assert_eq!(code(from_str::<Vec<i64>>("[1999, 19.99]")),
+                   DeserializerError(de::F64(19.99), ExpectExactNumber(de::I64Kind)));
+        assert_eq!(code(from_str::<u8>("300")),
+                   DeserializerError(de::I64(300), ExpectExactNumber(de::U8Kind)));
+        assert_eq!(code(from_str::<uint>("-1")),
+                   DeserializerError(de::I64(-1), ExpectExactNumber(de::UintKind)));
+        assert_eq!(code(from_str::<i64>("1e300")),
+                   DeserializerError(de::F64(1e300), ExpectExactNumber(de::I64Kind)));
+        assert_eq!(code(from_json::<u8>(Integer(256))),
+                   DeserializerError(de::I64(256), ExpectExactNumber(de::U8Kind)));
+
+        let mut parser = Parser::new("NaN".bytes()).non_finite_floats(NonFiniteAsLiteral);
+        let value: Result<int, ParserError> = de::Deserializable::deserialize(&mut parser);
+        assert!(value.is_err());
+
+        assert_eq!(DeserializerError(de::F64(3.7), ExpectExactNumber(de::IntKind)).to_string(),
+                   "`F64(3.7)` cannot be converted exactly into an int".to_string());
+    }
+
+    #[test]
+    fn test_parse_raw_number() {
+        let tests = [
+            "0",
+            "-0",
+            "18446744073709551616",
+            "3.14159265358979323846264338327950288",
+            "-1.5E+300",
+            "2e-5",
+        ];
+
+        for s in tests.iter() {
+            let mut parser = Parser::new(s.bytes()).raw_numbers(true);
+            assert_eq!(parser.next(), Some(Ok(de::Number(s.to_string()))));
+            assert_eq!(parser.next(), None);
+
+            let mut parser = Parser::new(s.bytes()).raw_numbers(true);
+            let v: Json = de::Deserializable::deserialize(&mut parser).unwrap();
+            assert_eq!(v, Number(s.to_string()));
+            assert_eq!(super::to_string(&v).unwrap(), s.to_string());
+        }
+
+        let mut parser = Parser::new("[1.5, 20]".bytes()).raw_numbers(true);
+        let v: Vec<f64> = de::Deserializable::deserialize(&mut parser).unwrap();
+        assert_eq!(v, vec![1.5, 20.0]);
+
+        let mut parser = Parser::new("1.".bytes()).raw_numbers(true);
+        assert_eq!(parser.next(), Some(Err(SyntaxError(InvalidNumber, 1, 3, 2))));
     }
 
     #[test]
@@ -3003,9 +4642,45 @@ mod tests {
             ("0.4e5", 0.4e5),
             ("0.4e15", 0.4e15),
             ("0.4e-01", 0.4e-01),
+            ("0.30000000000000004", 0.30000000000000004),
+            ("1e23", 1e23),
+            ("2.2250738585072011e-308", 2.2250738585072011e-308),
+            ("4.9406564584124654e-324", 4.9406564584124654e-324),
+            ("18446744073709551616", 18446744073709551616.0),
+            ("-9223372036854775809", -9223372036854775809.0),
         ]);
     }
 
+    #[test]
+    fn test_parse_non_finite_floats() {
+        fn parse(s: &str, non_finite: NonFiniteFloats) -> Result<Vec<f64>, ParserError> {
+            let mut parser = Parser::new(s.bytes()).non_finite_floats(non_finite);
+            de::Deserializable::deserialize(&mut parser)
+        }
+
+        let v = parse("[NaN, Infinity, -Infinity, -1]", NonFiniteAsLiteral).unwrap();
+        assert!(v[0].is_nan());
+        assert_eq!(v.slice_from(1), [f64::INFINITY, f64::NEG_INFINITY, -1.0].as_slice());
+
+        let v = parse("[\"NaN\", \"Infinity\", \"-Infinity\"]", NonFiniteAsString).unwrap();
+        assert!(v[0].is_nan());
+        assert_eq!(v.slice_from(1), [f64::INFINITY, f64::NEG_INFINITY].as_slice());
+        assert!(parse("[\"1\"]", NonFiniteAsString).is_err());
+
+        let v = parse("[null, 2]", NonFiniteAsNull).unwrap();
+        assert!(v[0].is_nan());
+        assert_eq!(v[1], 2.0);
+
+        assert_eq!(parse("[NaN]", NonFiniteError),
+                   Err(SyntaxError(InvalidSyntax(SomeValue), 1, 2, 1)));
+        assert!(parse("[null]", NonFiniteError).is_err());
+
+        let mut parser = Parser::new("-Infinity".bytes())
+            .non_finite_floats(NonFiniteAsLiteral);
+        let v: Json = de::Deserializable::deserialize(&mut parser).unwrap();
+        assert_eq!(v, Floating(f64::NEG_INFINITY));
+    }
+
     #[test]
     fn test_json_deserialize_numbers() {
         test_json_deserialize_ok([
@@ -3022,9 +4697,9 @@ mod tests {
     #[test]
     fn test_parse_string() {
         test_parse_err::<string::String>([
-            ("\"", SyntaxError(EOFWhileParsingString, 1, 2)),
-            ("\"lol", SyntaxError(EOFWhileParsingString, 1, 5)),
-            ("\"lol\"a", SyntaxError(TrailingCharacters, 1, 6)),
+            ("\"", SyntaxError(EOFWhileParsingString, 1, 2, 1)),
+            ("\"lol", SyntaxError(EOFWhileParsingString, 1, 5, 4)),
+            ("\"lol\"a", SyntaxError(TrailingCharacters, 1, 6, 5)),
         ]);
 
         test_parse_ok([
@@ -3058,13 +4733,13 @@ mod tests {
     #[test]
     fn test_parse_list() {
         test_parse_err::<Vec<f64>>([
-            ("[", SyntaxError(EOFWhileParsingValue, 1, 2)),
-            ("[ ", SyntaxError(EOFWhileParsingValue, 1, 3)),
-            ("[1", SyntaxError(EOFWhileParsingList,  1, 3)),
-            ("[1,", SyntaxError(EOFWhileParsingValue, 1, 4)),
-            ("[1,]", SyntaxError(InvalidSyntax(SomeValue), 1, 4)),
-            ("[1 2]", SyntaxError(InvalidSyntax(ListCommaOrEnd), 1, 4)),
-            ("[]a", SyntaxError(TrailingCharacters, 1, 3)),
+            ("[", SyntaxError(EOFWhileParsingValue, 1, 2, 1)),
+            ("[ ", SyntaxError(EOFWhileParsingValue, 1, 3, 2)),
+            ("[1", SyntaxError(EOFWhileParsingList,  1, 3, 2)),
+            ("[1,", SyntaxError(EOFWhileParsingValue, 1, 4, 3)),
+            ("[1,]", SyntaxError(InvalidSyntax(SomeValue), 1, 4, 3)),
+            ("[1 2]", SyntaxError(InvalidSyntax(ListCommaOrEnd), 1, 4, 3)),
+            ("[]a", SyntaxError(TrailingCharacters, 1, 3, 2)),
         ]);
 
         test_parse_ok([
@@ -3115,21 +4790,222 @@ mod tests {
         ]);
     }
 
+    #[deriving(PartialEq, Show)]
+    #[deriving_deserializable]
+    struct SpannedConfig {
+        host: de::Spanned<string::String>,
+        ports: de::Spanned<Vec<uint>>,
+    }
+
+    #[test]
+    fn test_parse_spanned() {
+        fn span(line: uint, col: uint) -> Option<de::Position> {
+            Some(de::Position { line: line, col: col })
+        }
+
+        let config: SpannedConfig = from_str(
+            "{\"host\": \"a\", \"ports\": [1, 2]}").unwrap();
+
+        assert_eq!(config.host.value, "a".to_string());
+        assert_eq!((config.host.start, config.host.end), (span(1, 10), span(1, 13)));
+        assert_eq!(config.ports.value, vec![1, 2]);
+        assert_eq!((config.ports.start, config.ports.end), (span(1, 24), span(1, 30)));
+
+        let value: de::Spanned<Json> = from_str("  [1]").unwrap();
+        assert_eq!(value.value, List(vec![Integer(1)]));
+        assert_eq!((value.start, value.end), (span(1, 3), span(1, 6)));
+
+        // Spans are only known when parsing text.
+        let value: de::Spanned<int> = from_json(Integer(1)).unwrap();
+        assert_eq!((value.value, value.start, value.end), (1, None, None));
+    }
+
+    #[test]
+    fn test_error_render() {
+        let source = "{\n\t\"a\" 1\n}";
+        let result: Result<Json, ParserError> = from_str(source);
+        let err = result.unwrap_err();
+
+        assert_eq!(err, SyntaxError(ExpectedColon, 2, 6, 7));
+        assert_eq!(err.to_string(), "expected `:` at line 2, column 6".to_string());
+        assert_eq!(err.render(source),
+                   "expected `:` at line 2, column 6\n\t\"a\" 1\n\t    ^".to_string());
+
+        // Columns count characters, as the rendering does.
+        let source = "[\"\u00e9\",\n \"\u20ac\" 1]";
+        let result: Result<Json, ParserError> = from_str(source);
+        let err = result.unwrap_err();
+        assert_eq!(err, SyntaxError(InvalidSyntax(ListCommaOrEnd), 2, 6, 14));
+        assert!(err.render(source).starts_with(err.to_string().as_slice()));
+
+        let result: Result<Json, ParserError> = from_str("[1,");
+        let err = result.unwrap_err();
+        assert_eq!(err.render("[1,"),
+                   "unexpected end of input while parsing a value at line 1, column 4\n[1,\n   ^"
+                   .to_string());
+
+        let code = DeserializerError(de::U64(3), ExpectTokens(vec![de::StrKind, de::StringKind]));
+        assert_eq!(code.to_string(), "expected a str or a string, found `U64(3)`".to_string());
+
+        let code = DeserializerError(de::Null, ExpectTokens(vec![de::MapStartKind]));
+        assert_eq!(code.to_string(), "expected a map, found `Null`".to_string());
+
+        let err = super::MissingFieldError("port".to_string());
+        assert_eq!(err.render(source), "missing field `port`".to_string());
+    }
+
+    #[deriving(Clone, PartialEq, Show)]
+    #[deriving_deserializable]
+    struct Form {
+        age: u8,
+        admin: bool,
+        tags: Vec<string::String>,
+        note: Option<string::String>,
+    }
+
+    #[test]
+    fn test_parse_lenient() {
+        fn parse(s: &'static str) -> Result<Form, ParserError> {
+            let mut parser = de::LenientDeserializer::new(Parser::new(s.bytes()));
+            de::Deserializable::deserialize(&mut parser)
+        }
+
+        let expected = Form {
+            age: 42,
+            admin: true,
+            tags: vec!["a".to_string()],
+            note: None,
+        };
+
+        let s = "{\"age\": \"42\", \"admin\": \"on\", \"tags\": \"a\", \"note\": \"\"}";
+        assert_eq!(parse(s), Ok(expected.clone()));
+
+        // Strict input reads the same.
+        let s = "{\"age\": 42, \"admin\": true, \"tags\": [\"a\"], \"note\": null}";
+        assert_eq!(parse(s), Ok(expected.clone()));
+        assert_eq!(from_str::<Form>(s), Ok(expected));
+
+        let form = parse("{\"age\": 7, \"admin\": \"0\"}").unwrap();
+        assert_eq!(form, Form { age: 7, admin: false, tags: vec![], note: None });
+
+        assert!(parse("{\"age\": \"many\", \"admin\": true}").is_err());
+        assert!(from_str::<Form>("{\"age\": \"42\", \"admin\": true}").is_err());
+    }
+
+    #[deriving(PartialEq, Show)]
+    #[deriving_deserializable]
+    struct GameState {
+        tick: u64,
+        #[serial_name = "player-names"]
+        names: Vec<string::String>,
+        scores: TreeMap<string::String, int>,
+        note: Option<string::String>,
+    }
+
+    #[test]
+    fn test_deserialize_into() {
+        use de::Deserializable;
+
+        let mut state: GameState = from_str(concat!(
+            "{\"tick\": 1, \"player-names\": [\"ann\", \"bob\", \"cy\"], ",
+            "\"scores\": {\"ann\": 3}, \"note\": \"first\"}")).unwrap();
+        state.names.reserve(64);
+        let capacity = state.names.capacity();
+
+        let mut parser = Parser::new(concat!(
+            "{\"extra\": [1], \"scores\": {\"bob\": 4}, ",
+            "\"player-names\": [\"bob\", \"dee\"], \"tick\": 2}").bytes());
+        state.deserialize_into(&mut parser).unwrap();
+
+        let mut scores = TreeMap::new();
+        scores.insert("bob".to_string(), 4);
+        assert_eq!(state, GameState {
+            tick: 2,
+            names: vec!["bob".to_string(), "dee".to_string()],
+            scores: scores,
+            note: None,
+        });
+        assert_eq!(state.names.capacity(), capacity);
+
+        let mut parser = Parser::new("{\"tick\": \"soon\"}".bytes());
+        assert!(state.deserialize_into(&mut parser).is_err());
+    }
+
+    #[test]
+    fn test_parse_relaxed() {
+        fn parse<
+            T: de::Deserializable<Parser<str::Bytes<'static>>, ParserError>
+        >(s: &'static str) -> Result<T, ParserError> {
+            let mut parser = Parser::new(s.bytes()).relaxed(true);
+            let value = try!(de::Deserializable::deserialize(&mut parser));
+            match parser.next() {
+                Some(Err(err)) => Err(err),
+                _ => Ok(value),
+            }
+        }
+
+        let s = concat!(
+            "// The server configuration.\n",
+            "{\n",
+            "    /* Where to listen. */\n",
+            "    host: 'localhost',\n",
+            "    port: 0x1F90,\n",
+            "    $weight_2: +1.5,\n",
+            "    \"quoted\": ['it\\'s', \"\\\"\", -0x10, ],\n",
+            "    'nested': { a: [], },\n",
+            "}\n",
+            "// trailing comment"
+        );
+
+        let value: Json = parse(s).unwrap();
+        let expected: Json = from_str(concat!(
+            "{\"host\":\"localhost\",\"port\":8080,\"$weight_2\":1.5,",
+            "\"quoted\":[\"it's\",\"\\\"\",-16],\"nested\":{\"a\":[]}}"
+        )).unwrap();
+        assert_eq!(value, expected);
+
+        let value: Inner = parse("{ a: null, b: +2, c: ['x', ], }").unwrap();
+        assert_eq!(value, Inner { a: (), b: 2, c: vec!["x".to_string()] });
+
+        let mut parser = Parser::new("[0xff, +3]".bytes()).relaxed(true).raw_numbers(true);
+        let value: Vec<Json> = de::Deserializable::deserialize(&mut parser).unwrap();
+        assert_eq!(value, vec![Number("255".to_string()), Number("3".to_string())]);
+
+        assert_eq!(parse::<Vec<int>>("[0x]"), Err(SyntaxError(InvalidNumber, 1, 4, 3)));
+        assert_eq!(parse::<Vec<int>>("[0x1.5]"), Err(SyntaxError(InvalidNumber, 1, 5, 4)));
+        assert_eq!(parse::<Vec<int>>("[1,,]"), Err(SyntaxError(InvalidSyntax(SomeValue), 1, 4, 3)));
+
+        // Broken comments are reported where they start.
+        assert_eq!(parse::<int>("1 /* oops"), Err(SyntaxError(EOFWhileParsingValue, 1, 3, 2)));
+        assert_eq!(parse::<int>("1 /*/"), Err(SyntaxError(EOFWhileParsingValue, 1, 3, 2)));
+        assert_eq!(parse::<Vec<int>>("[1, /2]"),
+                   Err(SyntaxError(InvalidSyntax(SomeValue), 1, 5, 4)));
+        assert_eq!(parse::<int>("1 /"), Err(SyntaxError(InvalidSyntax(SomeValue), 1, 3, 2)));
+
+        // None of it is accepted by default.
+        assert!(from_str::<Vec<int>>("[1,]").is_err());
+        assert!(from_str::<Vec<int>>("[1] // comment").is_err());
+        assert!(from_str::<Vec<int>>("[+1]").is_err());
+        assert!(from_str::<Vec<int>>("[0x1]").is_err());
+        assert!(from_str::<Vec<string::String>>("['a']").is_err());
+        assert!(from_str::<TreeMap<string::String, int>>("{a: 1}").is_err());
+    }
+
     #[test]
     fn test_parse_object() {
         test_parse_err::<TreeMap<string::String, int>>([
-            ("{", SyntaxError(EOFWhileParsingString, 1, 2)),
-            ("{ ", SyntaxError(EOFWhileParsingString, 1, 3)),
-            ("{1", SyntaxError(KeyMustBeAString, 1, 2)),
-            ("{ \"a\"", SyntaxError(EOFWhileParsingObject, 1, 6)),
-            ("{\"a\"", SyntaxError(EOFWhileParsingObject, 1, 5)),
-            ("{\"a\" ", SyntaxError(EOFWhileParsingObject, 1, 6)),
-            ("{\"a\" 1", SyntaxError(ExpectedColon, 1, 6)),
-            ("{\"a\":", SyntaxError(EOFWhileParsingValue, 1, 6)),
-            ("{\"a\":1", SyntaxError(EOFWhileParsingObject, 1, 7)),
-            ("{\"a\":1 1", SyntaxError(InvalidSyntax(ObjectCommaOrEnd), 1, 8)),
-            ("{\"a\":1,", SyntaxError(EOFWhileParsingString, 1, 8)),
-            ("{}a", SyntaxError(TrailingCharacters, 1, 3)),
+            ("{", SyntaxError(EOFWhileParsingString, 1, 2, 1)),
+            ("{ ", SyntaxError(EOFWhileParsingString, 1, 3, 2)),
+            ("{1", SyntaxError(KeyMustBeAString, 1, 2, 1)),
+            ("{ \"a\"", SyntaxError(EOFWhileParsingObject, 1, 6, 5)),
+            ("{\"a\"", SyntaxError(EOFWhileParsingObject, 1, 5, 4)),
+            ("{\"a\" ", SyntaxError(EOFWhileParsingObject, 1, 6, 5)),
+            ("{\"a\" 1", SyntaxError(ExpectedColon, 1, 6, 5)),
+            ("{\"a\":", SyntaxError(EOFWhileParsingValue, 1, 6, 5)),
+            ("{\"a\":1", SyntaxError(EOFWhileParsingObject, 1, 7, 6)),
+            ("{\"a\":1 1", SyntaxError(InvalidSyntax(ObjectCommaOrEnd), 1, 8, 7)),
+            ("{\"a\":1,", SyntaxError(EOFWhileParsingString, 1, 8, 7)),
+            ("{}a", SyntaxError(TrailingCharacters, 1, 3, 2)),
         ]);
 
         test_parse_ok([
@@ -3287,7 +5163,7 @@ mod tests {
     #[test]
     fn test_multiline_errors() {
         test_parse_err::<TreeMap<string::String, string::String>>([
-            ("{\n  \"foo\":\n \"bar\"", SyntaxError(EOFWhileParsingObject, 3u, 8u)),
+            ("{\n  \"foo\":\n \"bar\"", SyntaxError(EOFWhileParsingObject, 3u, 7u, 17)),
         ]);
     }
 
@@ -3364,6 +5240,18 @@ mod tests {
         assert!(found_str.is_some() && found_str.unwrap().as_string().unwrap() == "cheese");
     }
 
+    #[test]
+    fn test_pointer() {
+        let json_value: Json = from_str("{\"a/b\": [1, {\"~\": true}], \"\": null}").unwrap();
+        assert_eq!(json_value.pointer(""), Some(&json_value));
+        assert_eq!(json_value.pointer("/a~1b/0"), Some(&Integer(1)));
+        assert_eq!(json_value.pointer("/a~1b/1/~0"), Some(&Boolean(true)));
+        assert_eq!(json_value.pointer("/"), Some(&Null));
+        assert_eq!(json_value.pointer("/a~1b/01"), None);
+        assert_eq!(json_value.pointer("/a~1b/2"), None);
+        assert_eq!(json_value.pointer("a"), None);
+    }
+
     #[test]
     fn test_search(){
         let json_value: Json = from_str("{\"dog\":{\"cat\": {\"mouse\" : \"cheese\"}}}").unwrap();
@@ -0,0 +1,287 @@
+// Copyright 2012-2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! JSON values kept as their source text.
+//!
+//! ```rust
+//! #![feature(phase)]
+//! #[phase(plugin)]
+//! extern crate serde_macros;
+//! extern crate serde;
+//!
+//! use serde::json;
+//! use serde::json::RawJson;
+//!
+//! #[deriving_serializable]
+//! #[deriving_deserializable]
+//! struct Envelope {
+//!     id: u64,
+//!     body: RawJson,
+//! }
+//!
+//! fn main() {
+//!     let input = "{\"id\":1,\"body\":{\"b\": 1.50, \"a\": [1e3]}}";
+//!     let envelope: Envelope = json::from_str(input).unwrap();
+//!     assert_eq!(envelope.body.as_str(), "{\"b\": 1.50, \"a\": [1e3]}");
+//!     assert_eq!(json::to_string(&envelope).unwrap().as_slice(), input);
+//! }
+//! ```
+
+use std::string;
+
+use de;
+use ser;
+use ser::Serializable;
+
+use super::{Json, JsonDeserializer, Parser, ParserError, decode};
+
+/// The text of a single JSON value, which is neither interpreted nor
+/// reformatted.
+///
+/// When deserialized from a `Parser`, a `RawJson` holds the exact text of the
+/// value, without the whitespace around it. The text is checked as it is
+/// read. Deserializing from a `Json` writes the value out again.
+///
+/// JSON serializers write the text out as it is, and other serializers
+/// serialize the value it holds. Text read by a relaxed `Parser` is kept
+/// as it is too, so it may not be strict JSON. JSON serializers set to
+/// `ascii_only` or `html_safe` write the value out again so that its strings
+/// are escaped, keeping its numbers exact.
+#[deriving(Clone, PartialEq, Show)]
+pub struct RawJson {
+    text: string::String,
+}
+
+impl RawJson {
+    /// Wraps the text of a JSON value, after checking that it is one.
+    /// Surrounding whitespace is dropped.
+    pub fn from_str(text: &str) -> Result<RawJson, ParserError> {
+        let mut parser = Parser::new(text.bytes());
+        let text = try!(parser.parse_raw_value());
+
+        // Make sure there are no trailing characters.
+        match parser.next() {
+            Some(Err(err)) => Err(err),
+            _ => Ok(RawJson { text: text }),
+        }
+    }
+
+    pub fn as_str<'a>(&'a self) -> &'a str {
+        self.text.as_slice()
+    }
+
+    pub fn into_string(self) -> string::String {
+        self.text
+    }
+
+    /// Parses the text into a `Json`.
+    pub fn to_json(&self) -> Result<Json, ParserError> {
+        let mut parser = Parser::new(self.text.as_slice().bytes()).relaxed(true);
+        de::Deserializable::deserialize(&mut parser)
+    }
+}
+
+impl<S: ser::Serializer<E>, E> ser::Serializable<S, E> for RawJson {
+    #[inline]
+    fn serialize(&self, s: &mut S) -> Result<(), E> {
+        match s.serialize_raw_json(self.text.as_slice()) {
+            Some(result) => result,
+            None => {
+                // Every way of making a `RawJson` checks its text.
+                let value = self.to_json().ok().expect("RawJson holds invalid JSON");
+                value.serialize(s)
+            }
+        }
+    }
+}
+
+impl<Iter: Iterator<u8>> de::Deserializable<Parser<Iter>, ParserError> for RawJson {
+    #[inline]
+    fn deserialize(d: &mut Parser<Iter>) -> Result<RawJson, ParserError> {
+        Ok(RawJson { text: try!(d.parse_raw_value()) })
+    }
+
+    #[inline]
+    fn deserialize_token(d: &mut Parser<Iter>, token: de::Token) -> Result<RawJson, ParserError> {
+        Ok(RawJson { text: try!(d.parse_raw_token(token)) })
+    }
+
+    #[inline]
+    fn deserialize_into(&mut self, d: &mut Parser<Iter>) -> Result<(), ParserError> {
+        self.text = try!(d.parse_raw_value());
+        Ok(())
+    }
+}
+
+impl de::Deserializable<JsonDeserializer, ParserError> for RawJson {
+    #[inline]
+    fn deserialize_token(d: &mut JsonDeserializer,
+                         token: de::Token) -> Result<RawJson, ParserError> {
+        let value: Json = try!(de::Deserializable::deserialize_token(d, token));
+        Ok(RawJson { text: value.to_string() })
+    }
+}
+
+impl<'a> de::Deserializable<decode::Deserializer<'a>, ParserError> for RawJson {
+    #[inline]
+    fn deserialize_token(d: &mut decode::Deserializer<'a>,
+                         token: de::Token) -> Result<RawJson, ParserError> {
+        let value: Json = try!(de::Deserializable::deserialize_token(d, token));
+        Ok(RawJson { text: value.to_string() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::TreeMap;
+    use std::io::MemWriter;
+    use std::string;
+
+    use json::{Json, Integer, ParserError, SyntaxError, TrailingCharacters, InvalidSyntax};
+    use json::SomeValue;
+    use de::Deserializable;
+    use json;
+    use ser::Serializable;
+    use super::RawJson;
+
+    #[deriving(PartialEq, Show)]
+    #[deriving_serializable]
+    #[deriving_deserializable]
+    struct Envelope {
+        kind: string::String,
+        body: RawJson,
+    }
+
+    #[test]
+    fn test_raw_json_from_str() {
+        assert_eq!(RawJson::from_str(" [1.0, \"\\u00e9\"] ").unwrap().as_str(),
+                   "[1.0, \"\\u00e9\"]");
+        assert_eq!(RawJson::from_str("1 2"),
+                   Err(SyntaxError(TrailingCharacters, 1, 3, 2)));
+        assert_eq!(RawJson::from_str("{\"a\": }"),
+                   Err(SyntaxError(InvalidSyntax(SomeValue), 1, 7, 6)));
+        assert_eq!(RawJson::from_str("1.50").unwrap().to_json(), Ok(json::Floating(1.5)));
+    }
+
+    #[test]
+    fn test_raw_json_envelope() {
+        let inputs = [
+            "{\"kind\":\"a\",\"body\":{ \"z\": 1.50, \"a\": [1e3, \"\\u00e9\"] }}",
+            "{\"kind\":\"b\",\"body\":\"caf\\u00e9\"}",
+            "{\"body\":-0.0,\"kind\":\"c\"}",
+            "{\"kind\":\"d\",\"body\":null}",
+        ];
+        let bodies = [
+            "{ \"z\": 1.50, \"a\": [1e3, \"\\u00e9\"] }",
+            "\"caf\\u00e9\"",
+            "-0.0",
+            "null",
+        ];
+
+        for (input, body) in inputs.iter().zip(bodies.iter()) {
+            let envelope: Envelope = json::from_str(*input).unwrap();
+            assert_eq!(envelope.body.as_str(), *body);
+        }
+
+        let envelope = Envelope {
+            kind: "a".to_string(),
+            body: RawJson::from_str("{ \"z\": 1.50 }").unwrap(),
+        };
+        assert_eq!(json::to_string(&envelope).unwrap(),
+                   "{\"kind\":\"a\",\"body\":{ \"z\": 1.50 }}".to_string());
+
+        // Escaping writers write the value out again, with the same numbers.
+        let envelope = Envelope {
+            kind: "a".to_string(),
+            body: RawJson::from_str("{\"z\": [1.50, \"</script>\u2028\"]}").unwrap(),
+        };
+        let mut serializer = json::Serializer::new(MemWriter::new()).html_safe(true);
+        envelope.serialize(&mut serializer).unwrap();
+        assert_eq!(string::String::from_utf8(serializer.unwrap().unwrap()).unwrap(),
+                   "{\"kind\":\"a\",\"body\":{\"z\":[1.50,\"\\u003c/script\\u003e\\u2028\"]}}"
+                   .to_string());
+        let mut serializer = json::PrettySerializer::new(MemWriter::new()).ascii_only(true);
+        RawJson::from_str("\"caf\u00e9\"").unwrap().serialize(&mut serializer).unwrap();
+        assert_eq!(string::String::from_utf8(serializer.unwrap().unwrap()).unwrap(),
+                   "\"caf\\u00e9\"".to_string());
+
+        // Serializers that don't write JSON get the value.
+        assert_eq!(json::to_json_value(&envelope.body).find(&"z".to_string()),
+                   Some(&json::Floating(1.5)));
+    }
+
+    #[test]
+    fn test_raw_json_errors() {
+        // Errors in the text are found where a `Json` would find them.
+        let inputs = [
+            "{\"kind\":\"a\",\"body\":[1, tru]}",
+            "{\"kind\":\"a\",\"body\":{\n  \"a\": [1,\n  2,, 3]}}",
+            "{\"kind\":\"a\",\"body\":[1, {\"a\": 2}",
+            "{\"kind\":\"a\",\"body\":\"abc}",
+        ];
+
+        for input in inputs.iter() {
+            let raw: Result<Envelope, ParserError> = json::from_str(*input);
+            let value: Result<Json, ParserError> = json::from_str(*input);
+            assert_eq!(raw.unwrap_err(), value.unwrap_err());
+        }
+    }
+
+    #[test]
+    fn test_raw_json_nested() {
+        let values: Vec<RawJson> = json::from_str("[ {\"a\" : 1} , [ ], 1.50 ]").unwrap();
+        let values: Vec<&str> = values.iter().map(|v| v.as_str()).collect();
+        assert_eq!(values, vec!["{\"a\" : 1}", "[ ]", "1.50"]);
+
+        let values: Vec<Option<RawJson>> = json::from_str("[null, -0.0 , \"\\u00e9\"]").unwrap();
+        let values: Vec<Option<&str>> = values.iter()
+            .map(|v| v.as_ref().map(|v| v.as_str()))
+            .collect();
+        assert_eq!(values, vec![None, Some("-0.0"), Some("\"\\u00e9\"")]);
+
+        let values: TreeMap<string::String, RawJson> =
+            json::from_str("{\"a\": 1.50, \"b\": {\"c\":[]}}").unwrap();
+        assert_eq!(values.find(&"a".to_string()).unwrap().as_str(), "1.50");
+        assert_eq!(values.find(&"b".to_string()).unwrap().as_str(), "{\"c\":[]}");
+
+        let value: Option<RawJson> = json::from_str("{ }").unwrap();
+        assert_eq!(value.unwrap().as_str(), "{ }");
+
+        let value: Option<RawJson> = json::from_str(" 1.50 ").unwrap();
+        assert_eq!(value.unwrap().as_str(), "1.50");
+
+        let value: TreeMap<string::String, Option<RawJson>> =
+            json::from_str("{\"a\": 1e3, \"b\": null}").unwrap();
+        assert_eq!(value.find(&"a".to_string()).unwrap().as_ref().unwrap().as_str(), "1e3");
+        assert_eq!(value.find(&"b".to_string()), Some(&None));
+
+        // Deserializing over existing elements keeps the text too.
+        let mut values: Vec<RawJson> = json::from_str("[1, 2]").unwrap();
+        let mut parser = json::Parser::new("[1.50, {\"a\": 1}, 2.0]".bytes());
+        values.deserialize_into(&mut parser).unwrap();
+        let values: Vec<&str> = values.iter().map(|v| v.as_str()).collect();
+        assert_eq!(values, vec!["1.50", "{\"a\": 1}", "2.0"]);
+
+        let mut values: Vec<Option<RawJson>> = json::from_str("[null, 1]").unwrap();
+        let mut parser = json::Parser::new("[1.50, null, -0.0]".bytes());
+        values.deserialize_into(&mut parser).unwrap();
+        let values: Vec<Option<&str>> = values.iter()
+            .map(|v| v.as_ref().map(|v| v.as_str()))
+            .collect();
+        assert_eq!(values, vec![Some("1.50"), None, Some("-0.0")]);
+
+        let value: RawJson = json::from_json(Integer(3)).unwrap();
+        assert_eq!(value.as_str(), "3");
+
+        let doc: Json = json::from_str("{\"a\": [1, 2]}").unwrap();
+        let value: RawJson = doc.get_as("/a").unwrap();
+        assert_eq!(value.as_str(), "[1,2]");
+    }
+}
@@ -0,0 +1,610 @@
+// Copyright 2012-2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A small regular expression engine for JSON Schema patterns. It supports
+//! the subset of ECMAScript regular expressions that schemas use in practice:
+//! literals, `.`, character classes with ranges, `\d`, `\w` and `\s` and
+//! their negations, `^` and `$`, groups, alternation, and the `*`, `+`, `?`
+//! and `{n,m}` quantifiers. Lookaround, backreferences and word boundaries
+//! are rejected when compiling.
+//!
+//! Patterns are compiled to an automaton that is run over the text once,
+//! tracking every state it can be in rather than backtracking. Matching takes
+//! time proportional to the length of the text times the size of the
+//! pattern, so a hostile string can't make it slow.
+
+use std::mem;
+use std::string;
+
+/// A compiled regular expression.
+pub struct Regex {
+    prog: Vec<Inst>,
+}
+
+enum Node {
+    Literal(char),
+    AnyChar,
+    /// Ranges of characters, and whether the class is negated.
+    Class(Vec<(char, char)>, bool),
+    StartAnchor,
+    EndAnchor,
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    /// A node repeated between a minimum and an optional maximum of times.
+    Repeat(Box<Node>, uint, Option<uint>),
+}
+
+/// An instruction of the automaton a pattern is compiled to.
+enum Inst {
+    MatchChar(char),
+    MatchAny,
+    MatchClass(Vec<(char, char)>, bool),
+    AssertStart,
+    AssertEnd,
+    /// Carries on at both instructions.
+    Split(uint, uint),
+    Jump(uint),
+    Accept,
+}
+
+/// Patterns that compile to more instructions than this are rejected, which
+/// keeps counted repetitions such as `(a{1000}){1000}` in check.
+static MAX_INSTS: uint = 10000;
+
+/// What a backslash escape stands for.
+enum Escape {
+    EscapedChar(char),
+    EscapedClass(Vec<(char, char)>, bool),
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, string::String> {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut parser = RegexParser {
+            chars: chars.as_slice(),
+            pos: 0,
+        };
+
+        let node = try!(parser.parse_alternate());
+
+        // Only an unmatched `)` stops the outermost alternation early.
+        if parser.pos < parser.chars.len() {
+            return Err("unmatched `)`".to_string());
+        }
+
+        let mut compiler = Compiler { prog: Vec::new() };
+        try!(compiler.compile(&node));
+        try!(compiler.push(Accept));
+
+        Ok(Regex { prog: compiler.prog })
+    }
+
+    /// Returns whether the expression matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        let text = chars.as_slice();
+
+        let mut current = Threads::new(self.prog.len());
+        let mut next = Threads::new(self.prog.len());
+        let mut stack = Vec::new();
+
+        for pos in range(0, text.len() + 1) {
+            // A match may start anywhere.
+            if self.add(&mut current, &mut stack, 0, text, pos) {
+                return true;
+            }
+
+            if pos == text.len() {
+                break;
+            }
+
+            let ch = text[pos];
+            for &pc in current.pcs.iter() {
+                let matched = match self.prog.as_slice()[pc] {
+                    MatchChar(expected) => ch == expected,
+                    MatchAny => !is_line_terminator(ch),
+                    MatchClass(ref ranges, negated) => in_ranges(ranges.as_slice(), ch) != negated,
+                    _ => false,
+                };
+
+                if matched && self.add(&mut next, &mut stack, pc + 1, text, pos + 1) {
+                    return true;
+                }
+            }
+
+            mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+
+        false
+    }
+
+    /// Adds the instruction at `pc` to `threads`, along with everything it
+    /// leads to without reading a character from `pos`. Returns whether that
+    /// reaches the end of the pattern.
+    fn add(&self,
+           threads: &mut Threads,
+           stack: &mut Vec<uint>,
+           pc: uint,
+           text: &[char],
+           pos: uint) -> bool {
+        stack.push(pc);
+
+        loop {
+            let pc = match stack.pop() {
+                Some(pc) => pc,
+                None => { return false; }
+            };
+
+            if threads.seen.as_slice()[pc] {
+                continue;
+            }
+            *threads.seen.get_mut(pc) = true;
+            threads.pcs.push(pc);
+
+            match self.prog.as_slice()[pc] {
+                Split(first, second) => {
+                    stack.push(second);
+                    stack.push(first);
+                }
+                Jump(target) => { stack.push(target); }
+                AssertStart if pos == 0 => { stack.push(pc + 1); }
+                AssertEnd if pos == text.len() => { stack.push(pc + 1); }
+                Accept => {
+                    stack.clear();
+                    return true;
+                }
+                _ => { }
+            }
+        }
+    }
+}
+
+/// The instructions reached at one position of the text, as a set and in
+/// the order they were added.
+struct Threads {
+    seen: Vec<bool>,
+    pcs: Vec<uint>,
+}
+
+impl Threads {
+    fn new(len: uint) -> Threads {
+        Threads {
+            seen: Vec::from_elem(len, false),
+            pcs: Vec::with_capacity(len),
+        }
+    }
+
+    fn clear(&mut self) {
+        for &pc in self.pcs.iter() {
+            *self.seen.get_mut(pc) = false;
+        }
+        self.pcs.clear();
+    }
+}
+
+struct Compiler {
+    prog: Vec<Inst>,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> Result<uint, string::String> {
+        if self.prog.len() >= MAX_INSTS {
+            return Err("pattern is too large".to_string());
+        }
+
+        self.prog.push(inst);
+        Ok(self.prog.len() - 1)
+    }
+
+    fn compile(&mut self, node: &Node) -> Result<(), string::String> {
+        match *node {
+            Literal(ch) => { try!(self.push(MatchChar(ch))); }
+            AnyChar => { try!(self.push(MatchAny)); }
+            Class(ref ranges, negated) => { try!(self.push(MatchClass(ranges.clone(), negated))); }
+            StartAnchor => { try!(self.push(AssertStart)); }
+            EndAnchor => { try!(self.push(AssertEnd)); }
+            Concat(ref nodes) => {
+                for node in nodes.iter() {
+                    try!(self.compile(node));
+                }
+            }
+            Alternate(ref nodes) => {
+                // Every branch but the last is entered through a split, and
+                // jumps past the others once it is done.
+                let mut jumps = Vec::new();
+
+                for (i, node) in nodes.iter().enumerate() {
+                    if i + 1 == nodes.len() {
+                        try!(self.compile(node));
+                    } else {
+                        let split = try!(self.push(Split(0, 0)));
+                        try!(self.compile(node));
+                        jumps.push(try!(self.push(Jump(0))));
+                        *self.prog.get_mut(split) = Split(split + 1, self.prog.len());
+                    }
+                }
+
+                let end = self.prog.len();
+                for &jump in jumps.iter() {
+                    *self.prog.get_mut(jump) = Jump(end);
+                }
+            }
+            Repeat(ref node, min, max) => {
+                for _ in range(0, min) {
+                    try!(self.compile(&**node));
+                }
+
+                match max {
+                    None => {
+                        let split = try!(self.push(Split(0, 0)));
+                        try!(self.compile(&**node));
+                        try!(self.push(Jump(split)));
+                        *self.prog.get_mut(split) = Split(split + 1, self.prog.len());
+                    }
+                    Some(max) => {
+                        // Each optional copy may skip to the end.
+                        let mut splits = Vec::new();
+                        for _ in range(min, max) {
+                            splits.push(try!(self.push(Split(0, 0))));
+                            try!(self.compile(&**node));
+                        }
+
+                        let end = self.prog.len();
+                        for &split in splits.iter() {
+                            *self.prog.get_mut(split) = Split(split + 1, end);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct RegexParser<'a> {
+    chars: &'a [char],
+    pos: uint,
+}
+
+impl<'a> RegexParser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).map(|ch| *ch)
+    }
+
+    fn parse_alternate(&mut self) -> Result<Node, string::String> {
+        let mut branches = vec![try!(self.parse_concat())];
+
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            branches.push(try!(self.parse_concat()));
+        }
+
+        if branches.len() == 1 {
+            Ok(branches.pop().unwrap())
+        } else {
+            Ok(Alternate(branches))
+        }
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, string::String> {
+        let mut nodes = Vec::new();
+
+        loop {
+            match self.peek() {
+                None | Some('|') | Some(')') => { break; }
+                Some(_) => {
+                    let atom = try!(self.parse_atom());
+                    nodes.push(self.parse_quantifiers(atom));
+                }
+            }
+        }
+
+        Ok(Concat(nodes))
+    }
+
+    fn parse_quantifiers(&mut self, atom: Node) -> Node {
+        let mut node = atom;
+
+        loop {
+            let (min, max) = match self.peek() {
+                Some('*') => { self.pos += 1; (0, None) }
+                Some('+') => { self.pos += 1; (1, None) }
+                Some('?') => { self.pos += 1; (0, Some(1)) }
+                Some('{') => {
+                    match self.parse_bounds() {
+                        Some(bounds) => bounds,
+                        // Not a quantifier, so the `{` is a literal.
+                        None => { return node; }
+                    }
+                }
+                _ => { return node; }
+            };
+
+            // A `?` after a quantifier makes it lazy, which doesn't change
+            // whether there is a match.
+            if self.peek() == Some('?') {
+                self.pos += 1;
+            }
+
+            node = Repeat(box node, min, max);
+        }
+    }
+
+    /// Parses `{n}`, `{n,}` or `{n,m}`, leaving the position untouched if
+    /// there isn't one.
+    fn parse_bounds(&mut self) -> Option<(uint, Option<uint>)> {
+        let start = self.pos;
+        self.pos += 1;
+
+        let bounds = match self.parse_count() {
+            None => None,
+            Some(min) => {
+                match self.peek() {
+                    Some('}') => {
+                        self.pos += 1;
+                        Some((min, Some(min)))
+                    }
+                    Some(',') => {
+                        self.pos += 1;
+                        let max = self.parse_count();
+                        if self.peek() == Some('}') {
+                            self.pos += 1;
+                            Some((min, max))
+                        } else {
+                            None
+                        }
+                    }
+                    _ => None,
+                }
+            }
+        };
+
+        if bounds.is_none() {
+            self.pos = start;
+        }
+
+        bounds
+    }
+
+    fn parse_count(&mut self) -> Option<uint> {
+        let mut count = None;
+
+        loop {
+            match self.peek().and_then(|ch| ch.to_digit(10)) {
+                Some(digit) => {
+                    count = Some(count.unwrap_or(0u) * 10 + digit);
+                    self.pos += 1;
+                }
+                None => { return count; }
+            }
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, string::String> {
+        let ch = self.peek().unwrap();
+        self.pos += 1;
+
+        match ch {
+            '(' => {
+                if self.peek() == Some('?') {
+                    if self.chars.get(self.pos + 1) == Some(&':') {
+                        self.pos += 2;
+                    } else {
+                        return Err("lookaround is not supported".to_string());
+                    }
+                }
+
+                let node = try!(self.parse_alternate());
+
+                if self.peek() != Some(')') {
+                    return Err("unclosed group".to_string());
+                }
+                self.pos += 1;
+
+                Ok(node)
+            }
+            '[' => self.parse_class(),
+            '.' => Ok(AnyChar),
+            '^' => Ok(StartAnchor),
+            '$' => Ok(EndAnchor),
+            '\\' => {
+                match try!(self.parse_escape()) {
+                    EscapedChar(ch) => Ok(Literal(ch)),
+                    EscapedClass(ranges, negated) => Ok(Class(ranges, negated)),
+                }
+            }
+            '*' | '+' | '?' => Err(format!("nothing to repeat before `{}`", ch)),
+            ch => Ok(Literal(ch)),
+        }
+    }
+
+    /// Parses the rest of a character class, after the `[`.
+    fn parse_class(&mut self) -> Result<Node, string::String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+
+        let mut ranges = Vec::new();
+
+        loop {
+            let lo = match try!(self.parse_class_atom()) {
+                None => { break; }
+                Some(EscapedClass(class, false)) => {
+                    ranges.push_all(class.as_slice());
+                    continue;
+                }
+                Some(EscapedClass(_, true)) => {
+                    return Err("negated escapes are not supported in classes".to_string());
+                }
+                Some(EscapedChar(ch)) => ch,
+            };
+
+            let is_range = self.peek() == Some('-') &&
+                           self.chars.get(self.pos + 1).map_or(false, |ch| *ch != ']');
+
+            if is_range {
+                self.pos += 1;
+
+                match try!(self.parse_class_atom()) {
+                    Some(EscapedChar(hi)) if lo <= hi => { ranges.push((lo, hi)); }
+                    _ => { return Err("invalid range in character class".to_string()); }
+                }
+            } else {
+                ranges.push((lo, lo));
+            }
+        }
+
+        Ok(Class(ranges, negated))
+    }
+
+    /// Parses a character or an escape in a class, or returns `None` at the
+    /// closing `]`.
+    fn parse_class_atom(&mut self) -> Result<Option<Escape>, string::String> {
+        match self.peek() {
+            None => Err("unclosed character class".to_string()),
+            Some(']') => {
+                self.pos += 1;
+                Ok(None)
+            }
+            Some('\\') => {
+                self.pos += 1;
+                self.parse_escape().map(|escape| Some(escape))
+            }
+            Some(ch) => {
+                self.pos += 1;
+                Ok(Some(EscapedChar(ch)))
+            }
+        }
+    }
+
+    /// Parses an escape, after the backslash.
+    fn parse_escape(&mut self) -> Result<Escape, string::String> {
+        let ch = match self.peek() {
+            Some(ch) => ch,
+            None => { return Err("trailing backslash".to_string()); }
+        };
+        self.pos += 1;
+
+        let escape = match ch {
+            'd' => EscapedClass(DIGIT.to_vec(), false),
+            'D' => EscapedClass(DIGIT.to_vec(), true),
+            'w' => EscapedClass(WORD.to_vec(), false),
+            'W' => EscapedClass(WORD.to_vec(), true),
+            's' => EscapedClass(SPACE.to_vec(), false),
+            'S' => EscapedClass(SPACE.to_vec(), true),
+            'n' => EscapedChar('\n'),
+            'r' => EscapedChar('\r'),
+            't' => EscapedChar('\t'),
+            'f' => EscapedChar('\x0c'),
+            'v' => EscapedChar('\x0b'),
+            '0' => EscapedChar('\0'),
+            'u' => {
+                let mut n = 0u32;
+                for _ in range(0u, 4) {
+                    match self.peek().and_then(|ch| ch.to_digit(16)) {
+                        Some(digit) => { n = n * 16 + digit as u32; }
+                        None => { return Err("invalid `\\u` escape".to_string()); }
+                    }
+                    self.pos += 1;
+                }
+
+                match ::std::char::from_u32(n) {
+                    Some(ch) => EscapedChar(ch),
+                    None => { return Err("invalid `\\u` escape".to_string()); }
+                }
+            }
+            'b' | 'B' => { return Err("word boundaries are not supported".to_string()); }
+            '1' ... '9' => { return Err("backreferences are not supported".to_string()); }
+            ch => EscapedChar(ch),
+        };
+
+        Ok(escape)
+    }
+}
+
+static DIGIT: &'static [(char, char)] = &[('0', '9')];
+
+static WORD: &'static [(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+
+static SPACE: &'static [(char, char)] = &[
+    ('\t', '\r'),
+    (' ', ' '),
+    ('\u00a0', '\u00a0'),
+    ('\u2028', '\u2029'),
+    ('\ufeff', '\ufeff'),
+];
+
+fn in_ranges(ranges: &[(char, char)], ch: char) -> bool {
+    ranges.iter().any(|&(lo, hi)| lo <= ch && ch <= hi)
+}
+
+fn is_line_terminator(ch: char) -> bool {
+    match ch {
+        '\n' | '\r' | '\u2028' | '\u2029' => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Regex;
+
+    fn is_match(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn test_regex_match() {
+        assert!(is_match("abc", "xxabcxx"));
+        assert!(!is_match("^abc$", "xxabcxx"));
+        assert!(is_match("^[a-f0-9]{8}$", "deadbeef"));
+        assert!(!is_match("^[a-f0-9]{8}$", "deadbeefs"));
+        assert!(is_match("^(\\+|-)?\\d+(\\.\\d{1,2})?$", "-12.50"));
+        assert!(!is_match("^(\\+|-)?\\d+(\\.\\d{1,2})?$", "12.505"));
+        assert!(is_match("^(?:ab)*c$", "ababc"));
+        assert!(!is_match("^(?:ab)*c$", "abac"));
+        assert!(is_match("^[^\\s@]+@[^\\s@]+$", "user@example.com"));
+        assert!(!is_match("^[^\\s@]+@[^\\s@]+$", "user @example.com"));
+        assert!(is_match("^a{2,}$", "aaaa"));
+        assert!(is_match("^x{y$", "x{y"));
+        assert!(is_match("^(a*)*b$", "aaab"));
+        assert!(is_match("^.\\u00e9$", "c\u00e9"));
+        assert!(is_match("^(a|b|)+$", "abba"));
+        assert!(is_match("^a{0,2}$", ""));
+        assert!(!is_match("^a{0,2}$", "aaa"));
+        assert!(is_match("x|^$", ""));
+    }
+
+    #[test]
+    fn test_regex_long_input() {
+        // These take quadratic time or worse if matching restarts at every
+        // offset, or backtracks.
+        let text = String::from_char(100000, 'a');
+        assert!(!is_match("a*b", text.as_slice()));
+        assert!(!is_match("^(a*)*b$", text.as_slice()));
+        assert!(is_match("^[a-z]*$", text.as_slice()));
+        assert!(is_match("a{3}$", text.as_slice()));
+    }
+
+    #[test]
+    fn test_regex_errors() {
+        assert!(Regex::new("a(").is_err());
+        assert!(Regex::new("a)").is_err());
+        assert!(Regex::new("[a-").is_err());
+        assert!(Regex::new("[z-a]").is_err());
+        assert!(Regex::new("*a").is_err());
+        assert!(Regex::new("(?=a)").is_err());
+        assert!(Regex::new("(a)\\1").is_err());
+        assert!(Regex::new("(a{1000}){1000}").is_err());
+    }
+}
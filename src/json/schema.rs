@@ -0,0 +1,1712 @@
+// Copyright 2012-2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Validation of `Json` values against a JSON Schema.
+//!
+//! The keywords of draft 7 and 2020-12 that constrain values are supported:
+//! `type`, `enum`, `const`, the numeric ranges and `multipleOf`,
+//! `minLength`, `maxLength`, `pattern`, `items` (including the draft 7 array
+//! form with `additionalItems`), `prefixItems`, `minItems`, `maxItems`,
+//! `uniqueItems`, `properties`, `required`, `additionalProperties`,
+//! `minProperties`, `maxProperties`, `allOf`, `anyOf`, `oneOf` and `not`.
+//! Other keywords are ignored.
+//!
+//! `$ref` may point anywhere within the schema document, as a fragment such
+//! as `#/definitions/address` or `#/$defs/address`. Keywords next to a
+//! `$ref` apply as well, as in 2020-12. Patterns use the small regular
+//! expression subset described in the `regex` module.
+//!
+//! Schemas can also be generated from Rust types that implement
+//! `ToJsonSchema`, which `#[deriving_json_schema]` does for structs and enums.
+//!
+//! ```rust
+//! use serde::json;
+//! use serde::json::schema::Schema;
+//!
+//! let schema = Schema::compile(&json::from_str(
+//!     "{\"type\": \"object\", \"required\": [\"id\"]}").unwrap()).unwrap();
+//!
+//! let errors = schema.validate(&json::from_str("{}").unwrap()).unwrap_err();
+//! let error = &errors.as_slice()[0];
+//! assert_eq!(error.path, "".to_string());
+//! assert_eq!(error.keyword, "required");
+//! ```
+
+use std::collections::{HashMap, TreeMap};
+use std::fmt;
+use std::{i8, i16, i32, i64, int};
+use std::{u8, u16, u32, u64, uint};
+use std::string;
+
+use super::{
+    Json,
+    JsonObject,
+    Null,
+    Boolean,
+    Integer,
+    U64,
+    Floating,
+    Number,
+    String,
+    List,
+    Object,
+    escape_pointer_token,
+};
+use super::dtoa;
+use super::regex::Regex;
+
+/// A compiled schema.
+pub struct Schema {
+    nodes: Vec<SchemaNode>,
+}
+
+/// A way a value failed to match a schema.
+#[deriving(Clone, PartialEq, Show)]
+pub struct ValidationError {
+    /// A JSON Pointer to the value that failed.
+    pub path: string::String,
+    /// The keyword that failed, such as `minimum`.
+    pub keyword: &'static str,
+    pub message: string::String,
+}
+
+/// The errors that can arise while compiling a schema.
+#[deriving(Clone, PartialEq, Show)]
+pub enum SchemaError {
+    /// The schema or keyword at a JSON Pointer into the schema is malformed.
+    InvalidSchema(string::String, string::String),
+    /// A `$ref` that doesn't point within the schema document.
+    UnresolvedRef(string::String),
+}
+
+enum SchemaNode {
+    /// `true` accepts every value, `false` none.
+    BoolSchema(bool),
+    ObjectSchema(Keywords),
+}
+
+#[deriving(Clone, PartialEq, Show)]
+enum JsonType {
+    NullType,
+    BooleanType,
+    IntegerType,
+    NumberType,
+    StringType,
+    ArrayType,
+    ObjectType,
+}
+
+/// The keywords of a schema object. Subschemas are indices into
+/// `Schema::nodes`.
+struct Keywords {
+    reference: Option<uint>,
+    types: Option<Vec<JsonType>>,
+    enumeration: Option<Vec<Json>>,
+    constant: Option<Json>,
+    minimum: Option<Num>,
+    maximum: Option<Num>,
+    exclusive_minimum: Option<Num>,
+    exclusive_maximum: Option<Num>,
+    multiple_of: Option<Num>,
+    min_length: Option<uint>,
+    max_length: Option<uint>,
+    pattern: Option<(string::String, Regex)>,
+    prefix_items: Vec<uint>,
+    items: Option<uint>,
+    min_items: Option<uint>,
+    max_items: Option<uint>,
+    unique_items: bool,
+    properties: Vec<(string::String, uint)>,
+    required: Vec<string::String>,
+    additional_properties: Option<uint>,
+    min_properties: Option<uint>,
+    max_properties: Option<uint>,
+    all_of: Vec<uint>,
+    any_of: Vec<uint>,
+    one_of: Vec<uint>,
+    not: Option<uint>,
+}
+
+impl Schema {
+    /// Compiles a schema document.
+    pub fn compile(schema: &Json) -> Result<Schema, SchemaError> {
+        let mut compiler = Compiler {
+            document: schema,
+            nodes: Vec::new(),
+            compiled: HashMap::new(),
+        };
+
+        try!(compiler.compile(string::String::new(), schema));
+        try!(compiler.check_cycles());
+
+        Ok(Schema {
+            nodes: compiler.nodes.into_iter().map(|node| node.unwrap()).collect(),
+        })
+    }
+
+    /// Validates a value, returning every way in which it doesn't match.
+    pub fn validate(&self, value: &Json) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        self.validate_node(0, value, "", &mut errors);
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn is_valid(&self, value: &Json) -> bool {
+        self.is_valid_node(0, value)
+    }
+
+    fn is_valid_node(&self, index: uint, value: &Json) -> bool {
+        let mut errors = Vec::new();
+        self.validate_node(index, value, "", &mut errors);
+        errors.is_empty()
+    }
+
+    fn validate_node(&self,
+                     index: uint,
+                     value: &Json,
+                     path: &str,
+                     errors: &mut Vec<ValidationError>) {
+        let keywords = match self.nodes.as_slice()[index] {
+            BoolSchema(true) => { return; }
+            BoolSchema(false) => {
+                errors.push(error(path, "false", "no value is allowed here".to_string()));
+                return;
+            }
+            ObjectSchema(ref keywords) => keywords,
+        };
+
+        match keywords.reference {
+            Some(target) => self.validate_node(target, value, path, errors),
+            None => { }
+        }
+
+        match keywords.types {
+            Some(ref types) if !types.iter().any(|ty| ty.matches(value)) => {
+                let names: Vec<&str> = types.iter().map(|ty| ty.name()).collect();
+                errors.push(error(path, "type", format!("expected {}, found {}",
+                                                        names.connect(" or "),
+                                                        type_of(value).name())));
+            }
+            _ => { }
+        }
+
+        match keywords.enumeration {
+            Some(ref values) if !values.iter().any(|v| json_equal(v, value)) => {
+                errors.push(error(path, "enum",
+                                  format!("{} is not one of the allowed values", value)));
+            }
+            _ => { }
+        }
+
+        match keywords.constant {
+            Some(ref constant) if !json_equal(constant, value) => {
+                errors.push(error(path, "const",
+                                  format!("expected {}, found {}", constant, value)));
+            }
+            _ => { }
+        }
+
+        match Num::from_json(value) {
+            Some(n) => self.validate_number(keywords, n, value, path, errors),
+            None => { }
+        }
+
+        match *value {
+            String(ref s) => self.validate_string(keywords, s.as_slice(), path, errors),
+            List(ref list) => self.validate_list(keywords, list.as_slice(), path, errors),
+            Object(ref map) => self.validate_object(keywords, map, path, errors),
+            _ => { }
+        }
+
+        for &schema in keywords.all_of.iter() {
+            self.validate_node(schema, value, path, errors);
+        }
+
+        if !keywords.any_of.is_empty() &&
+           !keywords.any_of.iter().any(|&schema| self.is_valid_node(schema, value)) {
+            errors.push(error(path, "anyOf",
+                              "value doesn't match any of the schemas in anyOf".to_string()));
+        }
+
+        if !keywords.one_of.is_empty() {
+            let matches = keywords.one_of.iter()
+                .filter(|&&schema| self.is_valid_node(schema, value))
+                .count();
+
+            if matches != 1 {
+                errors.push(error(path, "oneOf", format!(
+                    "value matches {} of the schemas in oneOf, instead of exactly one",
+                    matches)));
+            }
+        }
+
+        match keywords.not {
+            Some(schema) if self.is_valid_node(schema, value) => {
+                errors.push(error(path, "not", "value matches the schema in not".to_string()));
+            }
+            _ => { }
+        }
+    }
+
+    fn validate_number(&self,
+                       keywords: &Keywords,
+                       n: Num,
+                       value: &Json,
+                       path: &str,
+                       errors: &mut Vec<ValidationError>) {
+        match keywords.minimum {
+            Some(min) if n.compare(min) == Some(Less) => {
+                errors.push(error(path, "minimum",
+                                  format!("{} is less than the minimum of {}", value, min)));
+            }
+            _ => { }
+        }
+
+        match keywords.maximum {
+            Some(max) if n.compare(max) == Some(Greater) => {
+                errors.push(error(path, "maximum",
+                                  format!("{} is greater than the maximum of {}", value, max)));
+            }
+            _ => { }
+        }
+
+        match keywords.exclusive_minimum {
+            Some(min) if n.compare(min) != Some(Greater) => {
+                errors.push(error(path, "exclusiveMinimum",
+                                  format!("{} is not greater than {}", value, min)));
+            }
+            _ => { }
+        }
+
+        match keywords.exclusive_maximum {
+            Some(max) if n.compare(max) != Some(Less) => {
+                errors.push(error(path, "exclusiveMaximum",
+                                  format!("{} is not less than {}", value, max)));
+            }
+            _ => { }
+        }
+
+        match keywords.multiple_of {
+            Some(step) if !n.is_multiple_of(step) => {
+                errors.push(error(path, "multipleOf",
+                                  format!("{} is not a multiple of {}", value, step)));
+            }
+            _ => { }
+        }
+    }
+
+    fn validate_string(&self,
+                       keywords: &Keywords,
+                       s: &str,
+                       path: &str,
+                       errors: &mut Vec<ValidationError>) {
+        let len = s.chars().count();
+
+        match keywords.min_length {
+            Some(min) if len < min => {
+                errors.push(error(path, "minLength",
+                                  format!("string is shorter than {} characters", min)));
+            }
+            _ => { }
+        }
+
+        match keywords.max_length {
+            Some(max) if len > max => {
+                errors.push(error(path, "maxLength",
+                                  format!("string is longer than {} characters", max)));
+            }
+            _ => { }
+        }
+
+        match keywords.pattern {
+            Some((ref pattern, ref regex)) if !regex.is_match(s) => {
+                errors.push(error(path, "pattern",
+                                  format!("string doesn't match the pattern `{}`", pattern)));
+            }
+            _ => { }
+        }
+    }
+
+    fn validate_list(&self,
+                     keywords: &Keywords,
+                     list: &[Json],
+                     path: &str,
+                     errors: &mut Vec<ValidationError>) {
+        for (i, item) in list.iter().enumerate() {
+            let schema = if i < keywords.prefix_items.len() {
+                Some(keywords.prefix_items.as_slice()[i])
+            } else {
+                keywords.items
+            };
+
+            match schema {
+                Some(schema) => {
+                    let item_path = format!("{}/{}", path, i);
+                    self.validate_node(schema, item, item_path.as_slice(), errors);
+                }
+                None => { }
+            }
+        }
+
+        match keywords.min_items {
+            Some(min) if list.len() < min => {
+                errors.push(error(path, "minItems",
+                                  format!("array has fewer than {} items", min)));
+            }
+            _ => { }
+        }
+
+        match keywords.max_items {
+            Some(max) if list.len() > max => {
+                errors.push(error(path, "maxItems",
+                                  format!("array has more than {} items", max)));
+            }
+            _ => { }
+        }
+
+        if keywords.unique_items {
+            let duplicate = range(0, list.len()).any(|i| {
+                range(i + 1, list.len()).any(|j| json_equal(&list[i], &list[j]))
+            });
+
+            if duplicate {
+                errors.push(error(path, "uniqueItems",
+                                  "array items aren't unique".to_string()));
+            }
+        }
+    }
+
+    fn validate_object(&self,
+                       keywords: &Keywords,
+                       map: &JsonObject,
+                       path: &str,
+                       errors: &mut Vec<ValidationError>) {
+        for name in keywords.required.iter() {
+            if !map.contains_key(name) {
+                errors.push(error(path, "required",
+                                  format!("missing required property `{}`", name)));
+            }
+        }
+
+        for &(ref name, schema) in keywords.properties.iter() {
+            match map.find(name) {
+                Some(value) => {
+                    let property_path = child_path(path, name.as_slice());
+                    self.validate_node(schema, value, property_path.as_slice(), errors);
+                }
+                None => { }
+            }
+        }
+
+        match keywords.additional_properties {
+            Some(schema) => {
+                for (name, value) in map.iter() {
+                    if keywords.properties.iter().any(|&(ref known, _)| known == name) {
+                        continue;
+                    }
+
+                    let property_path = child_path(path, name.as_slice());
+
+                    match self.nodes.as_slice()[schema] {
+                        BoolSchema(false) => {
+                            errors.push(error(property_path.as_slice(), "additionalProperties",
+                                              format!("property `{}` is not allowed", name)));
+                        }
+                        _ => {
+                            self.validate_node(schema, value, property_path.as_slice(), errors);
+                        }
+                    }
+                }
+            }
+            None => { }
+        }
+
+        match keywords.min_properties {
+            Some(min) if map.len() < min => {
+                errors.push(error(path, "minProperties",
+                                  format!("object has fewer than {} properties", min)));
+            }
+            _ => { }
+        }
+
+        match keywords.max_properties {
+            Some(max) if map.len() > max => {
+                errors.push(error(path, "maxProperties",
+                                  format!("object has more than {} properties", max)));
+            }
+            _ => { }
+        }
+    }
+}
+
+fn error(path: &str, keyword: &'static str, message: string::String) -> ValidationError {
+    ValidationError {
+        path: path.to_string(),
+        keyword: keyword,
+        message: message,
+    }
+}
+
+fn child_path(path: &str, token: &str) -> string::String {
+    format!("{}/{}", path, escape_pointer_token(token))
+}
+
+/// Compares values the way JSON Schema does, where numbers are equal if they
+/// have the same value regardless of how they are written.
+fn json_equal(a: &Json, b: &Json) -> bool {
+    match (a, b) {
+        (&Integer(a), &Integer(b)) => a == b,
+        (&U64(a), &U64(b)) => a == b,
+        (&List(ref a), &List(ref b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| json_equal(a, b))
+        }
+        (&Object(ref a), &Object(ref b)) => {
+            a.len() == b.len() && a.iter().all(|(key, a)| {
+                b.find(key).map_or(false, |b| json_equal(a, b))
+            })
+        }
+        _ if a.is_number() && b.is_number() => {
+            match (Num::from_json(a), Num::from_json(b)) {
+                (Some(a), Some(b)) => a.compare(b) == Some(Equal),
+                _ => false,
+            }
+        }
+        _ => a == b,
+    }
+}
+
+/// A number from a schema or a value. Integers are kept exact, so that
+/// those beyond 2^53 compare correctly; a float with no fraction that fits
+/// in 64 bits is held as an integer too.
+#[deriving(Clone, PartialEq)]
+enum Num {
+    /// Always negative.
+    NegInt(i64),
+    PosInt(u64),
+    Float(f64),
+}
+
+impl Num {
+    fn from_json(value: &Json) -> Option<Num> {
+        match *value {
+            Integer(n) if n < 0 => Some(NegInt(n)),
+            Integer(n) => Some(PosInt(n as u64)),
+            U64(n) => Some(PosInt(n)),
+            Floating(n) => Some(Num::from_f64(n)),
+            Number(ref s) => {
+                match from_str::<u64>(s.as_slice()) {
+                    Some(n) => { return Some(PosInt(n)); }
+                    None => { }
+                }
+                match from_str::<i64>(s.as_slice()) {
+                    Some(n) if n < 0 => { return Some(NegInt(n)); }
+                    _ => { }
+                }
+                from_str::<f64>(s.as_slice()).map(Num::from_f64)
+            }
+            _ => None,
+        }
+    }
+
+    fn from_f64(n: f64) -> Num {
+        // Both bounds are powers of two, so they are exact as floats, and
+        // every integral float between them converts exactly.
+        if n.fract() != 0.0 {
+            Float(n)
+        } else if n >= 0.0 && n < 18446744073709551616.0 {
+            PosInt(n as u64)
+        } else if n < 0.0 && n >= -9223372036854775808.0 {
+            NegInt(n as i64)
+        } else {
+            Float(n)
+        }
+    }
+
+    fn to_f64(self) -> f64 {
+        match self {
+            NegInt(n) => n as f64,
+            PosInt(n) => n as f64,
+            Float(n) => n,
+        }
+    }
+
+    /// The number as decimal digits and a power of ten, ignoring the sign.
+    fn decimal(self) -> Option<(u64, i64)> {
+        match self {
+            NegInt(n) => Some((magnitude(n), 0)),
+            PosInt(n) => Some((n, 0)),
+            Float(n) => decimal(n),
+        }
+    }
+
+    fn is_integral(self) -> bool {
+        match self {
+            NegInt(_) | PosInt(_) => true,
+            // Outside of the 64-bit integers, every float is a whole number.
+            Float(n) => n.is_finite() && n.fract() == 0.0,
+        }
+    }
+
+    fn compare(self, other: Num) -> Option<Ordering> {
+        match (self, other) {
+            (NegInt(a), NegInt(b)) => Some(a.cmp(&b)),
+            (PosInt(a), PosInt(b)) => Some(a.cmp(&b)),
+            (NegInt(_), PosInt(_)) => Some(Less),
+            (PosInt(_), NegInt(_)) => Some(Greater),
+            (Float(a), Float(b)) => a.partial_cmp(&b),
+            (Float(a), b) => b.compare_float(a).map(|ordering| ordering.reverse()),
+            (a, Float(b)) => a.compare_float(b),
+        }
+    }
+
+    /// Compares an integer with a float that `from_f64` left as a float.
+    fn compare_float(self, f: f64) -> Option<Ordering> {
+        if f.fract() == 0.0 {
+            // A whole number outside of the 64-bit integers.
+            Some(if f > 0.0 { Less } else { Greater })
+        } else {
+            // A float with a fraction is smaller than 2^52 in magnitude, so
+            // rounding the integer to a float can't change how they order.
+            self.to_f64().partial_cmp(&f)
+        }
+    }
+
+    /// Whether `self` is a whole multiple of `step`, which is positive.
+    /// Integers are checked exactly. Otherwise both numbers are taken as
+    /// the shortest decimals that round-trip, so that `0.3` is a multiple
+    /// of `0.1`, and checked with integer arithmetic when they fit.
+    fn is_multiple_of(self, step: Num) -> bool {
+        match (self, step) {
+            (NegInt(n), PosInt(step)) => magnitude(n) % step == 0,
+            (PosInt(n), PosInt(step)) => n % step == 0,
+            (n, step) => {
+                match (n.decimal(), step.decimal()) {
+                    (Some((n_digits, n_exp)), Some((step_digits, step_exp))) => {
+                        let exp = n_exp.min(step_exp);
+                        match (scale(n_digits, n_exp - exp), scale(step_digits, step_exp - exp)) {
+                            (Some(n), Some(step)) => { return n % step == 0; }
+                            _ => { }
+                        }
+                    }
+                    _ => { }
+                }
+
+                // The decimals are too far apart in scale to compare exactly.
+                (n.to_f64() / step.to_f64()).fract() == 0.0
+            }
+        }
+    }
+}
+
+impl fmt::Show for Num {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NegInt(n) => write!(f, "{}", n),
+            PosInt(n) => write!(f, "{}", n),
+            Float(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// The magnitude of a negative `i64`, which doesn't overflow for `i64::MIN`.
+fn magnitude(n: i64) -> u64 {
+    (-(n + 1)) as u64 + 1
+}
+
+/// Splits a finite, non-zero float into the digits and power of ten of the
+/// shortest decimal that round-trips, ignoring the sign, as in `-1.25` =
+/// `125e-2`.
+fn decimal(n: f64) -> Option<(u64, i64)> {
+    if !n.is_finite() || n == 0.0 {
+        return None;
+    }
+
+    let mut buf = [0u8, .. 17];
+    let (len, exp) = dtoa::shortest(&dtoa::decode_f64(n.abs()), &mut buf);
+    let digits = buf.slice_to(len).iter().fold(0u64, |digits, &b| {
+        digits * 10 + (b - b'0') as u64
+    });
+
+    // `shortest` gives `0.d1d2...dn * 10^exp`.
+    Some((digits, exp as i64 - len as i64))
+}
+
+/// `digits * 10^exp`, if it fits in a `u64`.
+fn scale(digits: u64, exp: i64) -> Option<u64> {
+    let mut n = digits;
+    for _ in range(0, exp) {
+        n = match n.checked_mul(&10) {
+            Some(n) => n,
+            None => { return None; }
+        };
+    }
+    Some(n)
+}
+
+fn type_of(value: &Json) -> JsonType {
+    match *value {
+        Null => NullType,
+        Boolean(_) => BooleanType,
+        Integer(_) | U64(_) => IntegerType,
+        Floating(_) | Number(_) => NumberType,
+        String(_) => StringType,
+        List(_) => ArrayType,
+        Object(_) => ObjectType,
+    }
+}
+
+impl JsonType {
+    fn from_name(name: &str) -> Option<JsonType> {
+        match name {
+            "null" => Some(NullType),
+            "boolean" => Some(BooleanType),
+            "integer" => Some(IntegerType),
+            "number" => Some(NumberType),
+            "string" => Some(StringType),
+            "array" => Some(ArrayType),
+            "object" => Some(ObjectType),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            NullType => "null",
+            BooleanType => "boolean",
+            IntegerType => "integer",
+            NumberType => "number",
+            StringType => "string",
+            ArrayType => "array",
+            ObjectType => "object",
+        }
+    }
+
+    fn matches(&self, value: &Json) -> bool {
+        match (*self, type_of(value)) {
+            (NumberType, IntegerType) => true,
+            // Integers may be written with a fraction of zero, as in `1.0`.
+            (IntegerType, NumberType) => Num::from_json(value).map_or(false, |n| n.is_integral()),
+            (expected, found) => expected == found,
+        }
+    }
+}
+
+impl Keywords {
+    /// The subschemas that apply to the same value as this schema.
+    fn in_place_subschemas(&self) -> Vec<uint> {
+        let mut subschemas: Vec<uint> = self.reference.iter().map(|&i| i).collect();
+        subschemas.push_all(self.all_of.as_slice());
+        subschemas.push_all(self.any_of.as_slice());
+        subschemas.push_all(self.one_of.as_slice());
+        subschemas.extend(self.not.iter().map(|&i| i));
+        subschemas
+    }
+}
+
+#[deriving(Clone, PartialEq)]
+enum Visit {
+    Unvisited,
+    Visiting,
+    Visited,
+}
+
+struct Compiler<'a> {
+    document: &'a Json,
+    nodes: Vec<Option<SchemaNode>>,
+    /// The index of each schema compiled so far, by its JSON Pointer into
+    /// the document, so recursive references end up sharing nodes.
+    compiled: HashMap<string::String, uint>,
+}
+
+impl<'a> Compiler<'a> {
+    fn compile(&mut self, pointer: string::String, schema: &'a Json) -> Result<uint, SchemaError> {
+        match self.compiled.find(&pointer) {
+            Some(&index) => { return Ok(index); }
+            None => { }
+        }
+
+        let index = self.nodes.len();
+        self.nodes.push(None);
+        self.compiled.insert(pointer.clone(), index);
+
+        let node = match *schema {
+            Boolean(value) => BoolSchema(value),
+            Object(ref map) => ObjectSchema(try!(self.compile_keywords(pointer.as_slice(), map))),
+            _ => {
+                return Err(InvalidSchema(pointer,
+                                         "a schema must be an object or a boolean".to_string()));
+            }
+        };
+
+        self.nodes.as_mut_slice()[index] = Some(node);
+        Ok(index)
+    }
+
+    fn compile_ref(&mut self, reference: &str) -> Result<uint, SchemaError> {
+        if !reference.starts_with("#") {
+            return Err(UnresolvedRef(reference.to_string()));
+        }
+
+        let target = reference.slice_from(1);
+        let document = self.document;
+
+        match document.pointer(target) {
+            Some(schema) => self.compile(target.to_string(), schema),
+            None => Err(UnresolvedRef(reference.to_string())),
+        }
+    }
+
+    /// Rejects a schema that applies itself to the same value through
+    /// `$ref`, `allOf`, `anyOf`, `oneOf` or `not`, as in `{"$ref": "#"}`,
+    /// since validating against it would never finish. Cycles through
+    /// `properties` or `items` are fine, as each step moves into a child of
+    /// the value.
+    fn check_cycles(&self) -> Result<(), SchemaError> {
+        let mut visits = Vec::from_elem(self.nodes.len(), Unvisited);
+
+        for index in range(0, self.nodes.len()) {
+            match self.find_cycle(index, &mut visits) {
+                Some(index) => {
+                    let pointer = self.compiled.iter()
+                        .find(|&(_, &i)| i == index)
+                        .map(|(pointer, _)| pointer.clone())
+                        .unwrap();
+                    let message = "the schema refers back to itself without moving into \
+                                   a child value";
+                    return Err(InvalidSchema(pointer, message.to_string()));
+                }
+                None => { }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Walks the subschemas applied in place from `index`, returning a node
+    /// that is reached again while it is still being walked.
+    fn find_cycle(&self, index: uint, visits: &mut Vec<Visit>) -> Option<uint> {
+        match visits.as_slice()[index] {
+            Visiting => { return Some(index); }
+            Visited => { return None; }
+            Unvisited => { }
+        }
+
+        *visits.get_mut(index) = Visiting;
+
+        match *self.nodes.as_slice()[index].as_ref().unwrap() {
+            ObjectSchema(ref keywords) => {
+                for &next in keywords.in_place_subschemas().iter() {
+                    match self.find_cycle(next, visits) {
+                        Some(index) => { return Some(index); }
+                        None => { }
+                    }
+                }
+            }
+            BoolSchema(_) => { }
+        }
+
+        *visits.get_mut(index) = Visited;
+        None
+    }
+
+    fn compile_keywords(&mut self,
+                        pointer: &str,
+                        map: &'a JsonObject) -> Result<Keywords, SchemaError> {
+        let reference = match get(map, "$ref") {
+            Some(&String(ref reference)) => Some(try!(self.compile_ref(reference.as_slice()))),
+            Some(_) => { return Err(invalid(pointer, "$ref", "must be a string")); }
+            None => None,
+        };
+
+        let types = match get(map, "type") {
+            Some(&String(ref name)) => {
+                match JsonType::from_name(name.as_slice()) {
+                    Some(ty) => Some(vec![ty]),
+                    None => { return Err(invalid(pointer, "type", "is not a known type")); }
+                }
+            }
+            Some(&List(ref names)) => {
+                let mut types = Vec::new();
+                for name in names.iter() {
+                    match name.as_string().and_then(|name| JsonType::from_name(name)) {
+                        Some(ty) => types.push(ty),
+                        None => { return Err(invalid(pointer, "type", "is not a known type")); }
+                    }
+                }
+                Some(types)
+            }
+            Some(_) => { return Err(invalid(pointer, "type", "must be a string or an array")); }
+            None => None,
+        };
+
+        let enumeration = match get(map, "enum") {
+            Some(&List(ref values)) => Some(values.clone()),
+            Some(_) => { return Err(invalid(pointer, "enum", "must be an array")); }
+            None => None,
+        };
+
+        let pattern = match get(map, "pattern") {
+            Some(&String(ref pattern)) => {
+                match Regex::new(pattern.as_slice()) {
+                    Ok(regex) => Some((pattern.clone(), regex)),
+                    Err(err) => {
+                        return Err(invalid(pointer, "pattern", err.as_slice()));
+                    }
+                }
+            }
+            Some(_) => { return Err(invalid(pointer, "pattern", "must be a string")); }
+            None => None,
+        };
+
+        // The draft 7 `items` array is the same as the 2020-12 `prefixItems`,
+        // with `additionalItems` for the rest.
+        let (prefix_items, items) = match get(map, "items") {
+            Some(&List(_)) => {
+                (try!(self.compile_list(pointer, map, "items")),
+                 try!(self.compile_schema(pointer, map, "additionalItems")))
+            }
+            _ => {
+                (try!(self.compile_list(pointer, map, "prefixItems")),
+                 try!(self.compile_schema(pointer, map, "items")))
+            }
+        };
+
+        let unique_items = match get(map, "uniqueItems") {
+            Some(&Boolean(unique)) => unique,
+            Some(_) => { return Err(invalid(pointer, "uniqueItems", "must be a boolean")); }
+            None => false,
+        };
+
+        let mut properties = Vec::new();
+        match get(map, "properties") {
+            Some(&Object(ref schemas)) => {
+                let properties_pointer = child_path(pointer, "properties");
+                for (name, schema) in schemas.iter() {
+                    let schema_pointer = child_path(properties_pointer.as_slice(),
+                                                    name.as_slice());
+                    properties.push((name.clone(), try!(self.compile(schema_pointer, schema))));
+                }
+            }
+            Some(_) => { return Err(invalid(pointer, "properties", "must be an object")); }
+            None => { }
+        }
+
+        let mut required = Vec::new();
+        match get(map, "required") {
+            Some(&List(ref names)) => {
+                for name in names.iter() {
+                    match name.as_string() {
+                        Some(name) => required.push(name.to_string()),
+                        None => {
+                            return Err(invalid(pointer, "required", "must hold strings"));
+                        }
+                    }
+                }
+            }
+            Some(_) => { return Err(invalid(pointer, "required", "must be an array")); }
+            None => { }
+        }
+
+        Ok(Keywords {
+            reference: reference,
+            types: types,
+            enumeration: enumeration,
+            constant: get(map, "const").map(|constant| constant.clone()),
+            minimum: try!(get_number(pointer, map, "minimum")),
+            maximum: try!(get_number(pointer, map, "maximum")),
+            exclusive_minimum: try!(get_number(pointer, map, "exclusiveMinimum")),
+            exclusive_maximum: try!(get_number(pointer, map, "exclusiveMaximum")),
+            multiple_of: match try!(get_number(pointer, map, "multipleOf")) {
+                Some(step) if step.compare(PosInt(0)) != Some(Greater) => {
+                    return Err(invalid(pointer, "multipleOf", "must be greater than zero"));
+                }
+                step => step,
+            },
+            min_length: try!(get_count(pointer, map, "minLength")),
+            max_length: try!(get_count(pointer, map, "maxLength")),
+            pattern: pattern,
+            prefix_items: prefix_items,
+            items: items,
+            min_items: try!(get_count(pointer, map, "minItems")),
+            max_items: try!(get_count(pointer, map, "maxItems")),
+            unique_items: unique_items,
+            properties: properties,
+            required: required,
+            additional_properties: try!(self.compile_schema(pointer, map, "additionalProperties")),
+            min_properties: try!(get_count(pointer, map, "minProperties")),
+            max_properties: try!(get_count(pointer, map, "maxProperties")),
+            all_of: try!(self.compile_list(pointer, map, "allOf")),
+            any_of: try!(self.compile_list(pointer, map, "anyOf")),
+            one_of: try!(self.compile_list(pointer, map, "oneOf")),
+            not: try!(self.compile_schema(pointer, map, "not")),
+        })
+    }
+
+    fn compile_schema(&mut self,
+                      pointer: &str,
+                      map: &'a JsonObject,
+                      keyword: &str) -> Result<Option<uint>, SchemaError> {
+        match get(map, keyword) {
+            Some(schema) => {
+                let index = try!(self.compile(child_path(pointer, keyword), schema));
+                Ok(Some(index))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn compile_list(&mut self,
+                    pointer: &str,
+                    map: &'a JsonObject,
+                    keyword: &str) -> Result<Vec<uint>, SchemaError> {
+        match get(map, keyword) {
+            Some(&List(ref schemas)) => {
+                let list_pointer = child_path(pointer, keyword);
+                let mut indices = Vec::new();
+                for (i, schema) in schemas.iter().enumerate() {
+                    let schema_pointer = format!("{}/{}", list_pointer, i);
+                    indices.push(try!(self.compile(schema_pointer, schema)));
+                }
+                Ok(indices)
+            }
+            Some(_) => Err(invalid(pointer, keyword, "must be an array of schemas")),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+fn get<'a>(map: &'a JsonObject, keyword: &str) -> Option<&'a Json> {
+    map.find(&keyword.to_string())
+}
+
+fn get_number(pointer: &str,
+              map: &JsonObject,
+              keyword: &str) -> Result<Option<Num>, SchemaError> {
+    match get(map, keyword) {
+        Some(value) => {
+            match Num::from_json(value) {
+                Some(n) => Ok(Some(n)),
+                None => Err(invalid(pointer, keyword, "must be a number")),
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+fn get_count(pointer: &str,
+             map: &JsonObject,
+             keyword: &str) -> Result<Option<uint>, SchemaError> {
+    match get(map, keyword) {
+        Some(value) => {
+            match value.as_u64() {
+                Some(n) => Ok(Some(n as uint)),
+                None => Err(invalid(pointer, keyword, "must be a non-negative integer")),
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+fn invalid(pointer: &str, keyword: &str, message: &str) -> SchemaError {
+    InvalidSchema(child_path(pointer, keyword), format!("`{}` {}", keyword, message))
+}
+
+/// Types that can describe the JSON they serialize to as a schema.
+///
+/// `#[deriving_json_schema]` implements this for structs and enums, using
+/// the same field names and `#[serial_name]` renames as
+/// `#[deriving_serializable]`. Struct fields whose type is optional are left
+/// out of `required`, and an enum becomes a `oneOf` with one alternative per
+/// variant, each an object holding the variant name and its list of fields.
+/// Derived schemas are collected in `Definitions` and referred to by type
+/// name, so that recursive types are supported.
+pub trait ToJsonSchema {
+    /// The schema of values of this type, adding the schemas of the named
+    /// types it uses to `defs`.
+    fn json_schema(_: Option<Self>, defs: &mut Definitions) -> Json;
+
+    /// Whether a struct field of this type may be left out.
+    fn is_optional(_: Option<Self>) -> bool { false }
+}
+
+/// Returns the schema of `T`.
+///
+/// ```rust
+/// use serde::json;
+/// use serde::json::schema::schema_for;
+///
+/// let schema = schema_for::<Vec<String>>();
+/// assert_eq!(schema.to_string(),
+///            "{\"items\":{\"type\":\"string\"},\"type\":\"array\"}".to_string());
+/// ```
+pub fn schema_for<T: ToJsonSchema>() -> Json {
+    let mut defs = Definitions::new();
+    let schema = ToJsonSchema::json_schema(None::<T>, &mut defs);
+    defs.into_schema(schema)
+}
+
+/// The schemas of the named types met while building a schema, which end up
+/// under `$defs`. Types are referred to with a `$ref` by name rather than
+/// inlined, so a recursive type such as `struct Node { children: Vec<Node> }`
+/// doesn't expand forever.
+pub struct Definitions {
+    /// `Null` while the type's schema is being built.
+    schemas: TreeMap<string::String, Json>,
+    /// How many times each type is referred to.
+    uses: HashMap<string::String, uint>,
+    /// The name under `$defs` of each type, by its `definition_key`.
+    names: HashMap<string::String, string::String>,
+}
+
+impl Definitions {
+    pub fn new() -> Definitions {
+        Definitions {
+            schemas: TreeMap::new(),
+            uses: HashMap::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    /// Returns a `$ref` to the schema of the type called `name`, calling
+    /// `build` for the schema the first time the type is met. `key` tells
+    /// the type apart from others of the same name, which are defined as
+    /// `name2`, `name3` and so on. This is used by `#[deriving_json_schema]`.
+    pub fn define(&mut self,
+                  name: &str,
+                  key: string::String,
+                  build: |&mut Definitions| -> Json) -> Json {
+        let name = match self.names.find(&key) {
+            Some(name) => name.clone(),
+            None => {
+                let name = self.unused_name(name);
+                self.names.insert(key, name.clone());
+                self.schemas.insert(name.clone(), Null);
+                let schema = build(self);
+                self.schemas.insert(name.clone(), schema);
+                name
+            }
+        };
+
+        let uses = match self.uses.find(&name) {
+            Some(&uses) => uses + 1,
+            None => 1,
+        };
+        self.uses.insert(name.clone(), uses);
+
+        definition_ref(name.as_slice())
+    }
+
+    fn unused_name(&self, name: &str) -> string::String {
+        let mut unused = name.to_string();
+        let mut suffix = 1u;
+        while self.schemas.contains_key(&unused) {
+            suffix += 1;
+            unused = format!("{}{}", name, suffix);
+        }
+        unused
+    }
+
+    /// Finishes the schema `root` with the definitions it refers to. If
+    /// `root` is a reference to a type used nowhere else, that type's schema
+    /// is inlined in its place.
+    fn into_schema(mut self, root: Json) -> Json {
+        let inlined = self.schemas.keys()
+            .find(|name| {
+                self.uses.find(*name) == Some(&1) && definition_ref(name.as_slice()) == root
+            })
+            .map(|name| name.clone());
+
+        let root = match inlined {
+            Some(name) => self.schemas.remove(&name).unwrap(),
+            None => root,
+        };
+
+        if self.schemas.is_empty() {
+            return root;
+        }
+
+        match root {
+            Object(mut map) => {
+                map.insert("$defs".to_string(), Object(self.schemas));
+                Object(map)
+            }
+            root => root,
+        }
+    }
+}
+
+/// Builds the key `Definitions` tells types apart by, from the path of a
+/// type and the schemas of its type parameters. This is used by
+/// `#[deriving_json_schema]`.
+pub fn definition_key(path: &str, params: Vec<Json>) -> string::String {
+    if params.is_empty() {
+        return path.to_string();
+    }
+
+    let params: Vec<string::String> = params.iter().map(|param| param.to_string()).collect();
+    format!("{}<{}>", path, params.connect(", "))
+}
+
+fn definition_ref(name: &str) -> Json {
+    object(vec![
+        ("$ref", String(format!("#/$defs/{}", escape_pointer_token(name)))),
+    ])
+}
+
+/// Builds the schema of a struct from its fields, given as their serialized
+/// name, schema and whether they are optional. This is used by
+/// `#[deriving_json_schema]`.
+pub fn struct_schema(name: &str, fields: Vec<(&str, Json, bool)>) -> Json {
+    let mut properties = TreeMap::new();
+    let mut required = Vec::new();
+
+    for (field, schema, optional) in fields.into_iter() {
+        if !optional {
+            required.push(String(field.to_string()));
+        }
+        properties.insert(field.to_string(), schema);
+    }
+
+    object(vec![
+        ("title", String(name.to_string())),
+        ("type", String("object".to_string())),
+        ("properties", Object(properties)),
+        ("required", List(required)),
+    ])
+}
+
+/// Builds the schema of an enum from its variants, given as their name and
+/// the schemas of their fields. This is used by `#[deriving_json_schema]`.
+pub fn enum_schema(name: &str, variants: Vec<(&str, Vec<Json>)>) -> Json {
+    let alternatives = variants.into_iter().map(|(variant, fields)| {
+        let len = fields.len() as u64;
+        let mut properties = TreeMap::new();
+        properties.insert(variant.to_string(), object(vec![
+            ("type", String("array".to_string())),
+            ("prefixItems", List(fields)),
+            ("minItems", U64(len)),
+            ("maxItems", U64(len)),
+        ]));
+
+        object(vec![
+            ("type", String("object".to_string())),
+            ("properties", Object(properties)),
+            ("required", List(vec![String(variant.to_string())])),
+            ("additionalProperties", Boolean(false)),
+        ])
+    }).collect();
+
+    object(vec![
+        ("title", String(name.to_string())),
+        ("oneOf", List(alternatives)),
+    ])
+}
+
+fn object(entries: Vec<(&str, Json)>) -> Json {
+    let mut map = TreeMap::new();
+    for (key, value) in entries.into_iter() {
+        map.insert(key.to_string(), value);
+    }
+    Object(map)
+}
+
+fn type_schema(ty: &str) -> Json {
+    object(vec![("type", String(ty.to_string()))])
+}
+
+fn integer_schema(min: Json, max: Json) -> Json {
+    object(vec![
+        ("type", String("integer".to_string())),
+        ("minimum", min),
+        ("maximum", max),
+    ])
+}
+
+macro_rules! impl_signed_schema {
+    ($ty:ty, $min:expr, $max:expr) => {
+        impl ToJsonSchema for $ty {
+            fn json_schema(_: Option<$ty>, _: &mut Definitions) -> Json {
+                integer_schema(Integer($min as i64), Integer($max as i64))
+            }
+        }
+    }
+}
+
+macro_rules! impl_unsigned_schema {
+    ($ty:ty, $max:expr) => {
+        impl ToJsonSchema for $ty {
+            fn json_schema(_: Option<$ty>, _: &mut Definitions) -> Json {
+                integer_schema(U64(0), U64($max as u64))
+            }
+        }
+    }
+}
+
+impl_signed_schema!(int, int::MIN, int::MAX)
+impl_signed_schema!(i8, i8::MIN, i8::MAX)
+impl_signed_schema!(i16, i16::MIN, i16::MAX)
+impl_signed_schema!(i32, i32::MIN, i32::MAX)
+impl_signed_schema!(i64, i64::MIN, i64::MAX)
+impl_unsigned_schema!(uint, uint::MAX)
+impl_unsigned_schema!(u8, u8::MAX)
+impl_unsigned_schema!(u16, u16::MAX)
+impl_unsigned_schema!(u32, u32::MAX)
+impl_unsigned_schema!(u64, u64::MAX)
+
+impl ToJsonSchema for f32 {
+    fn json_schema(_: Option<f32>, _: &mut Definitions) -> Json { type_schema("number") }
+}
+
+impl ToJsonSchema for f64 {
+    fn json_schema(_: Option<f64>, _: &mut Definitions) -> Json { type_schema("number") }
+}
+
+impl ToJsonSchema for bool {
+    fn json_schema(_: Option<bool>, _: &mut Definitions) -> Json { type_schema("boolean") }
+}
+
+impl ToJsonSchema for () {
+    fn json_schema(_: Option<()>, _: &mut Definitions) -> Json { type_schema("null") }
+}
+
+impl ToJsonSchema for char {
+    fn json_schema(_: Option<char>, _: &mut Definitions) -> Json {
+        object(vec![
+            ("type", String("string".to_string())),
+            ("minLength", U64(1)),
+            ("maxLength", U64(1)),
+        ])
+    }
+}
+
+impl ToJsonSchema for string::String {
+    fn json_schema(_: Option<string::String>, _: &mut Definitions) -> Json {
+        type_schema("string")
+    }
+}
+
+impl<'a> ToJsonSchema for &'a str {
+    fn json_schema(_: Option<&'a str>, _: &mut Definitions) -> Json { type_schema("string") }
+}
+
+impl ToJsonSchema for Json {
+    fn json_schema(_: Option<Json>, _: &mut Definitions) -> Json { Boolean(true) }
+}
+
+impl<T: ToJsonSchema> ToJsonSchema for Option<T> {
+    fn json_schema(_: Option<Option<T>>, defs: &mut Definitions) -> Json {
+        object(vec![
+            ("anyOf", List(vec![
+                ToJsonSchema::json_schema(None::<T>, defs),
+                type_schema("null"),
+            ])),
+        ])
+    }
+
+    fn is_optional(_: Option<Option<T>>) -> bool { true }
+}
+
+impl<T: ToJsonSchema> ToJsonSchema for Box<T> {
+    fn json_schema(_: Option<Box<T>>, defs: &mut Definitions) -> Json {
+        ToJsonSchema::json_schema(None::<T>, defs)
+    }
+
+    fn is_optional(_: Option<Box<T>>) -> bool {
+        ToJsonSchema::is_optional(None::<T>)
+    }
+}
+
+impl<T: ToJsonSchema> ToJsonSchema for Vec<T> {
+    fn json_schema(_: Option<Vec<T>>, defs: &mut Definitions) -> Json {
+        object(vec![
+            ("type", String("array".to_string())),
+            ("items", ToJsonSchema::json_schema(None::<T>, defs)),
+        ])
+    }
+}
+
+impl<V: ToJsonSchema> ToJsonSchema for TreeMap<string::String, V> {
+    fn json_schema(_: Option<TreeMap<string::String, V>>, defs: &mut Definitions) -> Json {
+        object(vec![
+            ("type", String("object".to_string())),
+            ("additionalProperties", ToJsonSchema::json_schema(None::<V>, defs)),
+        ])
+    }
+}
+
+impl<V: ToJsonSchema> ToJsonSchema for HashMap<string::String, V> {
+    fn json_schema(_: Option<HashMap<string::String, V>>, defs: &mut Definitions) -> Json {
+        object(vec![
+            ("type", String("object".to_string())),
+            ("additionalProperties", ToJsonSchema::json_schema(None::<V>, defs)),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::TreeMap;
+    use std::i64;
+    use std::string;
+
+    use json::Json;
+    use json;
+    use super::{Schema, InvalidSchema, UnresolvedRef, schema_for};
+
+    #[deriving_serializable]
+    #[deriving_json_schema]
+    struct Item {
+        name: string::String,
+        #[serial_name = "qty"]
+        quantity: u8,
+        note: Option<string::String>,
+        tags: Vec<string::String>,
+        counts: TreeMap<string::String, int>,
+    }
+
+    #[deriving_serializable]
+    #[deriving_json_schema]
+    enum Shape {
+        Circle(f64),
+        Rect(f64, f64),
+        Empty,
+    }
+
+    #[deriving_serializable]
+    #[deriving_json_schema]
+    struct Node {
+        name: string::String,
+        children: Vec<Node>,
+        next: Option<Box<Node>>,
+    }
+
+    #[deriving_serializable]
+    #[deriving_json_schema]
+    struct Wrapper<T> {
+        value: T,
+    }
+
+    mod other {
+        #[deriving_serializable]
+        #[deriving_json_schema]
+        pub struct Wrapper {
+            pub flag: bool,
+        }
+    }
+
+    #[deriving_serializable]
+    #[deriving_json_schema]
+    struct Pair {
+        number: Wrapper<int>,
+        text: Wrapper<string::String>,
+        other: other::Wrapper,
+    }
+
+    fn parse(s: &str) -> Json {
+        json::from_str(s).unwrap()
+    }
+
+    fn failures(schema: &Schema, value: &str) -> Vec<(string::String, &'static str)> {
+        match schema.validate(&parse(value)) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.into_iter().map(|err| (err.path, err.keyword)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_schema_validate() {
+        let schema = Schema::compile(&parse(concat!(
+            "{",
+            "  \"type\": \"object\",",
+            "  \"required\": [\"id\", \"event\", \"amount\"],",
+            "  \"properties\": {",
+            "    \"id\": {\"type\": \"string\", \"pattern\": \"^[a-f0-9]{8}$\"},",
+            "    \"event\": {\"enum\": [\"created\", \"deleted\"]},",
+            "    \"amount\": {\"$ref\": \"#/definitions/money\"},",
+            "    \"tags\": {",
+            "      \"type\": \"array\",",
+            "      \"items\": {\"type\": \"string\", \"maxLength\": 3},",
+            "      \"uniqueItems\": true",
+            "    }",
+            "  },",
+            "  \"additionalProperties\": false,",
+            "  \"definitions\": {",
+            "    \"money\": {\"type\": \"number\", \"minimum\": 0, \"exclusiveMaximum\": 1000}",
+            "  }",
+            "}"
+        ))).unwrap();
+
+        assert!(schema.is_valid(&parse(
+            "{\"id\": \"deadbeef\", \"event\": \"created\", \"amount\": 12.5, \"tags\": [\"a\"]}"
+        )));
+
+        assert_eq!(
+            failures(&schema,
+                     "{\"id\": \"DEADBEEF\", \"amount\": -1, \"tags\": [\"abcd\", \"abcd\"], \
+                      \"extra\": 1}"),
+            vec![
+                ("".to_string(), "required"),
+                ("/amount".to_string(), "minimum"),
+                ("/id".to_string(), "pattern"),
+                ("/tags/0".to_string(), "maxLength"),
+                ("/tags/1".to_string(), "maxLength"),
+                ("/tags".to_string(), "uniqueItems"),
+                ("/extra".to_string(), "additionalProperties"),
+            ]);
+
+        assert_eq!(failures(&schema, "[]"), vec![
+            ("".to_string(), "type"),
+        ]);
+    }
+
+    #[test]
+    fn test_schema_combinators() {
+        let schema = Schema::compile(&parse(concat!(
+            "{",
+            "  \"$defs\": {",
+            "    \"node\": {",
+            "      \"type\": \"object\",",
+            "      \"properties\": {",
+            "        \"value\": {\"type\": \"integer\"},",
+            "        \"next\": {\"anyOf\": [{\"type\": \"null\"}, {\"$ref\": \"#/$defs/node\"}]}",
+            "      }",
+            "    }",
+            "  },",
+            "  \"$ref\": \"#/$defs/node\"",
+            "}"
+        ))).unwrap();
+
+        assert!(schema.is_valid(&parse(
+            "{\"value\": 1, \"next\": {\"value\": 2.0, \"next\": null}}")));
+        assert_eq!(failures(&schema, "{\"value\": 1, \"next\": {\"value\": 1.5}}"), vec![
+            ("/next".to_string(), "anyOf"),
+        ]);
+
+        let schema = Schema::compile(&parse(
+            "{\"oneOf\": [{\"type\": \"integer\"}, {\"minimum\": 0}]}")).unwrap();
+        assert!(schema.is_valid(&parse("-1")));
+        assert!(schema.is_valid(&parse("0.5")));
+        assert_eq!(failures(&schema, "5"), vec![("".to_string(), "oneOf")]);
+
+        let schema = Schema::compile(&parse(
+            "{\"allOf\": [{\"type\": \"string\"}, {\"not\": {\"const\": \"x\"}}]}")).unwrap();
+        assert!(schema.is_valid(&parse("\"y\"")));
+        assert_eq!(failures(&schema, "\"x\""), vec![("".to_string(), "not")]);
+        assert_eq!(failures(&schema, "1"), vec![("".to_string(), "type")]);
+
+        let schema = Schema::compile(&parse(
+            "{\"items\": [{\"type\": \"string\"}], \"additionalItems\": false}")).unwrap();
+        assert!(schema.is_valid(&parse("[\"a\"]")));
+        assert_eq!(failures(&schema, "[\"a\", 1]"), vec![("/1".to_string(), "false")]);
+    }
+
+    #[test]
+    fn test_schema_compile_errors() {
+        assert_eq!(Schema::compile(&parse("{\"$ref\": \"#/nope\"}")).err(),
+                   Some(UnresolvedRef("#/nope".to_string())));
+        assert_eq!(Schema::compile(&parse("{\"$ref\": \"other.json\"}")).err(),
+                   Some(UnresolvedRef("other.json".to_string())));
+
+        match Schema::compile(&parse("{\"properties\": {\"a\": {\"type\": \"strnig\"}}}")) {
+            Err(InvalidSchema(pointer, _)) => assert_eq!(pointer, "/properties/a/type".to_string()),
+            _ => panic!("expected an invalid schema"),
+        }
+
+        match Schema::compile(&parse("{\"pattern\": \"a(\"}")) {
+            Err(InvalidSchema(pointer, _)) => assert_eq!(pointer, "/pattern".to_string()),
+            _ => panic!("expected an invalid schema"),
+        }
+
+        assert!(Schema::compile(&parse("1")).is_err());
+        assert!(Schema::compile(&parse("true")).unwrap().is_valid(&parse("1")));
+    }
+
+    #[test]
+    fn test_schema_numbers() {
+        let compile = |schema: &str| Schema::compile(&parse(schema)).unwrap();
+
+        // Integers beyond 2^53 are compared exactly.
+        let schema = compile("{\"maximum\": 9007199254740992}");
+        assert!(schema.is_valid(&json::U64(9007199254740992)));
+        assert!(!schema.is_valid(&json::U64(9007199254740993)));
+        assert!(!schema.is_valid(&parse("9007199254740993")));
+
+        let schema = compile("{\"exclusiveMinimum\": 18446744073709551614}");
+        assert!(schema.is_valid(&json::U64(18446744073709551615)));
+        assert!(!schema.is_valid(&json::U64(18446744073709551614)));
+        assert!(schema.is_valid(&json::Floating(1e20)));
+        assert!(!schema.is_valid(&json::Integer(-1)));
+
+        let schema = compile("{\"const\": 9007199254740993}");
+        assert!(schema.is_valid(&json::U64(9007199254740993)));
+        assert!(!schema.is_valid(&json::U64(9007199254740992)));
+        assert!(!schema.is_valid(&json::Floating(9007199254740992.0)));
+
+        let schema = compile("{\"enum\": [-9223372036854775807, 1.5]}");
+        assert!(schema.is_valid(&json::Integer(-9223372036854775807)));
+        assert!(!schema.is_valid(&json::Integer(-9223372036854775806)));
+        assert!(schema.is_valid(&json::Floating(1.5)));
+        assert!(!schema.is_valid(&json::Integer(1)));
+
+        // Integers are multiples exactly, without any tolerance.
+        let schema = compile("{\"multipleOf\": 3}");
+        assert!(schema.is_valid(&json::U64(18446744073709551615)));
+        assert!(!schema.is_valid(&json::U64(18446744073709551614)));
+        assert!(schema.is_valid(&json::Integer(-9)));
+        assert!(!schema.is_valid(&json::Integer(i64::MIN)));
+
+        let schema = compile("{\"multipleOf\": 1000000000}");
+        assert!(schema.is_valid(&json::U64(5000000000000000000)));
+        assert!(!schema.is_valid(&json::U64(5000000000000000001)));
+
+        // Decimal fractions are multiples as written.
+        let schema = compile("{\"multipleOf\": 0.1}");
+        assert!(schema.is_valid(&json::Floating(0.3)));
+        assert!(schema.is_valid(&json::U64(7)));
+        assert!(!schema.is_valid(&json::Floating(0.35)));
+        assert!(!schema.is_valid(&json::Floating(0.1000000001)));
+
+        let schema = compile("{\"multipleOf\": 0.25}");
+        assert!(schema.is_valid(&json::Floating(-1.75)));
+        assert!(!schema.is_valid(&json::Floating(1.8)));
+
+        match Schema::compile(&parse("{\"multipleOf\": 0}")) {
+            Err(InvalidSchema(pointer, _)) => assert_eq!(pointer, "/multipleOf".to_string()),
+            _ => panic!("expected an invalid schema"),
+        }
+    }
+
+    #[test]
+    fn test_schema_ref_cycles() {
+        let pointer_of = |schema: &str| {
+            match Schema::compile(&parse(schema)) {
+                Err(InvalidSchema(pointer, _)) => pointer,
+                _ => panic!("expected an invalid schema"),
+            }
+        };
+
+        assert_eq!(pointer_of("{\"$ref\": \"#\"}"), "".to_string());
+        assert_eq!(pointer_of("{\"not\": {\"$ref\": \"#\"}}"), "".to_string());
+        assert_eq!(pointer_of("{\"anyOf\": [{\"type\": \"null\"}, {\"$ref\": \"#\"}]}"),
+                   "".to_string());
+        assert_eq!(pointer_of("{
+            \"$ref\": \"#/$defs/a\",
+            \"$defs\": {
+                \"a\": {\"$ref\": \"#/$defs/b\"},
+                \"b\": {\"allOf\": [{\"$ref\": \"#/$defs/a\"}]}
+            }
+        }"), "/$defs/a".to_string());
+
+        // Recursing through a child value is fine.
+        let schema = Schema::compile(&parse("{
+            \"type\": \"object\",
+            \"properties\": {\"next\": {\"anyOf\": [{\"type\": \"null\"}, {\"$ref\": \"#\"}]}}
+        }")).unwrap();
+        assert!(schema.is_valid(&parse("{\"next\": {\"next\": null}}")));
+        assert!(!schema.is_valid(&parse("{\"next\": {\"next\": 1}}")));
+    }
+
+    #[test]
+    fn test_schema_derive() {
+        let schema = schema_for::<Item>();
+        assert_eq!(schema.pointer("/required").unwrap().to_string(),
+                   "[\"counts\",\"name\",\"qty\",\"tags\"]".to_string());
+        assert_eq!(schema.pointer("/properties/qty/maximum"), Some(&json::U64(255)));
+        assert_eq!(schema.pointer("/properties/tags/items/type"),
+                   Some(&json::String("string".to_string())));
+
+        let schema = Schema::compile(&schema).unwrap();
+
+        let mut counts = TreeMap::new();
+        counts.insert("a".to_string(), 1i);
+        let item = Item {
+            name: "bolt".to_string(),
+            quantity: 3,
+            note: None,
+            tags: vec!["small".to_string()],
+            counts: counts,
+        };
+        assert!(schema.is_valid(&parse(json::to_string(&item).unwrap().as_slice())));
+
+        assert_eq!(
+            failures(&schema, "{\"name\": 1, \"qty\": 300, \"tags\": [], \"counts\": {\"a\": \"b\"}}"),
+            vec![
+                ("/counts/a".to_string(), "type"),
+                ("/name".to_string(), "type"),
+                ("/qty".to_string(), "maximum"),
+            ]);
+
+        let schema = Schema::compile(&schema_for::<Shape>()).unwrap();
+        for shape in vec![Circle(1.0), Rect(2.0, 3.0), Empty].iter() {
+            assert!(schema.is_valid(&parse(json::to_string(shape).unwrap().as_slice())));
+        }
+        assert!(!schema.is_valid(&parse("{\"Rect\": [2.0]}")));
+        assert!(!schema.is_valid(&parse("{\"Square\": [2.0]}")));
+    }
+
+    #[test]
+    fn test_schema_derive_recursive() {
+        let schema = schema_for::<Node>();
+        assert_eq!(schema.pointer("/$ref"), Some(&json::String("#/$defs/Node".to_string())));
+        assert_eq!(schema.pointer("/$defs/Node/properties/children/items/$ref"),
+                   Some(&json::String("#/$defs/Node".to_string())));
+        assert_eq!(schema.pointer("/$defs/Node/properties/next/anyOf/0/$ref"),
+                   Some(&json::String("#/$defs/Node".to_string())));
+
+        // Inside another schema, the recursive type is still defined once.
+        let schema = schema_for::<Vec<Node>>();
+        assert_eq!(schema.pointer("/items/$ref"),
+                   Some(&json::String("#/$defs/Node".to_string())));
+        let defs = schema.pointer("/$defs").and_then(|defs| defs.as_object());
+        assert_eq!(defs.map(|defs| defs.len()), Some(1));
+
+        let schema = Schema::compile(&schema_for::<Node>()).unwrap();
+        let node = Node {
+            name: "root".to_string(),
+            children: vec![Node { name: "leaf".to_string(), children: vec![], next: None }],
+            next: Some(box Node { name: "next".to_string(), children: vec![], next: None }),
+        };
+        assert!(schema.is_valid(&parse(json::to_string(&node).unwrap().as_slice())));
+        assert_eq!(
+            failures(&schema, "{\"name\": \"a\", \"children\": [{\"name\": 1, \"children\": []}]}"),
+            vec![("/children/0/name".to_string(), "type")]);
+    }
+    #[test]
+    fn test_schema_derive_generic() {
+        let schema = schema_for::<Pair>();
+        assert_eq!(schema.pointer("/properties/number/$ref"),
+                   Some(&json::String("#/$defs/Wrapper".to_string())));
+        assert_eq!(schema.pointer("/properties/text/$ref"),
+                   Some(&json::String("#/$defs/Wrapper2".to_string())));
+        assert_eq!(schema.pointer("/properties/other/$ref"),
+                   Some(&json::String("#/$defs/Wrapper3".to_string())));
+
+        let schema = Schema::compile(&schema).unwrap();
+        let pair = Pair {
+            number: Wrapper { value: 1 },
+            text: Wrapper { value: "a".to_string() },
+            other: other::Wrapper { flag: true },
+        };
+        assert!(schema.is_valid(&parse(json::to_string(&pair).unwrap().as_slice())));
+        assert_eq!(
+            failures(&schema, "{\"number\": {\"value\": \"a\"}, \"text\": {\"value\": 1}, \
+                              \"other\": {\"value\": 1}}"),
+            vec![
+                ("/number/value".to_string(), "type"),
+                ("/other".to_string(), "required"),
+                ("/text/value".to_string(), "type"),
+            ]);
+    }
+}
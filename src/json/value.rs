@@ -0,0 +1,380 @@
+// Copyright 2012-2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Serializing values into a `Json` tree rather than into text.
+//!
+//! The tree has the same shape the JSON text would have: structs become
+//! objects, enums become an object holding the variant name and a list of its
+//! fields, and `None` becomes `null`. Map keys that don't serialize to a
+//! string are written as their compact JSON text. Floats are kept as they
+//! are, so `NaN` and the infinities are only dealt with once the tree is
+//! written out.
+//!
+//! ```rust
+//! use std::collections::TreeMap;
+//! use serde::json;
+//! use serde::json::to_json_value;
+//!
+//! let mut map = TreeMap::new();
+//! map.insert("a".to_string(), vec![1i, 2]);
+//!
+//! let mut value = to_json_value(&map);
+//! match value {
+//!     json::Object(ref mut object) => {
+//!         object.insert("b".to_string(), json::Boolean(true));
+//!     }
+//!     _ => unreachable!(),
+//! }
+//!
+//! assert_eq!(value.to_string(), "{\"a\":[1,2],\"b\":true}".to_string());
+//! ```
+
+use std::collections::TreeMap;
+use std::string;
+
+use ser::Serializable;
+use ser;
+
+use super::{
+    Json,
+    JsonObject,
+    Null,
+    Boolean,
+    Integer,
+    Floating,
+    Number,
+    String,
+    List,
+    Object,
+    from_u64,
+};
+
+enum State {
+    ListState(Vec<Json>),
+    ObjectState(JsonObject),
+    EnumState(string::String, Vec<Json>),
+}
+
+/// A serializer that builds a `Json` value. It never fails.
+pub struct Serializer {
+    stack: Vec<State>,
+    value: Option<Json>,
+}
+
+impl Serializer {
+    pub fn new() -> Serializer {
+        Serializer {
+            stack: Vec::new(),
+            value: None,
+        }
+    }
+
+    /// Unwrap the serialized value. This is `Null` if nothing has been
+    /// serialized yet.
+    pub fn unwrap(self) -> Json {
+        self.value.unwrap_or(Null)
+    }
+
+    #[inline]
+    fn set(&mut self, value: Json) -> Result<(), ()> {
+        self.value = Some(value);
+        Ok(())
+    }
+
+    fn elt(&mut self, value: Json) {
+        match self.stack.last_mut() {
+            Some(state) => {
+                match *state {
+                    ListState(ref mut list) => { list.push(value); return; }
+                    EnumState(_, ref mut fields) => { fields.push(value); return; }
+                    ObjectState(_) => { }
+                }
+            }
+            None => { }
+        }
+        panic!("state machine error, expected a list or an enum")
+    }
+}
+
+impl ser::Serializer<()> for Serializer {
+    #[inline]
+    fn serialize_null(&mut self) -> Result<(), ()> {
+        self.set(Null)
+    }
+
+    #[inline]
+    fn serialize_bool(&mut self, v: bool) -> Result<(), ()> {
+        self.set(Boolean(v))
+    }
+
+    #[inline]
+    fn serialize_i64(&mut self, v: i64) -> Result<(), ()> {
+        self.set(Integer(v))
+    }
+
+    #[inline]
+    fn serialize_u64(&mut self, v: u64) -> Result<(), ()> {
+        self.set(from_u64(v))
+    }
+
+    #[inline]
+    fn serialize_f64(&mut self, v: f64) -> Result<(), ()> {
+        self.set(Floating(v))
+    }
+
+    #[inline]
+    fn serialize_number(&mut self, v: &str) -> Result<(), ()> {
+        self.set(Number(v.to_string()))
+    }
+
+    #[inline]
+    fn serialize_char(&mut self, v: char) -> Result<(), ()> {
+        self.set(String(string::String::from_char(1, v)))
+    }
+
+    #[inline]
+    fn serialize_str(&mut self, v: &str) -> Result<(), ()> {
+        self.set(String(v.to_string()))
+    }
+
+    #[inline]
+    fn serialize_tuple_start(&mut self, len: uint) -> Result<(), ()> {
+        self.stack.push(ListState(Vec::with_capacity(len)));
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_tuple_elt<
+        T: Serializable<Serializer, ()>
+    >(&mut self, value: &T) -> Result<(), ()> {
+        self.elt(to_json_value(value));
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_tuple_end(&mut self) -> Result<(), ()> {
+        match self.stack.pop() {
+            Some(ListState(list)) => self.set(List(list)),
+            _ => panic!("state machine error, expected a list"),
+        }
+    }
+
+    #[inline]
+    fn serialize_struct_start(&mut self, _name: &str, _len: uint) -> Result<(), ()> {
+        self.stack.push(ObjectState(TreeMap::new()));
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_struct_elt<
+        T: Serializable<Serializer, ()>
+    >(&mut self, name: &str, value: &T) -> Result<(), ()> {
+        let value = to_json_value(value);
+        match self.stack.last_mut() {
+            Some(state) => {
+                match *state {
+                    ObjectState(ref mut object) => {
+                        object.insert(name.to_string(), value);
+                        return Ok(());
+                    }
+                    _ => { }
+                }
+            }
+            None => { }
+        }
+        panic!("state machine error, expected an object")
+    }
+
+    #[inline]
+    fn serialize_struct_end(&mut self) -> Result<(), ()> {
+        match self.stack.pop() {
+            Some(ObjectState(object)) => self.set(Object(object)),
+            _ => panic!("state machine error, expected an object"),
+        }
+    }
+
+    #[inline]
+    fn serialize_enum_start(&mut self, _name: &str, variant: &str, len: uint) -> Result<(), ()> {
+        self.stack.push(EnumState(variant.to_string(), Vec::with_capacity(len)));
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_enum_elt<
+        T: Serializable<Serializer, ()>
+    >(&mut self, value: &T) -> Result<(), ()> {
+        self.elt(to_json_value(value));
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_enum_end(&mut self) -> Result<(), ()> {
+        match self.stack.pop() {
+            Some(EnumState(variant, fields)) => {
+                let mut object = TreeMap::new();
+                object.insert(variant, List(fields));
+                self.set(Object(object))
+            }
+            _ => panic!("state machine error, expected an enum"),
+        }
+    }
+
+    #[inline]
+    fn serialize_option<
+        T: Serializable<Serializer, ()>
+    >(&mut self, v: &Option<T>) -> Result<(), ()> {
+        match *v {
+            Some(ref v) => v.serialize(self),
+            None => self.set(Null),
+        }
+    }
+
+    #[inline]
+    fn serialize_seq<
+        T: Serializable<Serializer, ()>,
+        Iter: Iterator<T>
+    >(&mut self, iter: Iter) -> Result<(), ()> {
+        let list = iter.map(|elt| to_json_value(&elt)).collect();
+        self.set(List(list))
+    }
+
+    #[inline]
+    fn serialize_map<
+        K: Serializable<Serializer, ()>,
+        V: Serializable<Serializer, ()>,
+        Iter: Iterator<(K, V)>
+    >(&mut self, mut iter: Iter) -> Result<(), ()> {
+        let mut object = TreeMap::new();
+        for (key, value) in iter {
+            let key = match to_json_value(&key) {
+                String(key) => key,
+                key => key.to_string(),
+            };
+            object.insert(key, to_json_value(&value));
+        }
+        self.set(Object(object))
+    }
+}
+
+/// Serializes a value into a `Json` tree.
+pub fn to_json_value<T: Serializable<Serializer, ()>>(value: &T) -> Json {
+    let mut serializer = Serializer::new();
+    // Building a `Json` doesn't fail, so we can ignore the error.
+    value.serialize(&mut serializer).unwrap();
+    serializer.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::TreeMap;
+    use std::f64;
+    use std::string;
+    use std::u64;
+
+    use json::{Json, Null, Boolean, Integer, U64, Floating, String, List, Object, from_json};
+    use json;
+    use super::to_json_value;
+
+    #[deriving(PartialEq, Show)]
+    #[deriving_serializable]
+    #[deriving_deserializable]
+    struct Inner {
+        a: (),
+        b: uint,
+        c: Vec<Option<char>>,
+    }
+
+    #[deriving(PartialEq, Show)]
+    #[deriving_serializable]
+    #[deriving_deserializable]
+    struct Outer {
+        #[serial_name = "inner-list"]
+        inner: Vec<Inner>,
+    }
+
+    #[deriving(PartialEq, Show)]
+    #[deriving_serializable]
+    #[deriving_deserializable]
+    enum Animal {
+        Dog,
+        Frog(string::String, Vec<int>),
+    }
+
+    fn object(entries: Vec<(&str, Json)>) -> Json {
+        let mut object = TreeMap::new();
+        for (key, value) in entries.into_iter() {
+            object.insert(key.to_string(), value);
+        }
+        Object(object)
+    }
+
+    #[test]
+    fn test_to_json_value_primitives() {
+        assert_eq!(to_json_value(&()), Null);
+        assert_eq!(to_json_value(&true), Boolean(true));
+        assert_eq!(to_json_value(&-3i8), Integer(-3));
+        assert_eq!(to_json_value(&3u16), Integer(3));
+        assert_eq!(to_json_value(&u64::MAX), U64(u64::MAX));
+        assert_eq!(to_json_value(&0.5f32), Floating(0.5));
+        assert_eq!(to_json_value(&'x'), String("x".to_string()));
+        assert_eq!(to_json_value(&"abc"), String("abc".to_string()));
+        assert_eq!(to_json_value(&None::<int>), Null);
+        assert_eq!(to_json_value(&Some(1i)), Integer(1));
+        assert_eq!(to_json_value(&(1i, "a")), List(vec![Integer(1), String("a".to_string())]));
+
+        match to_json_value(&f64::NAN) {
+            Floating(v) => assert!(v.is_nan()),
+            value => panic!("unexpected value {}", value),
+        }
+
+        let value = json::from_str::<Json>("[1, 1.5e300, {\"a\": null}]").unwrap();
+        assert_eq!(to_json_value(&value), value);
+    }
+
+    #[test]
+    fn test_to_json_value_maps() {
+        let mut map = TreeMap::new();
+        map.insert(2i, "two");
+        map.insert(1i, "one");
+        assert_eq!(to_json_value(&map), object(vec![
+            ("1", String("one".to_string())),
+            ("2", String("two".to_string())),
+        ]));
+    }
+
+    #[test]
+    fn test_to_json_value_derived() {
+        let outer = Outer {
+            inner: vec![
+                Inner { a: (), b: 2, c: vec![None, Some('a')] },
+            ],
+        };
+
+        let value = to_json_value(&outer);
+        assert_eq!(value, object(vec![
+            ("inner-list", List(vec![
+                object(vec![
+                    ("a", Null),
+                    ("b", Integer(2)),
+                    ("c", List(vec![Null, String("a".to_string())])),
+                ]),
+            ])),
+        ]));
+        assert_eq!(value.to_string(), json::to_string(&outer).unwrap());
+        assert_eq!(from_json::<Outer>(value).unwrap(), outer);
+
+        let value = to_json_value(&Frog("Henry".to_string(), vec![349, 102]));
+        assert_eq!(value.to_string(), "{\"Frog\":[\"Henry\",[349,102]]}".to_string());
+        assert_eq!(from_json::<Animal>(value).unwrap(),
+                   Frog("Henry".to_string(), vec![349, 102]));
+
+        assert_eq!(to_json_value(&Dog), object(vec![("Dog", List(Vec::new()))]));
+    }
+}
@@ -0,0 +1,389 @@
+// Copyright 2012-2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Writing a JSON document piece by piece.
+//!
+//! A `JsonWriter` writes lists and objects as they are opened, keyed and
+//! closed, so a document can be produced from a source that is too large to
+//! hold in memory, such as rows coming from a database cursor. The nesting is
+//! checked as it goes: a misplaced key, a value without a key or a mismatched
+//! close is an `InvalidInput` error, and nothing is written for it.
+//!
+//! ```rust
+//! use std::io::MemWriter;
+//! use serde::json::writer::JsonWriter;
+//!
+//! let mut wr = JsonWriter::new(MemWriter::new());
+//! wr.begin_object().unwrap();
+//! wr.key("rows").unwrap();
+//! wr.begin_array().unwrap();
+//! for row in range(0i, 3) {
+//!     wr.value(&(row, row * row)).unwrap();
+//! }
+//! wr.end_array().unwrap();
+//! wr.end_object().unwrap();
+//!
+//! let out = wr.finish().unwrap().unwrap();
+//! assert_eq!(out.as_slice(), b"{\"rows\":[[0,0],[1,1],[2,4]]}");
+//! ```
+
+use std::default::Default;
+use std::io::{IoError, IoResult, MemWriter};
+use std::io;
+
+use ser::Serializable;
+
+use super::{Escaping, PrettySerializer, Serializer, escape_str_with};
+
+#[deriving(PartialEq)]
+enum FrameKind {
+    ListFrame,
+    ObjectFrame,
+}
+
+struct Frame {
+    kind: FrameKind,
+    empty: bool,
+    /// Whether an object member's key has been written without its value.
+    has_key: bool,
+}
+
+/// Writes a JSON document incrementally to a `Writer`.
+pub struct JsonWriter<W> {
+    wr: W,
+    pretty: bool,
+    indent: String,
+    escaping: Escaping,
+    frames: Vec<Frame>,
+    done: bool,
+}
+
+impl<W: Writer> JsonWriter<W> {
+    /// Creates a writer that writes compact JSON.
+    pub fn new(wr: W) -> JsonWriter<W> {
+        JsonWriter {
+            wr: wr,
+            pretty: false,
+            indent: "  ".to_string(),
+            escaping: Default::default(),
+            frames: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Creates a writer that lays the document out the way
+    /// `PrettySerializer` does.
+    pub fn pretty(wr: W) -> JsonWriter<W> {
+        let mut writer = JsonWriter::new(wr);
+        writer.pretty = true;
+        writer
+    }
+
+    /// Sets the string written once per level of nesting in the pretty
+    /// layout. Defaults to two spaces.
+    pub fn indent(mut self, indent: &str) -> JsonWriter<W> {
+        self.indent = indent.to_string();
+        self
+    }
+
+    /// Escapes every non-ASCII character in strings, so the output is plain
+    /// ASCII.
+    pub fn ascii_only(mut self, ascii_only: bool) -> JsonWriter<W> {
+        self.escaping.ascii_only = ascii_only;
+        self
+    }
+
+    /// Escapes `<`, `>`, `&`, U+2028 and U+2029 in strings, so the output can
+    /// be embedded in an HTML `<script>` element.
+    pub fn html_safe(mut self, html_safe: bool) -> JsonWriter<W> {
+        self.escaping.html_safe = html_safe;
+        self
+    }
+
+    /// Starts a list.
+    pub fn begin_array(&mut self) -> IoResult<()> {
+        self.begin(ListFrame, "[")
+    }
+
+    /// Ends the innermost list.
+    pub fn end_array(&mut self) -> IoResult<()> {
+        self.end(ListFrame, "]")
+    }
+
+    /// Starts an object.
+    pub fn begin_object(&mut self) -> IoResult<()> {
+        self.begin(ObjectFrame, "{")
+    }
+
+    /// Ends the innermost object.
+    pub fn end_object(&mut self) -> IoResult<()> {
+        self.end(ObjectFrame, "}")
+    }
+
+    /// Writes the key of the next member of the innermost object.
+    pub fn key(&mut self, key: &str) -> IoResult<()> {
+        match self.frames.last() {
+            Some(frame) if frame.kind == ObjectFrame && !frame.has_key => { }
+            Some(frame) if frame.kind == ObjectFrame => {
+                return Err(invalid("expected a value after an object key"));
+            }
+            _ => { return Err(invalid("keys can only be written inside an object")); }
+        }
+
+        try!(self.write_separator());
+        try!(escape_str_with(&mut self.wr, key, self.escaping));
+        try!(self.wr.write_str(if self.pretty { ": " } else { ":" }));
+
+        let frame = self.frames.last_mut().unwrap();
+        frame.has_key = true;
+        Ok(())
+    }
+
+    /// Writes a whole value, such as a row of a report.
+    pub fn value<
+        T: Serializable<Serializer<MemWriter>, IoError>
+         + Serializable<PrettySerializer<MemWriter>, IoError>
+    >(&mut self, value: &T) -> IoResult<()> {
+        // Serialize first, so that a failing value leaves the document as it
+        // was.
+        let buf = if self.pretty {
+            let mut serializer = PrettySerializer::new(MemWriter::new())
+                .indent(self.indent.as_slice())
+                .ascii_only(self.escaping.ascii_only)
+                .html_safe(self.escaping.html_safe);
+            try!(value.serialize(&mut serializer));
+            serializer.unwrap().unwrap()
+        } else {
+            let mut serializer = Serializer::new(MemWriter::new())
+                .ascii_only(self.escaping.ascii_only)
+                .html_safe(self.escaping.html_safe);
+            try!(value.serialize(&mut serializer));
+            serializer.unwrap().unwrap()
+        };
+
+        try!(self.before_value());
+
+        if !self.pretty {
+            return self.wr.write(buf.as_slice());
+        }
+
+        // The value was laid out as if it were at the top level, so indent
+        // each of its lines to the current depth. Newlines inside strings are
+        // escaped, so every newline here is part of the layout.
+        let level = self.frames.len();
+        let mut start = 0;
+        for (i, &b) in buf.iter().enumerate() {
+            if b == b'\n' {
+                try!(self.wr.write(buf.slice(start, i + 1)));
+                try!(self.write_indent(level));
+                start = i + 1;
+            }
+        }
+        self.wr.write(buf.slice_from(start))
+    }
+
+    /// Checks that the document is complete and returns the underlying
+    /// writer.
+    pub fn finish(self) -> IoResult<W> {
+        if !self.frames.is_empty() {
+            Err(invalid("the document has unclosed lists or objects"))
+        } else if !self.done {
+            Err(invalid("the document is empty"))
+        } else {
+            Ok(self.wr)
+        }
+    }
+
+    fn begin(&mut self, kind: FrameKind, open: &'static str) -> IoResult<()> {
+        try!(self.before_value());
+        try!(self.wr.write_str(open));
+        self.frames.push(Frame { kind: kind, empty: true, has_key: false });
+        Ok(())
+    }
+
+    fn end(&mut self, kind: FrameKind, close: &'static str) -> IoResult<()> {
+        let empty = match self.frames.last() {
+            Some(frame) if frame.kind == kind && !frame.has_key => frame.empty,
+            Some(frame) if frame.kind == kind => {
+                return Err(invalid("expected a value after an object key"));
+            }
+            Some(_) => { return Err(invalid("mismatched end of a list or object")); }
+            None => { return Err(invalid("there is no list or object to end")); }
+        };
+
+        self.frames.pop();
+
+        if self.pretty && !empty {
+            try!(self.wr.write_str("\n"));
+            let level = self.frames.len();
+            try!(self.write_indent(level));
+        }
+
+        self.wr.write_str(close)
+    }
+
+    // Checks that a value may be written here, and writes whatever comes
+    // before it.
+    fn before_value(&mut self) -> IoResult<()> {
+        let kind = match self.frames.last() {
+            Some(frame) => frame.kind,
+            None => {
+                if self.done {
+                    return Err(invalid("a document holds a single value"));
+                }
+                self.done = true;
+                return Ok(());
+            }
+        };
+
+        match kind {
+            ListFrame => self.write_separator(),
+            ObjectFrame => {
+                let frame = self.frames.last_mut().unwrap();
+                if !frame.has_key {
+                    return Err(invalid("expected a key before an object member's value"));
+                }
+                frame.has_key = false;
+                Ok(())
+            }
+        }
+    }
+
+    fn write_separator(&mut self) -> IoResult<()> {
+        let first = {
+            let frame = self.frames.last_mut().unwrap();
+            let first = frame.empty;
+            frame.empty = false;
+            first
+        };
+
+        if !first {
+            try!(self.wr.write_str(","));
+        }
+
+        if self.pretty {
+            try!(self.wr.write_str("\n"));
+            let level = self.frames.len();
+            try!(self.write_indent(level));
+        }
+
+        Ok(())
+    }
+
+    fn write_indent(&mut self, level: uint) -> IoResult<()> {
+        for _ in range(0, level) {
+            try!(self.wr.write_str(self.indent.as_slice()));
+        }
+        Ok(())
+    }
+}
+
+fn invalid(desc: &'static str) -> IoError {
+    IoError {
+        kind: io::InvalidInput,
+        desc: desc,
+        detail: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::TreeMap;
+    use std::io::{MemWriter, InvalidInput};
+    use std::str;
+    use std::string;
+
+    use json;
+    use super::JsonWriter;
+
+    fn write_report(wr: &mut JsonWriter<MemWriter>) {
+        let mut totals = TreeMap::new();
+        totals.insert("count".to_string(), 2i);
+
+        wr.begin_object().unwrap();
+        wr.key("rows").unwrap();
+        wr.begin_array().unwrap();
+        wr.value(&vec![1i, 2]).unwrap();
+        wr.begin_array().unwrap();
+        wr.end_array().unwrap();
+        wr.end_array().unwrap();
+        wr.key("title").unwrap();
+        wr.value(&"Q3 \"draft\"").unwrap();
+        wr.key("totals").unwrap();
+        wr.value(&totals).unwrap();
+        wr.end_object().unwrap();
+    }
+
+    fn finish(wr: JsonWriter<MemWriter>) -> string::String {
+        let buf = wr.finish().unwrap().unwrap();
+        str::from_utf8(buf.as_slice()).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_writer_compact() {
+        let mut wr = JsonWriter::new(MemWriter::new());
+        write_report(&mut wr);
+        assert_eq!(finish(wr),
+                   "{\"rows\":[[1,2],[]],\"title\":\"Q3 \\\"draft\\\"\",\"totals\":{\"count\":2}}"
+                   .to_string());
+    }
+
+    #[test]
+    fn test_writer_pretty() {
+        let mut wr = JsonWriter::pretty(MemWriter::new());
+        write_report(&mut wr);
+        let out = finish(wr);
+        assert_eq!(out, concat!(
+            "{\n",
+            "  \"rows\": [\n",
+            "    [\n",
+            "      1,\n",
+            "      2\n",
+            "    ],\n",
+            "    []\n",
+            "  ],\n",
+            "  \"title\": \"Q3 \\\"draft\\\"\",\n",
+            "  \"totals\": {\n",
+            "    \"count\": 2\n",
+            "  }\n",
+            "}").to_string());
+
+        // The same layout as serializing the whole document at once.
+        let value: json::Json = json::from_str(out.as_slice()).unwrap();
+        assert_eq!(value.to_pretty_string(), out);
+    }
+
+    #[test]
+    fn test_writer_errors() {
+        let mut wr = JsonWriter::new(MemWriter::new());
+        assert_eq!(wr.key("a").unwrap_err().kind, InvalidInput);
+        assert_eq!(wr.end_array().unwrap_err().kind, InvalidInput);
+
+        wr.begin_object().unwrap();
+        assert_eq!(wr.value(&1i).unwrap_err().kind, InvalidInput);
+        assert_eq!(wr.end_array().unwrap_err().kind, InvalidInput);
+        wr.key("a").unwrap();
+        assert_eq!(wr.key("b").unwrap_err().kind, InvalidInput);
+        assert_eq!(wr.end_object().unwrap_err().kind, InvalidInput);
+        wr.begin_array().unwrap();
+        assert_eq!(wr.key("b").unwrap_err().kind, InvalidInput);
+        wr.end_array().unwrap();
+
+        assert!(wr.finish().is_err());
+
+        let mut wr = JsonWriter::new(MemWriter::new());
+        wr.value(&true).unwrap();
+        assert_eq!(wr.value(&true).unwrap_err().kind, InvalidInput);
+        assert_eq!(finish(wr), "true".to_string());
+
+        let wr = JsonWriter::new(MemWriter::new());
+        assert!(wr.finish().is_err());
+    }
+}
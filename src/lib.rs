@@ -12,6 +12,9 @@ extern crate serde_macros;
 #[cfg(test)]
 extern crate serialize;
 
+#[macro_escape]
+mod macros;
+
 pub use ser::{Serializer, Serializable};
 pub use de::{Deserializer, Deserializable};
 
@@ -23,4 +26,5 @@ pub mod json;
 mod serde {
     pub use de;
     pub use ser;
+    pub use json;
 }
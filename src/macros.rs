@@ -0,0 +1,54 @@
+// Copyright 2012-2014 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/// Builds a `json::Json` from a JSON literal.
+///
+/// Lists and objects are written as in JSON, and `null` is `json::Null`.
+/// Any other value is an expression whose type implements `json::ToJson`.
+/// Each value must be a single token tree, so expressions such as `-1` or
+/// `x.len()` need to be wrapped in parentheses.
+///
+/// ```rust
+/// #![feature(phase)]
+/// #[phase(plugin, link)]
+/// extern crate serde;
+///
+/// use serde::json;
+///
+/// fn main() {
+///     let x = "three";
+///     let value = json!({
+///         "a": 1i,
+///         "b": [true, null, x],
+///         "c": (-2i)
+///     });
+///
+///     assert_eq!(value.to_string(),
+///                "{\"a\":1,\"b\":[true,null,\"three\"],\"c\":-2}".to_string());
+/// }
+/// ```
+#[macro_export]
+macro_rules! json {
+    (null) => (::serde::json::Null);
+
+    ([]) => (::serde::json::List(Vec::new()));
+    ([ $($elem:tt),+ ]) => (::serde::json::List(vec![$(json!($elem)),+]));
+    ([ $($elem:tt),+, ]) => (json!([ $($elem),+ ]));
+
+    ({}) => (::serde::json::Object(::std::collections::TreeMap::new()));
+    ({ $($key:expr : $value:tt),+ }) => ({
+        let mut object = ::std::collections::TreeMap::new();
+        $(object.insert(($key).to_string(), json!($value));)+
+        ::serde::json::Object(object)
+    });
+    ({ $($key:expr : $value:tt),+, }) => (json!({ $($key : $value),+ }));
+
+    ($other:expr) => (::serde::json::ToJson::to_json(&$other));
+}
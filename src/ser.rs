@@ -13,6 +13,8 @@ use std::hash::Hash;
 use std::rc::Rc;
 use std::sync::Arc;
 
+use de::Spanned;
+
 //////////////////////////////////////////////////////////////////////////////
 
 pub trait Serializer<E> {
@@ -73,6 +75,21 @@ pub trait Serializer<E> {
 
     fn serialize_f64(&mut self, v: f64) -> Result<(), E>;
 
+    /// Serializes a number that is kept in its exact textual form. Formats
+    /// that can't represent arbitrary numbers fall back to a string.
+    #[inline]
+    fn serialize_number(&mut self, v: &str) -> Result<(), E> {
+        self.serialize_str(v)
+    }
+
+    /// Writes out the text of a JSON value, as held by a `json::RawJson`.
+    /// Only serializers that write JSON can do this, and others return
+    /// `None`, in which case the value the text holds is serialized instead.
+    #[inline]
+    fn serialize_raw_json(&mut self, _v: &str) -> Option<Result<(), E>> {
+        None
+    }
+
     fn serialize_char(&mut self, v: char) -> Result<(), E>;
 
     fn serialize_str(&mut self, v: &str) -> Result<(), E>;
@@ -194,6 +211,17 @@ impl<
     }
 }
 
+impl<
+    S: Serializer<E>,
+    E,
+    T: Serializable<S, E>
+> Serializable<S, E> for Spanned<T> {
+    #[inline]
+    fn serialize(&self, s: &mut S) -> Result<(), E> {
+        self.value.serialize(s)
+    }
+}
+
 //////////////////////////////////////////////////////////////////////////////
 
 impl<